@@ -4,7 +4,7 @@ use ic_cdk::{
     storage,
 };
 use state::{get_request_value, set_request_value};
-use types::{ADCResponse, ADCResponseV2, Headers, RequestOpts};
+use types::{ADCResponse, ADCResponseV2, Headers, PairSpec, RequestError, RequestOpts};
 use verity_ic::{owner, verify::types::ProofResponse};
 
 pub mod state;
@@ -171,6 +171,7 @@ fn transform(raw: TransformArgs) -> HttpResponse {
 
 #[ic_cdk::init]
 async fn init(adc_canister: Option<Principal>) {
+    state::migrate_schema();
     owner::init_owner();
     state::set_adc_address(adc_canister);
     state::set_transaction_fee(2_000_000_000);
@@ -206,7 +207,10 @@ async fn submit_adc_request(currency_pairs: String) -> String {
     let adc_canister_request_method = "request_data";
     //TODO: change the principal to that of the processor's
     let adc_canister_principal = state::get_adc_address().unwrap();
-    let options = RequestOpts { price: true };
+    let options = RequestOpts {
+        price: true,
+        ..Default::default()
+    };
 
     let (request_id,): (String,) = call_with_payment128(
         adc_canister_principal,
@@ -220,6 +224,58 @@ async fn submit_adc_request(currency_pairs: String) -> String {
     return request_id;
 }
 
+#[ic_cdk::update]
+/// where `pairs` is a list of structured pairs
+/// e.g [{ base: "ETH", quote: Some("BTC"), sources: Some(["pyth"]) }]
+async fn submit_adc_request_v2(pairs: Vec<PairSpec>) -> Result<String, RequestError> {
+    assert!(state::get_adc_address().is_some(), "ADC_ADDRESS_NOT_SET");
+
+    let adc_canister_request_method = "request_data_v2";
+    let adc_canister_principal = state::get_adc_address().unwrap();
+    let options = RequestOpts {
+        price: true,
+        ..Default::default()
+    };
+
+    let (request_result,): (Result<String, RequestError>,) = call_with_payment128(
+        adc_canister_principal,
+        adc_canister_request_method,
+        (pairs, options),
+        state::get_transaction_fee(),
+    )
+    .await
+    .unwrap();
+
+    request_result
+}
+
+#[ic_cdk::update]
+/// subscribes to the prices of a list of structured pairs, which are pushed to `receive_adc_response`
+/// every `heartbeat` seconds or as soon as a price deviates by `deviation_threshold` (e.g 0.01 for 1%)
+/// the fee of `prepaid_updates` updates is attached as the balance of the subscription
+async fn subscribe_adc_prices(
+    pairs: Vec<PairSpec>,
+    heartbeat: u64,
+    deviation_threshold: Option<f64>,
+    prepaid_updates: u64,
+) -> Result<String, RequestError> {
+    assert!(state::get_adc_address().is_some(), "ADC_ADDRESS_NOT_SET");
+
+    let adc_canister_subscribe_method = "subscribe";
+    let adc_canister_principal = state::get_adc_address().unwrap();
+
+    let (subscription_result,): (Result<String, RequestError>,) = call_with_payment128(
+        adc_canister_principal,
+        adc_canister_subscribe_method,
+        (pairs, heartbeat, deviation_threshold),
+        state::get_transaction_fee() * prepaid_updates as u128,
+    )
+    .await
+    .unwrap();
+
+    subscription_result
+}
+
 #[ic_cdk::update]
 /// where `currency_pairs` is a comma seperated list of pairs
 /// e.g "BTC,ETH/USDT,sol"
@@ -291,22 +347,18 @@ fn receive_adc_response_v2(response: ADCResponseV2) {
 }
 
 // --------------------------- upgrade hooks ------------------------- //
-#[ic_cdk::pre_upgrade]
-/// backup state variables from canister
-fn pre_upgrade() {
-    let cloned_verifier = state::get_adc_address().clone();
-    let clone_fee = state::get_transaction_fee();
-
-    storage::stable_save((cloned_verifier, clone_fee)).unwrap()
-}
 #[ic_cdk::post_upgrade]
-/// restore state variables from backup
+/// the state is kept in stable memory across upgrades,
+/// only the adc address and fee serialized by the versions before stable structures are restored once
 async fn post_upgrade() {
-    let (cached_adc, cache_fee): (Option<Principal>, u128) = storage::stable_restore().unwrap();
+    if state::has_legacy_state() {
+        let (cached_adc, cache_fee): (Option<Principal>, u128) =
+            storage::stable_restore().expect("failed to restore the legacy state");
+        state::set_adc_address(cached_adc);
+        state::set_transaction_fee(cache_fee);
+    }
+    state::migrate_schema();
 
     owner::init_owner();
-
-    state::set_adc_address(cached_adc);
-    state::set_transaction_fee(cache_fee);
 }
 // --------------------------- upgrade hooks ------------------------- //
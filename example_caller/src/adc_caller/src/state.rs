@@ -1,39 +1,122 @@
-use candid::Principal;
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::stable::{stable_read, stable_size};
+use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, StableCell, Storable};
+use std::borrow::Cow;
 use std::cell::RefCell;
-use std::collections::HashMap;
+
+type Memory = VirtualMemory<DefaultMemoryImpl>;
+
+/// The version of the layout of the state kept in stable memory
+pub const SCHEMA_VERSION: u32 = 1;
+
+const SCHEMA_VERSION_MEMORY_ID: MemoryId = MemoryId::new(0);
+const IC_ADC_CANISTER_MEMORY_ID: MemoryId = MemoryId::new(1);
+const FEE_MEMORY_ID: MemoryId = MemoryId::new(2);
+const REQUEST_MAP_MEMORY_ID: MemoryId = MemoryId::new(3);
+
+/// A value kept in stable memory in its candid encoding
+#[derive(Clone, Debug, Default)]
+pub struct Candid<T>(pub T);
+
+impl<T> Storable for Candid<T>
+where
+    T: CandidType + for<'de> Deserialize<'de>,
+{
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(&self.0).expect("failed to encode a stable value"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Self(candid::decode_one(&bytes).expect("failed to decode a stable value"))
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
 
 thread_local! {
-    pub static IC_ADC_CANISTER: RefCell<Option<Principal>> = RefCell::default();
-    pub static FEE: RefCell<u128> = RefCell::default();
-    pub static REQUEST_MAP: RefCell<HashMap<String, String>> = RefCell::new(HashMap::new());
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
+        RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+    static STORED_SCHEMA_VERSION: RefCell<StableCell<u32, Memory>> = RefCell::new(
+        StableCell::init(get_memory(SCHEMA_VERSION_MEMORY_ID), 0)
+            .expect("failed to initialize the schema version"),
+    );
+    pub static IC_ADC_CANISTER: RefCell<StableCell<Candid<Option<Principal>>, Memory>> = RefCell::new(
+        StableCell::init(get_memory(IC_ADC_CANISTER_MEMORY_ID), Candid::default())
+            .expect("failed to initialize the adc address"),
+    );
+    pub static FEE: RefCell<StableCell<Candid<u128>, Memory>> = RefCell::new(
+        StableCell::init(get_memory(FEE_MEMORY_ID), Candid::default())
+            .expect("failed to initialize the fee"),
+    );
+    pub static REQUEST_MAP: RefCell<StableBTreeMap<String, String, Memory>> = RefCell::new(
+        StableBTreeMap::init(get_memory(REQUEST_MAP_MEMORY_ID)),
+    );
+}
+
+fn get_memory(id: MemoryId) -> Memory {
+    MEMORY_MANAGER.with(|mm| mm.borrow().get(id))
+}
+
+/// Check if the stable memory holds the adc address and fee serialized with `stable_save`
+/// by the versions before stable structures, which must be restored before any state variable is accessed
+pub fn has_legacy_state() -> bool {
+    if stable_size() == 0 {
+        return false;
+    }
+
+    let mut magic = [0u8; 3];
+    stable_read(0, &mut magic);
+    &magic != b"MGR"
+}
+
+/// Bring the state kept in stable memory up to the current schema version
+pub fn migrate_schema() {
+    let stored_version = STORED_SCHEMA_VERSION.with(|sv| *sv.borrow().get());
+    if stored_version > SCHEMA_VERSION {
+        ic_cdk::trap("stable memory schema version is newer than the version supported");
+    }
+
+    STORED_SCHEMA_VERSION.with(|sv| {
+        sv.borrow_mut()
+            .set(SCHEMA_VERSION)
+            .expect("failed to update the schema version")
+    });
 }
 
 /// Getter for `IC_ADC_CANISTER` state variable
 pub fn get_adc_address() -> Option<Principal> {
-    let verifier_canister = IC_ADC_CANISTER.with(|vc| vc.borrow().clone());
-    verifier_canister
+    IC_ADC_CANISTER.with(|vc| vc.borrow().get().0)
 }
 
 /// Getter for `FEE` state variable
 pub fn get_transaction_fee() -> u128 {
-    let fee = FEE.with(|vc| vc.borrow().clone());
-    fee
+    FEE.with(|fee| fee.borrow().get().0)
 }
 
 /// Setter for `IC_ADC_CANISTER` state variable
 pub fn set_adc_address(new_adc_addressr: Option<Principal>) {
-    IC_ADC_CANISTER.with(|old_adc_addressr| *old_adc_addressr.borrow_mut() = new_adc_addressr);
+    IC_ADC_CANISTER.with(|vc| {
+        vc.borrow_mut()
+            .set(Candid(new_adc_addressr))
+            .expect("failed to set the adc address")
+    });
 }
 
 /// Setter for `FEE` state variable
 pub fn set_transaction_fee(new_fee: u128) {
-    FEE.with(|old_fee| *old_fee.borrow_mut() = new_fee);
+    FEE.with(|fee| {
+        fee.borrow_mut()
+            .set(Candid(new_fee))
+            .expect("failed to set the fee")
+    });
 }
 
 /// Getter for the `REQUEST_MAP` state variable.
 /// Returns a clone of the string value associated with the provided key, if it exists.
 pub fn get_request_value(key: &str) -> Option<String> {
-    REQUEST_MAP.with(|map| map.borrow().get(key).cloned())
+    REQUEST_MAP.with(|map| map.borrow().get(&key.to_string()))
 }
 
 /// Setter for the `REQUEST_MAP` state variable.
@@ -4,11 +4,15 @@ use ic_agent::Agent;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 
+use crate::handlers::price::sources::pyth_catalog::{
+    DEFAULT_INTERMEDIATE_ASSETS, DEFAULT_PYTH_CATALOG_SCHEDULE,
+};
+use crate::handlers::price::subscriptions::DEFAULT_SUBSCRIPTION_SCHEDULE;
 use crate::helpers::logs::ic::{
     DEFAULT_IC_GATEWAY, DEFAULT_IC_GATEWAY_TRAILING_SLASH, DEFAULT_JOB_SCHEDULE,
     MAMANGEMENT_CANISTER_ID,
 };
-use crate::helpers::utils::get_env_or_default;
+use crate::helpers::utils::{get_env_or_default, get_env_or_none};
 use crate::helpers::verity::DEFAULT_PROVER_URL;
 
 use super::helpers::logs::ic::{create_agent, DEFAULT_IDENTITY_PATH, DEFAULT_SHARED_LOCAL_BIND};
@@ -29,6 +33,16 @@ pub struct Config {
     pub prover_url: String,
     /// is this dev or prod env
     pub is_dev: bool,
+    /// The path to a JSON file containing the definitions of additional pricing sources
+    pub sources_path: Option<String>,
+    /// The schedule of the job to refresh the catalog of Pyth price feeds
+    pub pyth_catalog_schedule: String,
+    /// The path to a JSON file containing the Pyth feed IDs to use for ambiguous currency pairs
+    pub pyth_feed_overrides_path: Option<String>,
+    /// The assets a currency pair without a direct feed can be priced through e.g ["ETH", "BTC"]
+    pub intermediate_assets: Vec<String>,
+    /// The schedule of the job to refresh the prices of the subscriptions
+    pub subscription_schedule: String,
 }
 
 impl Config {
@@ -58,6 +72,18 @@ impl Config {
         let keyfile_path = get_env_or_default("ICP_IDENTITY_FILEPATH", DEFAULT_IDENTITY_PATH);
         let job_schedule = get_env_or_default("JOB_SCHEDULE", DEFAULT_JOB_SCHEDULE);
         let prover_url = get_env_or_default("PROVER_URL", DEFAULT_PROVER_URL);
+        let sources_path = get_env_or_none("SOURCES_CONFIG_PATH");
+        let pyth_catalog_schedule =
+            get_env_or_default("PYTH_CATALOG_SCHEDULE", DEFAULT_PYTH_CATALOG_SCHEDULE);
+        let pyth_feed_overrides_path = get_env_or_none("PYTH_FEED_OVERRIDES_PATH");
+        let intermediate_assets =
+            get_env_or_default("INTERMEDIATE_ASSETS", DEFAULT_INTERMEDIATE_ASSETS)
+                .split(',')
+                .map(|asset| asset.trim().to_uppercase())
+                .filter(|asset| !asset.is_empty())
+                .collect();
+        let subscription_schedule =
+            get_env_or_default("SUBSCRIPTION_SCHEDULE", DEFAULT_SUBSCRIPTION_SCHEDULE);
         let is_mainnet = matches!(
             &icp_url[..],
             DEFAULT_IC_GATEWAY | DEFAULT_IC_GATEWAY_TRAILING_SLASH
@@ -70,6 +96,11 @@ impl Config {
             job_schedule: job_schedule,
             prover_url: prover_url,
             is_dev: !is_mainnet,
+            sources_path,
+            pyth_catalog_schedule,
+            pyth_feed_overrides_path,
+            intermediate_assets,
+            subscription_schedule,
         }
     }
 }
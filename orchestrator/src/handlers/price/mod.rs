@@ -4,17 +4,20 @@ use crate::{
 };
 // use anyhow::Result;
 use poller::LogPollerState;
+use sources::to_adc_error;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::{
+    collections::HashMap,
     result::Result::{self, Ok},
     sync::Arc,
 };
 use tracing::{debug, error, info};
-use types::{ErrorResponse, Response};
-use utils::process_proofs;
+use types::{ErrorResponse, PricingConfig, Request, Response};
+use utils::{process_proofs, resolve_routes, SharedProofs};
 
 pub mod poller;
 pub mod sources;
+pub mod subscriptions;
 pub mod traits;
 pub mod utils;
 
@@ -85,34 +88,86 @@ pub async fn process_canister_logs(
 }
 
 pub async fn fetch_pricing_data(event_logs: Vec<EventLog>) -> Vec<ResponseResult> {
+    let requests = event_logs
+        .into_iter()
+        .map(|event| {
+            debug!("Processing log #{}: {:?}", event.index, event.logs);
+            event.logs
+        })
+        .collect();
+
+    fetch_requests_pricing_data(requests).await
+}
+
+/// Fetch and prove the data requested by each request, whether it comes from the canister logs or a subscription
+pub async fn fetch_requests_pricing_data(requests: Vec<Request>) -> Vec<ResponseResult> {
     let mut responses: Vec<ResponseResult> = vec![];
+    let mut price_responses: Vec<Response> = vec![];
+    let pricing_config = get_pricing_config().await;
+
+    for request in requests {
+        // if the price, any market data or a price series is requested then we should fetch pricing data
+        if request.opts.wants_any_data() {
+            let mut price_response = Response::from(request);
+            // find a route through the intermediate assets for the pairs which need one
+            resolve_routes(&mut price_response).await;
+            price_responses.push(price_response);
+        }
+    }
 
-    for event in event_logs {
-        debug!("Processing log #{}: {:?}", event.index, event.logs);
-
-        let request = event.logs.clone();
-        let request_options = request.clone().opts;
-        let mut price_response = Response::from(request.clone());
-
-        // if the price option is set to true then we should fetch price data
-        if request_options.price {
-            let process_status = process_proofs(&mut price_response).await;
-            match process_status {
-                Err(msg) => {
-                    error!("Failed to process pricing data:{:?}", msg);
-                    // on error we push an error response to the canister
-                    responses.push(Err(ErrorResponse::new(
-                        request.id,
-                        request.owner,
-                        msg.to_string(),
-                    )));
-                }
-                Ok(_) => responses.push(Ok(price_response)),
-            };
+    // the sources which support several tickers per request are requested once for the whole batch
+    // for each of the times priced
+    let mut shared_proofs: HashMap<Option<u64>, SharedProofs> = HashMap::new();
+    for price_response in &price_responses {
+        let at_timestamp = price_response.opts.at_timestamp;
+        if !shared_proofs.contains_key(&at_timestamp) {
+            let proofs = SharedProofs::fetch(&price_responses, at_timestamp, &pricing_config).await;
+            shared_proofs.insert(at_timestamp, proofs);
         }
+    }
 
-        //TODO: check for other request options to fetch other details
+    for mut price_response in price_responses {
+        let at_timestamp = price_response.opts.at_timestamp;
+        let process_status = process_proofs(
+            &mut price_response,
+            &shared_proofs[&at_timestamp],
+            &pricing_config,
+        )
+        .await;
+        match process_status {
+            Err(msg) => {
+                error!("Failed to process pricing data:{:?}", msg);
+                // on error we push an error response to the canister
+                responses.push(Err(ErrorResponse::new(
+                    price_response.id,
+                    price_response.owner,
+                    to_adc_error(&msg),
+                )));
+            }
+            Ok(_) => responses.push(Ok(price_response)),
+        };
     }
 
     responses
 }
+
+/// Get the pricing config set on the canister, which the options of each request override,
+/// the default config is used when it cannot be queried
+pub async fn get_pricing_config() -> PricingConfig {
+    let pricing_config = async {
+        let config = Config::env();
+        let agent = config.get_agent().await?;
+        let response = agent
+            .query(&config.canister, "get_pricing_config")
+            .with_arg(candid::encode_args(())?)
+            .call()
+            .await?;
+        anyhow::Ok(candid::decode_one::<PricingConfig>(&response)?)
+    }
+    .await;
+
+    pricing_config.unwrap_or_else(|err| {
+        error!("Failed to get the pricing config of the canister: {}", err);
+        PricingConfig::default()
+    })
+}
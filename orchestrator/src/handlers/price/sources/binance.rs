@@ -0,0 +1,118 @@
+use anyhow::Context;
+use anyhow::{Ok, Result};
+use serde_json::Value;
+use types::ProofTypes;
+
+use super::{check_response_status, get_series_interval, to_invalid_response, MAX_SERIES_POINTS};
+
+use crate::handlers::price::traits::PricingDataSource;
+use crate::helpers::verity::get_verity_client;
+
+#[derive(Debug)]
+pub struct Binance {}
+
+impl Binance {
+    /// Get a proof for the candles of the USD market of a ticker between `from` and `to`
+    pub async fn get_series_proof(ticker: &str, from: u64, to: u64) -> Result<ProofTypes> {
+        // the candles are requested with timestamps in milliseconds
+        let (_, interval) = get_series_interval(to.saturating_sub(from));
+        let request_url = format!(
+            "https://api.binance.us/api/v3/klines?symbol={}USD&interval={}&startTime={}&endTime={}&limit={}",
+            ticker,
+            interval,
+            from * 1000,
+            to * 1000,
+            MAX_SERIES_POINTS
+        );
+
+        // get the proof using the verity client
+        let response = get_verity_client().get(&request_url).send().await?;
+        check_response_status("binance", response.subject.status())?;
+
+        // check for a succesfull and valid response
+        let http_response_string = response.subject.text().await?;
+        Self::validate_series_response(&http_response_string).map_err(to_invalid_response)?;
+
+        Ok(ProofTypes::BinanceSeries(response.proof))
+    }
+
+    /// Validate the candles gotten before saving and sending the proof
+    fn validate_series_response(http_response_string: &str) -> Result<()> {
+        // Parse the JSON response
+        let data: Value = serde_json::from_str(http_response_string)?;
+
+        let candles = data
+            .as_array()
+            .context("Candles not available: JSON structure changed")?;
+        if candles.is_empty() {
+            anyhow::bail!("No candles available within the window requested");
+        }
+
+        // the processor relies on the open and close times and the open, high, low and close prices of each candle
+        for candle in candles {
+            for time_index in [0, 6] {
+                candle[time_index]
+                    .as_u64()
+                    .context("Candle time not available: JSON structure changed")?;
+            }
+            for price_index in 1..=4 {
+                let price = candle[price_index]
+                    .as_str()
+                    .context("Candle price not available: JSON structure changed")?;
+                let _: f64 = price.parse()?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl PricingDataSource for Binance {
+    fn new() -> Self {
+        Self {}
+    }
+
+    /// The USD markets are only listed on Binance.US, which is queried so the prices are in USD like the other sources
+    async fn get_url(ticker: String) -> Result<String> {
+        Ok(format!(
+            "https://api.binance.us/api/v3/ticker/24hr?symbol={ticker}USD"
+        ))
+    }
+
+    async fn get_proof(ticker: String) -> Result<ProofTypes> {
+        // construct the request URL
+        let request_url = Self::get_url(ticker).await?;
+        let verity_client = get_verity_client();
+
+        // get the proof using the verity client
+        let response = verity_client.get(&request_url).send().await?;
+        check_response_status("binance", response.subject.status())?;
+
+        // check for a succesfull and valid response
+        let http_response_string = response.subject.text().await?;
+        Self::validate_response(http_response_string)
+            .await
+            .map_err(to_invalid_response)?;
+
+        return Ok(ProofTypes::Binance(response.proof));
+    }
+
+    /// Validate the response gotten before saving and sending the proof
+    async fn validate_response(http_response_string: String) -> Result<()> {
+        // Parse the JSON response
+        let data: Value = serde_json::from_str(&http_response_string)?;
+
+        // the processor relies on the close time to discard stale prices
+        data["closeTime"]
+            .as_u64()
+            .context("Close time not available: JSON structure changed")?;
+
+        // Access the 'lastPrice' property and make sure it is a valid number
+        let price = data["lastPrice"]
+            .as_str()
+            .context("Price not available: JSON structure changed")?;
+        let _: f64 = price.parse()?;
+
+        Ok(())
+    }
+}
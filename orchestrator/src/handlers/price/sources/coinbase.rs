@@ -0,0 +1,61 @@
+use anyhow::Context;
+use anyhow::{Ok, Result};
+use serde_json::Value;
+use types::ProofTypes;
+
+use super::{check_response_status, to_invalid_response};
+
+use crate::handlers::price::traits::PricingDataSource;
+use crate::helpers::verity::get_verity_client;
+
+#[derive(Debug)]
+pub struct Coinbase {}
+
+impl PricingDataSource for Coinbase {
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn get_url(ticker: String) -> Result<String> {
+        Ok(format!(
+            "https://api.exchange.coinbase.com/products/{ticker}-USD/ticker"
+        ))
+    }
+
+    async fn get_proof(ticker: String) -> Result<ProofTypes> {
+        // construct the request URL
+        let request_url = Self::get_url(ticker).await?;
+        let verity_client = get_verity_client();
+
+        // get the proof using the verity client
+        let response = verity_client.get(&request_url).send().await?;
+        check_response_status("coinbase", response.subject.status())?;
+
+        // check for a succesfull and valid response
+        let http_response_string = response.subject.text().await?;
+        Self::validate_response(http_response_string)
+            .await
+            .map_err(to_invalid_response)?;
+
+        return Ok(ProofTypes::Coinbase(response.proof));
+    }
+
+    /// Validate the response gotten before saving and sending the proof
+    async fn validate_response(http_response_string: String) -> Result<()> {
+        // Parse the JSON response
+        let data: Value = serde_json::from_str(&http_response_string)?;
+
+        // the processor relies on the time to discard stale prices
+        data["time"]
+            .as_str()
+            .context("Time not available: JSON structure changed")?;
+
+        // Access the 'price' property and make sure it is a valid number
+        let price = data["price"]
+            .as_str()
+            .context("Price not available: JSON structure changed")?;
+        let _: f64 = price.parse()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+use anyhow::Context;
+use anyhow::{Ok, Result};
+use serde_json::Value;
+use types::ProofTypes;
+
+use super::{check_response_status, to_invalid_response};
+use crate::handlers::price::traits::PricingDataSource;
+use crate::helpers::verity::get_verity_client;
+
+/// A source of the market data of tokens, such as their market cap and 24 hour volume
+#[derive(Debug)]
+pub struct CoinGecko {}
+
+impl PricingDataSource for CoinGecko {
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn get_url(ticker: String) -> Result<String> {
+        // the tokens sharing the symbol are sorted by market cap, the first one being the largest
+        Ok(format!(
+            "https://api.coingecko.com/api/v3/coins/markets?vs_currency=usd&order=market_cap_desc&symbols={}",
+            ticker.to_lowercase()
+        ))
+    }
+
+    async fn get_proof(ticker: String) -> Result<ProofTypes> {
+        // construct the request URL
+        let request_url = Self::get_url(ticker).await?;
+        let verity_client = get_verity_client();
+
+        // get the proof using the verity client
+        let response = verity_client.get(&request_url).send().await?;
+        check_response_status("coingecko", response.subject.status())?;
+
+        // check for a succesfull and valid response
+        let http_response_string = response.subject.text().await?;
+        Self::validate_response(http_response_string)
+            .await
+            .map_err(to_invalid_response)?;
+
+        return Ok(ProofTypes::CoinGecko(response.proof));
+    }
+
+    /// Validate the response gotten before saving and sending the proof
+    async fn validate_response(http_response_string: String) -> Result<()> {
+        // Parse the JSON response
+        let data: Value = serde_json::from_str(&http_response_string)?;
+
+        let token = data
+            .as_array()
+            .and_then(|tokens| tokens.first())
+            .context("Market data not available: unknown symbol or JSON structure changed")?;
+
+        // Access the market data properties and make sure they are valid numbers
+        token["market_cap"]
+            .as_f64()
+            .context("Market cap not available: JSON structure changed")?;
+        token["total_volume"]
+            .as_f64()
+            .context("Volume not available: JSON structure changed")?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+use std::sync::RwLock;
+use tracing::error;
+use types::{ProofTypes, SourceDefinition};
+
+use super::{check_response_status, to_invalid_response};
+
+use crate::config::Config;
+use crate::helpers::verity::get_verity_client;
+
+static CUSTOM_SOURCES: RwLock<Vec<CustomSource>> = RwLock::new(Vec::new());
+
+/// A pricing source defined by a `SourceDefinition` rather than a dedicated implementation
+#[derive(Debug, Clone)]
+pub struct CustomSource {
+    pub definition: SourceDefinition,
+}
+
+impl CustomSource {
+    pub fn new(definition: SourceDefinition) -> Self {
+        Self { definition }
+    }
+
+    pub fn get_url(&self, ticker: String) -> String {
+        self.definition.get_url(&ticker)
+    }
+
+    pub async fn get_proof(&self, ticker: String) -> Result<ProofTypes> {
+        // construct the request URL
+        let request_url = self.get_url(ticker);
+        let verity_client = get_verity_client();
+
+        // get the proof using the verity client
+        let response = verity_client.get(&request_url).send().await?;
+        check_response_status(&self.definition.name, response.subject.status())?;
+
+        // check for a succesfull and valid response
+        let http_response_string = response.subject.text().await?;
+        self.validate_response(http_response_string)
+            .map_err(to_invalid_response)?;
+
+        return Ok(ProofTypes::Custom {
+            source: self.definition.name.clone(),
+            proof: response.proof,
+        });
+    }
+
+    /// Validate the response gotten before saving and sending the proof
+    pub fn validate_response(&self, http_response_string: String) -> Result<()> {
+        self.definition.extract(&http_response_string)?;
+
+        Ok(())
+    }
+}
+
+/// Get the custom pricing sources loaded from the sources config file
+pub fn get_custom_sources() -> Vec<CustomSource> {
+    CUSTOM_SOURCES.read().unwrap().clone()
+}
+
+/// Load the custom pricing sources from the JSON file configured through `SOURCES_CONFIG_PATH`
+/// and keep them in memory, it is done once at startup so the orchestrator is restarted to pick up changes
+/// the sources are expected to also be registered on the processor canister
+pub fn load_custom_sources() -> Vec<CustomSource> {
+    let custom_sources = read_custom_sources();
+    *CUSTOM_SOURCES.write().unwrap() = custom_sources.clone();
+
+    custom_sources
+}
+
+fn read_custom_sources() -> Vec<CustomSource> {
+    let config = Config::env();
+    let Some(sources_path) = config.sources_path else {
+        return vec![];
+    };
+    // the sources config file is optional
+    if !Path::new(&sources_path).exists() {
+        return vec![];
+    }
+
+    let source_definitions = fs::read_to_string(&sources_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|contents| {
+            serde_json::from_str::<Vec<SourceDefinition>>(&contents).map_err(anyhow::Error::from)
+        });
+
+    match source_definitions {
+        Err(err) => {
+            error!("Failed to load sources from {}: {}", sources_path, err);
+            vec![]
+        }
+        Ok(source_definitions) => source_definitions
+            .into_iter()
+            .filter(|definition| match definition.validate() {
+                Err(err) => {
+                    error!("Invalid source definition {}: {}", definition.name, err);
+                    false
+                }
+                Ok(_) => true,
+            })
+            .map(CustomSource::new)
+            .collect(),
+    }
+}
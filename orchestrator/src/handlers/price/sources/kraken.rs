@@ -0,0 +1,86 @@
+use anyhow::Context;
+use anyhow::{Ok, Result};
+use serde_json::Value;
+use types::{ADCError, ProofTypes};
+
+use super::{check_response_status, to_invalid_response};
+use crate::handlers::price::traits::PricingDataSource;
+use crate::helpers::verity::get_verity_client;
+
+/// The error kraken reports for the pairs it does not support
+const KRAKEN_UNKNOWN_PAIR_ERROR: &str = "Unknown asset pair";
+
+#[derive(Debug)]
+pub struct Kraken {}
+
+impl Kraken {
+    /// Kraken uses its own names for some assets e.g XBT for BTC
+    pub fn get_kraken_ticker(ticker: String) -> String {
+        match &ticker[..] {
+            "BTC" => "XBT".to_string(),
+            "DOGE" => "XDG".to_string(),
+            _ => ticker,
+        }
+    }
+}
+
+impl PricingDataSource for Kraken {
+    fn new() -> Self {
+        Self {}
+    }
+
+    async fn get_url(ticker: String) -> Result<String> {
+        let kraken_ticker = Self::get_kraken_ticker(ticker);
+        Ok(format!(
+            "https://api.kraken.com/0/public/Ticker?pair={kraken_ticker}USD"
+        ))
+    }
+
+    async fn get_proof(ticker: String) -> Result<ProofTypes> {
+        // construct the request URL
+        let request_url = Self::get_url(ticker.clone()).await?;
+        let verity_client = get_verity_client();
+
+        // get the proof using the verity client
+        let response = verity_client.get(&request_url).send().await?;
+        check_response_status("kraken", response.subject.status())?;
+
+        // check for a succesfull and valid response
+        let http_response_string = response.subject.text().await?;
+        Self::validate_response(http_response_string)
+            .await
+            .map_err(
+                |err| match err.to_string().contains(KRAKEN_UNKNOWN_PAIR_ERROR) {
+                    true => ADCError::UnknownTicker(ticker).into(),
+                    false => to_invalid_response(err),
+                },
+            )?;
+
+        return Ok(ProofTypes::Kraken(response.proof));
+    }
+
+    /// Validate the response gotten before saving and sending the proof
+    async fn validate_response(http_response_string: String) -> Result<()> {
+        // Parse the JSON response
+        let data: Value = serde_json::from_str(&http_response_string)?;
+
+        // kraken reports errors such as unknown pairs in the 'error' property
+        if let Some(error) = data["error"].as_array().and_then(|errors| errors.first()) {
+            anyhow::bail!("Kraken error: {}", error)
+        }
+
+        // the result is keyed by kraken's name for the pair e.g XXBTZUSD
+        let ticker = data["result"]
+            .as_object()
+            .and_then(|result| result.values().next())
+            .context("Result not available: JSON structure changed")?;
+
+        // Access the last trade closed price and make sure it is a valid number
+        let price = ticker["c"][0]
+            .as_str()
+            .context("Price not available: JSON structure changed")?;
+        let _: f64 = price.parse()?;
+
+        Ok(())
+    }
+}
@@ -1,4 +1,82 @@
+use anyhow::Result;
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use types::{ADCError, ProofTypes};
+
+pub mod binance;
+pub mod coinbase;
+pub mod coingecko;
+pub mod custom;
+pub mod kraken;
 pub mod pyth;
+pub mod pyth_catalog;
 pub mod redstone;
 
 pub const PROXY_FETCH_DELAY: u64 = 5;
+/// The currency the price of a token is requested in from the sources
+pub const DEFAULT_QUOTE_CURRENCY: &str = "USD";
+
+/// The intervals (in seconds) the points of a price series can be requested at, with their binance names
+pub const SERIES_INTERVALS: [(u64, &str); 6] = [
+    (60, "1m"),
+    (300, "5m"),
+    (900, "15m"),
+    (3600, "1h"),
+    (14400, "4h"),
+    (86400, "1d"),
+];
+/// The maximum number of points requested for a price series
+pub const MAX_SERIES_POINTS: u64 = 1000;
+
+/// Get the shortest interval between the points of a price series which covers the window requested
+/// within `MAX_SERIES_POINTS` points
+pub fn get_series_interval(window: u64) -> (u64, &'static str) {
+    SERIES_INTERVALS
+        .into_iter()
+        .find(|(interval, _)| window.div_ceil(*interval) <= MAX_SERIES_POINTS)
+        .unwrap_or(SERIES_INTERVALS[SERIES_INTERVALS.len() - 1])
+}
+
+/// The proofs gotten from a batched request indexed by ticker or currency pair,
+/// the tickers which could not be proven are mapped to the reason why
+pub type BatchProofs = HashMap<String, Result<ProofTypes, ADCError>>;
+
+/// Check that a source responded with a successful http status
+pub fn check_response_status(source: &str, status: StatusCode) -> Result<()> {
+    if !status.is_success() {
+        return Err(ADCError::UpstreamHttpStatus {
+            source: source.to_string(),
+            status: status.as_u16(),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+/// Mark an error encountered while validating the response of a source as an invalid response,
+/// unless it is already a known kind of failure
+pub fn to_invalid_response(err: anyhow::Error) -> anyhow::Error {
+    if err.downcast_ref::<ADCError>().is_some() {
+        return err;
+    }
+
+    ADCError::InvalidResponse(err.to_string()).into()
+}
+
+/// Get the kind of failure of an error encountered while getting a proof from a source
+pub fn to_adc_error(err: &anyhow::Error) -> ADCError {
+    if let Some(adc_error) = err.downcast_ref::<ADCError>() {
+        return adc_error.clone();
+    }
+
+    let is_timeout = err.chain().any(|cause| {
+        cause
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(|err| err.is_timeout())
+    });
+    match is_timeout {
+        true => ADCError::Timeout(err.to_string()),
+        false => ADCError::Other(err.to_string()),
+    }
+}
@@ -1,8 +1,12 @@
 use anyhow::Context;
-use anyhow::{Ok, Result};
+use anyhow::Result;
 use serde_json::Value;
 use types::ProofTypes;
 
+use super::pyth_catalog::{PythFeedCatalog, PYTH_PRICE_FEEDS_URL};
+use super::{
+    check_response_status, to_adc_error, to_invalid_response, BatchProofs, DEFAULT_QUOTE_CURRENCY,
+};
 use crate::handlers::price::traits::PricingDataSource;
 use crate::helpers::verity::get_verity_client;
 
@@ -10,31 +14,151 @@ use crate::helpers::verity::get_verity_client;
 pub struct Pyth {}
 
 impl Pyth {
-    /// Given a ticker(e.g USDT) it should return the ID associated with it
-    pub async fn get_ticker_id(ticker: String) -> Result<String> {
-        let quote_currency_to_find = "USD";
-        // TODO: we could cache this api call then refresh it on a daily basis using a cronjob
-        let request_url = "https://hermes.pyth.network/v2/price_feeds".to_string();
-
-        let mut ticker_id: Option<String> = None;
-        let response = reqwest::get(request_url).await?.text().await?;
-        let api_response: Vec<Value> = serde_json::from_str(&response)?;
-
-        for item in api_response {
-            if item["attributes"]["base"] == ticker
-                && item["attributes"]["quote_currency"] == quote_currency_to_find
-            {
-                // Extract the string value associated with the "text" key
-                let message_string = item["id"].as_str().unwrap_or("");
-                ticker_id = Some(message_string.to_string())
+    /// Given a ticker(e.g USDT) and a quote currency(e.g USD) it should return the ID of the feed associated with them
+    pub async fn get_ticker_id(ticker: String, quote_currency: &str) -> Result<String> {
+        PythFeedCatalog::get_feed_id(&ticker, quote_currency).await
+    }
+
+    /// Get a single proof for the prices of several currency pairs (base, quote),
+    /// the proof of each pair references the price feed of the pair in the shared response
+    /// the proofs are indexed by the key of the pair in the feed catalog e.g "BTC/USD"
+    /// the prices published at `at_timestamp` are requested instead of the latest ones when it is set
+    pub async fn get_batch_proof(
+        pairs: &[(String, String)],
+        at_timestamp: Option<u64>,
+    ) -> BatchProofs {
+        let mut batch_proofs = BatchProofs::new();
+
+        let mut feed_ids: Vec<(String, String)> = vec![];
+        for (base, quote) in pairs {
+            let key = PythFeedCatalog::get_key(base, quote);
+            match Self::get_ticker_id(base.clone(), quote).await {
+                Ok(feed_id) => feed_ids.push((key, feed_id)),
+                Err(err) => {
+                    batch_proofs.insert(key, Err(to_adc_error(&err)));
+                }
             }
         }
+        if feed_ids.is_empty() {
+            return batch_proofs;
+        }
 
-        let ticker_id = match ticker_id {
-            Some(id) => id.to_string(),
-            None => anyhow::bail!("Invalid ticker id"),
+        // construct the request URL containing the feeds of all the tickers
+        let query = feed_ids
+            .iter()
+            .map(|(_, feed_id)| format!("ids[]={}", feed_id))
+            .collect::<Vec<String>>()
+            .join("&");
+        let request_url = match at_timestamp {
+            Some(at_timestamp) => format!(
+                "https://hermes.pyth.network/v2/updates/price/{}?{}&parsed=true",
+                at_timestamp, query
+            ),
+            None => format!(
+                "https://hermes.pyth.network/api/latest_price_feeds?{}",
+                query
+            ),
         };
-        Ok(ticker_id)
+
+        // get the proof using the verity client
+        let response = async {
+            let response = get_verity_client().get(&request_url).send().await?;
+            check_response_status("pyth", response.subject.status())?;
+            let http_response_string = response.subject.text().await?;
+            let data: Value = serde_json::from_str(&http_response_string)
+                .map_err(|err| to_invalid_response(err.into()))?;
+            anyhow::Ok((data, response.proof))
+        }
+        .await;
+
+        for (key, feed_id) in feed_ids {
+            // check for a valid price feed for each of the pairs
+            let proof = match &response {
+                Err(err) => Err(to_adc_error(err)),
+                Ok((data, proof)) => Self::find_price_feed(data, &feed_id)
+                    .and_then(Self::validate_price_feed)
+                    .map(|_| ProofTypes::PythBatch {
+                        feed_id: feed_id.clone(),
+                        proof: proof.clone(),
+                    })
+                    .map_err(|err| to_adc_error(&to_invalid_response(err))),
+            };
+            batch_proofs.insert(key, proof);
+        }
+
+        batch_proofs
+    }
+
+    /// Find the price feed with the ID provided in a response containing several price feeds,
+    /// the historical price updates provide them in the `parsed` field of the response
+    fn find_price_feed<'a>(data: &'a Value, feed_id: &str) -> Result<&'a Value> {
+        let feed_id = feed_id.trim_start_matches("0x");
+        data.as_array()
+            .or_else(|| data["parsed"].as_array())
+            .context("price feeds are missing")?
+            .iter()
+            .find(|price_feed| {
+                price_feed["id"]
+                    .as_str()
+                    .map(|id| id.trim_start_matches("0x").eq_ignore_ascii_case(feed_id))
+                    .unwrap_or(false)
+            })
+            .with_context(|| format!("price feed {} is missing", feed_id))
+    }
+
+    /// Get a proof of the attributes of the price feed `feed_id`,
+    /// which the processor checks the direct feed of the `base`/`quote` pair against
+    pub async fn get_feed_info_proof(base: &str, quote: &str, feed_id: &str) -> Result<ProofTypes> {
+        // the feeds are searched by the symbol of the pair e.g "Crypto.ETH/BTC"
+        let request_url = format!("{}?query={}%2F{}", PYTH_PRICE_FEEDS_URL, base, quote);
+
+        // get the proof using the verity client
+        let response = get_verity_client().get(&request_url).send().await?;
+        check_response_status("pyth", response.subject.status())?;
+
+        // check that the response describes the feed before sending the proof
+        let http_response_string = response.subject.text().await?;
+        let data: Value = serde_json::from_str(&http_response_string)
+            .map_err(|err| to_invalid_response(err.into()))?;
+        Self::find_price_feed(&data, feed_id)
+            .and_then(|price_feed| {
+                price_feed["attributes"]["base"]
+                    .as_str()
+                    .zip(price_feed["attributes"]["quote_currency"].as_str())
+                    .context("attributes of the price feed are missing")
+            })
+            .map_err(to_invalid_response)?;
+
+        Ok(ProofTypes::PythFeedInfo {
+            feed_id: feed_id.to_string(),
+            proof: response.proof,
+        })
+    }
+
+    /// Validate a single price feed of a response
+    fn validate_price_feed(price_feed: &Value) -> Result<()> {
+        let price = price_feed["price"]["price"]
+            .as_str()
+            .context("price.price field is missing")?;
+        let exp = price_feed["price"]["expo"]
+            .as_i64()
+            .context("price.expo field is missing")?;
+        // the processor relies on the publish time to discard stale prices
+        price_feed["price"]["publish_time"]
+            .as_u64()
+            .context("price.publish_time field is missing")?;
+
+        let confidence = price_feed["price"]["conf"]
+            .as_str()
+            .context("price.conf field is missing")?;
+
+        // try parsing the price gotten to check for any errors
+        let price: f64 = price.parse()?;
+        let _: f64 = confidence.parse()?;
+        let multiplier = 1.0 as f64 / (10 as f64).powf(exp.abs() as f64);
+        let _: f64 = price * multiplier;
+
+        Ok(())
     }
 }
 
@@ -44,7 +168,7 @@ impl PricingDataSource for Pyth {
     }
 
     async fn get_url(ticker: String) -> Result<String> {
-        let ticker_id = Self::get_ticker_id(ticker).await?;
+        let ticker_id = Self::get_ticker_id(ticker, DEFAULT_QUOTE_CURRENCY).await?;
         Ok(format!(
             "https://hermes.pyth.network/api/latest_price_feeds?ids[]={}",
             ticker_id
@@ -58,10 +182,13 @@ impl PricingDataSource for Pyth {
 
         // get the proof using the verity client
         let response = verity_client.get(&request_url).send().await?;
+        check_response_status("pyth", response.subject.status())?;
 
         // check for a succesfull and valid response
         let http_response_string = response.subject.text().await?;
-        Self::validate_response(http_response_string).await?;
+        Self::validate_response(http_response_string)
+            .await
+            .map_err(to_invalid_response)?;
 
         return Ok(ProofTypes::Pyth(response.proof));
     }
@@ -70,20 +197,7 @@ impl PricingDataSource for Pyth {
     async fn validate_response(http_response_string: String) -> Result<()> {
         // Parse the JSON response
         let data: Value = serde_json::from_str(&http_response_string)?;
-        let price = data[0]["price"]["price"]
-            .as_str()
-            .context("price.price field is missing")
-            .and_then(|price| Ok(price))?;
-        let exp = data[0]["price"]["expo"]
-            .as_i64()
-            .context("price.expo field is missing")
-            .and_then(|exp| Ok(exp))?;
-
-        // try parsing the price gotten to check for any errors
-        let price: f64 = price.parse()?;
-        let multiplier = 1.0 as f64 / (10 as f64).powf(exp.abs() as f64);
-        let _: f64 = price * multiplier;
 
-        Ok(())
+        Self::validate_price_feed(&data[0])
     }
 }
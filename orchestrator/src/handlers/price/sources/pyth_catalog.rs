@@ -0,0 +1,302 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::any::type_name;
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tracing::{error, info};
+use types::ADCError;
+
+use crate::config::Config;
+use crate::helpers::utils::{get_root_path, get_utc_timestamp};
+
+pub const PYTH_PRICE_FEEDS_URL: &str = "https://hermes.pyth.network/v2/price_feeds";
+/// The default schedule of the job refreshing the catalog (every hour)
+pub const DEFAULT_PYTH_CATALOG_SCHEDULE: &str = "0 0 * * * *";
+/// The default assets a currency pair without a direct feed can be priced through
+pub const DEFAULT_INTERMEDIATE_ASSETS: &str = "ETH,BTC";
+/// The maximum age (in seconds) of a cached catalog before it is downloaded again on lookup
+pub const PYTH_CATALOG_MAX_AGE: u64 = 24 * 60 * 60;
+
+static PYTH_FEED_CATALOG: RwLock<Option<PythFeedCatalog>> = RwLock::new(None);
+
+/// A cache of the Pyth price feed IDs indexed by their currency pair e.g "BTC/USD"
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PythFeedCatalog {
+    /// the time (in seconds) the catalog was downloaded
+    pub updated_at: u64,
+    pub feeds: HashMap<String, String>,
+    /// the feed IDs to use for ambiguous currency pairs, loaded from the overrides file along with the catalog
+    #[serde(skip)]
+    pub overrides: HashMap<String, String>,
+}
+
+impl PythFeedCatalog {
+    pub fn get_struct_name() -> &'static str {
+        type_name::<Self>().split(":").last().unwrap()
+    }
+
+    /// Get the key of a currency pair in the catalog
+    pub fn get_key(base: &str, quote: &str) -> String {
+        format!("{}/{}", base.to_uppercase(), quote.to_uppercase())
+    }
+
+    /// Build the catalog from the response of the price feeds endpoint,
+    /// when several feeds share a currency pair the crypto feed seen first is used
+    pub fn from_price_feeds(price_feeds: &[Value], updated_at: u64) -> Self {
+        let mut feeds: HashMap<String, (String, bool)> = HashMap::new();
+
+        for item in price_feeds {
+            let attributes = &item["attributes"];
+            let (Some(base), Some(quote), Some(id)) = (
+                attributes["base"].as_str(),
+                attributes["quote_currency"].as_str(),
+                item["id"].as_str(),
+            ) else {
+                continue;
+            };
+            let is_crypto = attributes["asset_type"]
+                .as_str()
+                .map(|asset_type| asset_type.eq_ignore_ascii_case("crypto"))
+                .unwrap_or(false);
+
+            let key = Self::get_key(base, quote);
+            let replace = match feeds.get(&key) {
+                None => true,
+                Some((_, existing_is_crypto)) => is_crypto && !existing_is_crypto,
+            };
+            if replace {
+                feeds.insert(key, (id.to_string(), is_crypto));
+            }
+        }
+
+        Self {
+            updated_at,
+            feeds: feeds.into_iter().map(|(key, (id, _))| (key, id)).collect(),
+            overrides: HashMap::new(),
+        }
+    }
+
+    /// Download the catalog of price feeds from Pyth
+    pub async fn fetch() -> Result<Self> {
+        let response = reqwest::get(PYTH_PRICE_FEEDS_URL).await?.text().await?;
+        let price_feeds: Vec<Value> = serde_json::from_str(&response)?;
+
+        Ok(Self::from_price_feeds(&price_feeds, get_utc_timestamp()))
+    }
+
+    /// Download the catalog and replace the cached one both in memory and on disk,
+    /// the overrides are loaded again so the changes made to the overrides file are picked up
+    pub async fn refresh() -> Result<Self> {
+        let mut catalog = Self::fetch().await?;
+        catalog.save_state()?;
+        catalog.overrides = load_overrides();
+        *PYTH_FEED_CATALOG.write().unwrap() = Some(catalog.clone());
+
+        info!(
+            "Refreshed the Pyth feed catalog: {} feeds",
+            catalog.feeds.len()
+        );
+        Ok(catalog)
+    }
+
+    /// Get the cached catalog, it is read from disk when it is not in memory
+    /// and downloaded when neither copy is recent enough
+    pub async fn get() -> Result<Self> {
+        let cached = PYTH_FEED_CATALOG.read().unwrap().clone();
+        if let Some(catalog) = cached.filter(|catalog| catalog.is_fresh()) {
+            return Ok(catalog);
+        }
+
+        if let Ok(Some(mut catalog)) = Self::load_state() {
+            if catalog.is_fresh() {
+                catalog.overrides = load_overrides();
+                *PYTH_FEED_CATALOG.write().unwrap() = Some(catalog.clone());
+                return Ok(catalog);
+            }
+        }
+
+        Self::refresh().await
+    }
+
+    /// Get the ID of the feed for a currency pair, the override table takes precedence over the catalog
+    pub async fn get_feed_id(base: &str, quote: &str) -> Result<String> {
+        let key = Self::get_key(base, quote);
+        let catalog = Self::get().await?;
+
+        catalog
+            .overrides
+            .get(&key)
+            .or_else(|| catalog.feeds.get(&key))
+            .cloned()
+            .ok_or_else(|| ADCError::UnknownTicker(key).into())
+    }
+
+    /// Find the shortest route of feeds from the base to the quote going only through the intermediate assets
+    /// e.g ["STETH", "ETH", "USD"], the route has at most `max_legs` feeds
+    pub fn find_route(
+        &self,
+        base: &str,
+        quote: &str,
+        intermediate_assets: &[String],
+        max_legs: usize,
+    ) -> Option<Vec<String>> {
+        let base = base.to_uppercase();
+        let quote = quote.to_uppercase();
+
+        // breadth first search so that the first route found is the shortest
+        let mut routes: VecDeque<Vec<String>> = VecDeque::from([vec![base]]);
+        while let Some(mut route) = routes.pop_front() {
+            let last = route.last().unwrap().clone();
+            if self.feeds.contains_key(&Self::get_key(&last, &quote)) {
+                route.push(quote);
+                return Some(route);
+            }
+            if route.len() >= max_legs {
+                continue;
+            }
+
+            for asset in intermediate_assets {
+                let asset = asset.to_uppercase();
+                if !route.contains(&asset)
+                    && asset != quote
+                    && self.feeds.contains_key(&Self::get_key(&last, &asset))
+                {
+                    let mut next_route = route.clone();
+                    next_route.push(asset);
+                    routes.push_back(next_route);
+                }
+            }
+        }
+
+        None
+    }
+
+    fn is_fresh(&self) -> bool {
+        get_utc_timestamp().saturating_sub(self.updated_at) <= PYTH_CATALOG_MAX_AGE
+    }
+
+    /// save this struct to a particular point in state
+    pub fn save_state(&self) -> Result<()> {
+        let storage_path: PathBuf = Self::get_storage_path();
+
+        // Serialize the struct to a JSON string
+        let json_string = serde_json::to_string(self)?;
+
+        // create directory if it does not exist
+        if !Path::exists(&storage_path) {
+            let prefix = storage_path.parent().unwrap();
+            std::fs::create_dir_all(prefix)?;
+        }
+
+        // Write the JSON string to a file
+        Ok(fs::write(storage_path, json_string)?)
+    }
+
+    /// restore this struct from a particular point in state if it has been saved
+    pub fn load_state() -> Result<Option<Self>> {
+        let storage_path: PathBuf = Self::get_storage_path();
+        if !Path::exists(&storage_path) {
+            return Ok(None);
+        }
+
+        let contents = fs::read_to_string(storage_path)?;
+        let loaded_state: Self = serde_json::from_str(&contents)?;
+
+        Ok(Some(loaded_state))
+    }
+
+    /// get the default path for the storage which should be a .cache folder
+    pub fn get_storage_path() -> PathBuf {
+        let struct_name = Self::get_struct_name();
+        get_root_path(".cache").join(format!("{struct_name}.json"))
+    }
+}
+
+/// Load the feed IDs to use for ambiguous currency pairs
+/// from the JSON file configured through `PYTH_FEED_OVERRIDES_PATH` e.g `{ "BTC/USD": "0xe62df6c8..." }`
+pub fn load_overrides() -> HashMap<String, String> {
+    let config = Config::env();
+    let Some(overrides_path) = config.pyth_feed_overrides_path else {
+        return HashMap::new();
+    };
+    // the overrides file is optional
+    if !Path::new(&overrides_path).exists() {
+        return HashMap::new();
+    }
+
+    let overrides = fs::read_to_string(&overrides_path)
+        .map_err(anyhow::Error::from)
+        .and_then(|contents| {
+            serde_json::from_str::<HashMap<String, String>>(&contents).map_err(anyhow::Error::from)
+        });
+
+    match overrides {
+        Err(err) => {
+            error!(
+                "Failed to load Pyth feed overrides from {}: {}",
+                overrides_path, err
+            );
+            HashMap::new()
+        }
+        Ok(overrides) => overrides
+            .into_iter()
+            .filter_map(|(pair, feed_id)| {
+                let (base, quote) = pair.split_once('/')?;
+                Some((PythFeedCatalog::get_key(base.trim(), quote.trim()), feed_id))
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_catalog_prefers_crypto_feeds() {
+        let price_feeds = vec![
+            json!({"id": "equity", "attributes": {"asset_type": "Equity", "base": "COIN", "quote_currency": "USD"}}),
+            json!({"id": "crypto", "attributes": {"asset_type": "Crypto", "base": "COIN", "quote_currency": "USD"}}),
+            json!({"id": "eth-btc", "attributes": {"asset_type": "Crypto", "base": "ETH", "quote_currency": "BTC"}}),
+            json!({"id": "invalid", "attributes": {}}),
+        ];
+
+        let catalog = PythFeedCatalog::from_price_feeds(&price_feeds, 0);
+        assert_eq!(catalog.feeds.len(), 2);
+        assert_eq!(catalog.feeds["COIN/USD"], "crypto");
+        assert_eq!(
+            catalog.feeds[&PythFeedCatalog::get_key("eth", "btc")],
+            "eth-btc"
+        );
+    }
+
+    #[test]
+    fn test_find_route_through_intermediate_assets() {
+        let feeds = ["STETH/ETH", "ETH/USD", "WBTC/BTC", "BTC/USD", "ETH/BTC"];
+        let catalog = PythFeedCatalog {
+            updated_at: 0,
+            feeds: feeds
+                .iter()
+                .map(|pair| (pair.to_string(), pair.to_string()))
+                .collect(),
+            overrides: HashMap::new(),
+        };
+        let intermediate_assets = vec!["ETH".to_string(), "BTC".to_string()];
+
+        let route = catalog.find_route("steth", "USD", &intermediate_assets, 3);
+        assert_eq!(route.unwrap(), vec!["STETH", "ETH", "USD"]);
+
+        let route = catalog.find_route("STETH", "BTC", &intermediate_assets, 3);
+        assert_eq!(route.unwrap(), vec!["STETH", "ETH", "BTC"]);
+
+        // the route cannot go through assets which are not configured as intermediate assets
+        let route = catalog.find_route("STETH", "USD", &["BTC".to_string()], 3);
+        assert!(route.is_none());
+        let route = catalog.find_route("STETH", "USD", &intermediate_assets, 1);
+        assert!(route.is_none());
+    }
+}
@@ -1,14 +1,163 @@
 use anyhow::Context;
-use anyhow::{Ok, Result};
+use anyhow::Result;
 use serde_json::Value;
-use types::ProofTypes;
+use tokio::task::JoinSet;
+use tracing::error;
+use types::{ADCError, ProofTypes, MAX_TIMESTAMP_TOLERANCE};
 
+use super::{
+    check_response_status, get_series_interval, to_adc_error, to_invalid_response, BatchProofs,
+};
 use crate::handlers::price::traits::PricingDataSource;
 use crate::helpers::verity::get_verity_client;
 
 #[derive(Debug)]
 pub struct Redstone {}
 
+impl Redstone {
+    /// Get a single proof for the prices of several tickers,
+    /// the proof of each ticker references the symbol of the ticker in the shared response
+    /// the historical prices at `at_timestamp` are requested for each ticker on its own when it is set
+    pub async fn get_batch_proof(tickers: &[String], at_timestamp: Option<u64>) -> BatchProofs {
+        let mut batch_proofs = BatchProofs::new();
+        if tickers.is_empty() {
+            return batch_proofs;
+        }
+
+        if let Some(at_timestamp) = at_timestamp {
+            let mut historical_proofs = JoinSet::new();
+            for ticker in tickers.iter().cloned() {
+                historical_proofs.spawn(async move {
+                    let proof = Self::get_historical_proof(ticker.clone(), at_timestamp).await;
+                    (ticker, proof.map_err(|err| to_adc_error(&err)))
+                });
+            }
+            while let Some(historical_proof) = historical_proofs.join_next().await {
+                match historical_proof {
+                    Ok((ticker, proof)) => {
+                        batch_proofs.insert(ticker, proof);
+                    }
+                    Err(err) => error!("Failed to get redstone historical proof: {}", err),
+                }
+            }
+            return batch_proofs;
+        }
+
+        // construct the request URL containing all the symbols
+        let request_url = format!(
+            "https://api.redstone.finance/prices?symbols={}&provider=redstone",
+            tickers.join(",")
+        );
+
+        // get the proof using the verity client
+        let response = async {
+            let response = get_verity_client().get(&request_url).send().await?;
+            check_response_status("redstone", response.subject.status())?;
+            let http_response_string = response.subject.text().await?;
+            let data: Value = serde_json::from_str(&http_response_string)
+                .map_err(|err| to_invalid_response(err.into()))?;
+            anyhow::Ok((data, response.proof))
+        }
+        .await;
+
+        for ticker in tickers {
+            // check for a valid price for each of the tickers
+            let proof = match &response {
+                Err(err) => Err(to_adc_error(err)),
+                // the symbols which are not supported are left out of the response
+                Ok((data, proof)) => data
+                    .get(ticker)
+                    .ok_or_else(|| ADCError::UnknownTicker(ticker.clone()).into())
+                    .and_then(Self::validate_price)
+                    .map(|_| ProofTypes::RedstoneBatch {
+                        symbol: ticker.clone(),
+                        proof: proof.clone(),
+                    })
+                    .map_err(|err| to_adc_error(&to_invalid_response(err))),
+            };
+            batch_proofs.insert(ticker.clone(), proof);
+        }
+
+        batch_proofs
+    }
+
+    /// Get a proof for the last price of a ticker published at or before `at_timestamp`,
+    /// the price is looked up within `MAX_TIMESTAMP_TOLERANCE` seconds before the timestamp
+    pub async fn get_historical_proof(ticker: String, at_timestamp: u64) -> Result<ProofTypes> {
+        // the historical endpoint expects the timestamps in milliseconds
+        let to_timestamp = at_timestamp * 1000;
+        let from_timestamp = at_timestamp.saturating_sub(MAX_TIMESTAMP_TOLERANCE) * 1000;
+        let request_url = format!(
+            "https://api.redstone.finance/prices?symbol={ticker}&provider=redstone&fromTimestamp={from_timestamp}&toTimestamp={to_timestamp}&limit=1"
+        );
+
+        // get the proof using the verity client
+        let response = get_verity_client().get(&request_url).send().await?;
+        check_response_status("redstone", response.subject.status())?;
+
+        // check for a succesfull and valid response
+        let http_response_string = response.subject.text().await?;
+        Self::validate_response(http_response_string)
+            .await
+            .map_err(to_invalid_response)?;
+
+        Ok(ProofTypes::Redstone(response.proof))
+    }
+
+    /// Get a proof for the prices of a ticker between `from` and `to`
+    pub async fn get_series_proof(ticker: &str, from: u64, to: u64) -> Result<ProofTypes> {
+        // the series endpoint expects the timestamps and the interval in milliseconds
+        let (interval, _) = get_series_interval(to.saturating_sub(from));
+        let request_url = format!(
+            "https://api.redstone.finance/prices?symbol={}&provider=redstone&fromTimestamp={}&toTimestamp={}&interval={}",
+            ticker,
+            from * 1000,
+            to * 1000,
+            interval * 1000
+        );
+
+        // get the proof using the verity client
+        let response = get_verity_client().get(&request_url).send().await?;
+        check_response_status("redstone", response.subject.status())?;
+
+        // check for a succesfull and valid response
+        let http_response_string = response.subject.text().await?;
+        Self::validate_series_response(&http_response_string).map_err(to_invalid_response)?;
+
+        Ok(ProofTypes::RedstoneSeries(response.proof))
+    }
+
+    /// Validate the prices of a series before saving and sending the proof
+    fn validate_series_response(http_response_string: &str) -> Result<()> {
+        // Parse the JSON response
+        let data: Value = serde_json::from_str(http_response_string)?;
+
+        let prices = data
+            .as_array()
+            .context("Prices not available: JSON structure changed")?;
+        if prices.is_empty() {
+            anyhow::bail!("No prices available within the window requested");
+        }
+
+        prices.iter().try_for_each(Self::validate_price)
+    }
+
+    /// Validate the price of a single symbol of a response
+    fn validate_price(symbol_price: &Value) -> Result<()> {
+        // the processor relies on the timestamp to discard stale prices
+        symbol_price["timestamp"]
+            .as_u64()
+            .context("Timestamp not available: JSON structure changed")?;
+
+        // Access the 'price' property and return it
+        symbol_price["value"]
+            .as_f64()
+            .context("Price not available: JSON structure changed")?;
+
+        Ok(())
+    }
+}
+
 impl PricingDataSource for Redstone {
     fn new() -> Self {
         Self {}
@@ -27,10 +176,13 @@ impl PricingDataSource for Redstone {
 
         // get the proof using the verity client
         let response = verity_client.get(&request_url).send().await?;
+        check_response_status("redstone", response.subject.status())?;
 
         // check for a succesfull and valid response
         let http_response_string = response.subject.text().await?;
-        Self::validate_response(http_response_string).await?;
+        Self::validate_response(http_response_string)
+            .await
+            .map_err(to_invalid_response)?;
 
         return Ok(ProofTypes::Redstone(response.proof));
     }
@@ -40,10 +192,6 @@ impl PricingDataSource for Redstone {
         // Parse the JSON response
         let data: Value = serde_json::from_str(&http_response_string)?;
 
-        // Access the 'price' property and return it
-        data[0]["value"]
-            .as_f64()
-            .context("Price not available: JSON structure changed")
-            .and_then(|_| Ok(()))
+        Self::validate_price(&data[0])
     }
 }
@@ -0,0 +1,78 @@
+use crate::config::{Config, NotaryInformation};
+use crate::helpers::utils::get_utc_timestamp;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::{debug, error, info};
+use types::Subscription;
+
+use super::fetch_requests_pricing_data;
+
+/// The default schedule of the job refreshing the prices of the subscriptions (every minute)
+pub const DEFAULT_SUBSCRIPTION_SCHEDULE: &str = "0 * * * * *";
+/// Define a global variable to track whether the subscriptions are being refreshed already
+pub static IS_REFRESHING: AtomicBool = AtomicBool::new(false);
+
+pub async fn handler(notary_information: Arc<NotaryInformation>) {
+    // if the previous refresh is still running then skip this one
+    if IS_REFRESHING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    if let Err(err) = refresh_subscriptions(notary_information).await {
+        error!("Failed to refresh the subscriptions: {}", err);
+    }
+
+    IS_REFRESHING.store(false, Ordering::SeqCst);
+}
+
+/// Fetch the prices of the active subscriptions of the canister which can have an update due and push them to it,
+/// the canister only forwards them to the subscribers whose heartbeat elapsed or whose prices deviated enough
+pub async fn refresh_subscriptions(
+    notary_information: Arc<NotaryInformation>,
+) -> anyhow::Result<()> {
+    let config = Config::env();
+    let agent = config.get_agent().await?;
+
+    let response = agent
+        .query(&config.canister, "get_active_subscriptions")
+        .with_arg(candid::encode_args(())?)
+        .call()
+        .await?;
+    let subscriptions: Vec<Subscription> = candid::decode_one(&response)?;
+
+    // the subscriptions without a deviation threshold are only refreshed once their heartbeat elapsed,
+    // as the prices of the others would not be forwarded
+    let now = get_utc_timestamp();
+    let requests: Vec<_> = subscriptions
+        .iter()
+        .filter(|subscription| subscription.may_be_due(now))
+        .map(Subscription::to_request)
+        .collect();
+    if requests.is_empty() {
+        return Ok(());
+    }
+
+    let responses = fetch_requests_pricing_data(requests).await;
+
+    info!("Refreshed {} subscriptions", responses.len());
+
+    let notary_pubkey = &notary_information.public_key;
+    for response in &responses {
+        debug!("Pushing subscription update {:?}", response);
+        // a failed push only skips the update of its subscription, which is refreshed again on the next run
+        let pushed = async {
+            agent
+                .update(&config.canister, "receive_subscription_update")
+                .with_arg(candid::encode_args((response, notary_pubkey))?)
+                .call_and_wait()
+                .await?;
+            anyhow::Ok(())
+        }
+        .await;
+        if let Err(err) = pushed {
+            error!("Failed to push a subscription update: {}", err);
+        }
+    }
+
+    Ok(())
+}
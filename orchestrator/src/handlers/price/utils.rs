@@ -1,74 +1,542 @@
-use types::{CurrencyPair, ProofTypes, Response, Token};
+use tokio::task::JoinSet;
+use tracing::error;
+use types::{
+    ADCError, CurrencyPair, PriceRoute, PricingConfig, ProofTypes, Response, SeriesOpts,
+    SourceFailure, Token, MAX_PRICE_LEGS,
+};
+
+use crate::{config::Config, helpers::utils::get_utc_timestamp};
 
 use super::{
-    sources::{pyth::Pyth, redstone::Redstone},
+    sources::{
+        binance::Binance, coinbase::Coinbase, coingecko::CoinGecko, custom::get_custom_sources,
+        kraken::Kraken, pyth::Pyth, pyth_catalog::PythFeedCatalog, redstone::Redstone,
+        to_adc_error, BatchProofs, DEFAULT_QUOTE_CURRENCY,
+    },
     traits::PricingDataSource,
 };
 
-/// Given a token, get proofs for the token price from the various supported sources
-async fn collect_proof_from_sources(token: &Token) -> anyhow::Result<Vec<ProofTypes>> {
-    // set the minimum number of proofs needed
-    let min_proofs_required = 1;
+/// The proofs gotten from the sources which support requesting the prices of several tickers at once
+#[derive(Debug, Default)]
+pub struct SharedProofs {
+    /// the pyth proofs indexed by currency pair e.g "BTC/USD" or "ETH/BTC"
+    pub pyth: BatchProofs,
+    /// the redstone proofs indexed by ticker
+    pub redstone: BatchProofs,
+    /// the time of the historical prices proven, the latest prices are proven when not set
+    pub at_timestamp: Option<u64>,
+}
+
+impl SharedProofs {
+    /// Get a single proof per batched source for the prices of all the tokens in the responses provided
+    /// the pairs which have a direct feed are requested as such instead of through their tokens,
+    /// unless more than one source is required for them with the pricing config provided,
+    /// and the tokens are only requested from the sources requested for their pair
+    /// only the responses requesting the prices at `at_timestamp` are considered
+    pub async fn fetch(
+        price_responses: &[Response],
+        at_timestamp: Option<u64>,
+        pricing_config: &PricingConfig,
+    ) -> Self {
+        let mut pyth_pairs: Vec<(String, String)> = vec![];
+        let mut redstone_tickers: Vec<String> = vec![];
+        let pairs = price_responses
+            .iter()
+            .filter(|response| response.opts.price && response.opts.at_timestamp == at_timestamp)
+            .flat_map(|response| {
+                let min_sources = get_min_sources(pricing_config, response);
+                response
+                    .pairs
+                    .iter()
+                    .flat_map(get_priced_pairs)
+                    .map(move |pair| (pair, min_sources))
+            });
+        for (pair, min_sources) in pairs {
+            let uses_pyth = pair.uses_source("pyth");
+            let mut tokens = vec![&pair.base];
+            if let Some(quote) = &pair.quote {
+                let direct_pair = (pair.base.ticker.clone(), quote.ticker.clone());
+                // the direct feed is a single source so it is only used when a single source is required
+                if uses_pyth
+                    && min_sources <= 1
+                    && has_direct_feed(&direct_pair.0, &direct_pair.1).await
+                {
+                    push_unique(&mut pyth_pairs, direct_pair);
+                    continue;
+                }
+                tokens.push(quote);
+            }
+
+            for token in tokens {
+                if uses_pyth {
+                    push_unique(
+                        &mut pyth_pairs,
+                        (token.ticker.clone(), DEFAULT_QUOTE_CURRENCY.to_string()),
+                    );
+                }
+                if pair.uses_source("redstone") {
+                    push_unique(&mut redstone_tickers, token.ticker.clone());
+                }
+            }
+        }
+
+        let (pyth, redstone) = tokio::join!(
+            Pyth::get_batch_proof(&pyth_pairs, at_timestamp),
+            Redstone::get_batch_proof(&redstone_tickers, at_timestamp)
+        );
+
+        Self {
+            pyth,
+            redstone,
+            at_timestamp,
+        }
+    }
+
+    /// Get the proofs of a ticker from the batched sources requested for a currency pair,
+    /// the tickers which were not part of the batch are requested on their own
+    async fn get(
+        &self,
+        ticker: &str,
+        currency_pair: &CurrencyPair,
+    ) -> Vec<(String, anyhow::Result<ProofTypes>)> {
+        let mut proofs = vec![];
+        if currency_pair.uses_source("pyth") {
+            let pyth_key = PythFeedCatalog::get_key(ticker, DEFAULT_QUOTE_CURRENCY);
+            let pyth_proof = match (self.pyth.get(&pyth_key), self.at_timestamp) {
+                (Some(proof), _) => to_proof_result(proof),
+                (None, None) => Pyth::get_proof(ticker.to_string()).await,
+                (None, Some(_)) => {
+                    let pair = (ticker.to_string(), DEFAULT_QUOTE_CURRENCY.to_string());
+                    let batch_proofs = Pyth::get_batch_proof(&[pair], self.at_timestamp).await;
+                    batch_proofs
+                        .get(&pyth_key)
+                        .map(to_proof_result)
+                        .unwrap_or_else(|| Err(ADCError::UnknownTicker(pyth_key).into()))
+                }
+            };
+            proofs.push(("pyth".to_string(), pyth_proof));
+        }
+        if currency_pair.uses_source("redstone") {
+            let redstone_proof = match (self.redstone.get(ticker), self.at_timestamp) {
+                (Some(proof), _) => to_proof_result(proof),
+                (None, None) => Redstone::get_proof(ticker.to_string()).await,
+                (None, Some(at_timestamp)) => {
+                    Redstone::get_historical_proof(ticker.to_string(), at_timestamp).await
+                }
+            };
+            proofs.push(("redstone".to_string(), redstone_proof));
+        }
+
+        proofs
+    }
+
+    /// Get the proof of the direct feed of a currency pair if it was part of the batch
+    fn get_direct(&self, base: &str, quote: &str) -> Option<Result<ProofTypes, ADCError>> {
+        self.pyth
+            .get(&PythFeedCatalog::get_key(base, quote))
+            .cloned()
+    }
+}
+
+fn to_proof_result(proof: &Result<ProofTypes, ADCError>) -> anyhow::Result<ProofTypes> {
+    proof.clone().map_err(anyhow::Error::from)
+}
+
+/// Get the pairs whose proofs are needed to price a currency pair,
+/// which are the legs of the pair if it is priced through a route
+fn get_priced_pairs(currency_pair: &CurrencyPair) -> &[CurrencyPair] {
+    match currency_pair.legs.is_empty() {
+        true => std::slice::from_ref(currency_pair),
+        false => &currency_pair.legs,
+    }
+}
+
+fn push_unique<T: PartialEq>(items: &mut Vec<T>, item: T) {
+    if !items.contains(&item) {
+        items.push(item);
+    }
+}
+
+/// Get the minimum number of sources required for the prices of a response,
+/// the one of the pricing config of the canister applies unless the request overrides it
+fn get_min_sources(pricing_config: &PricingConfig, price_response: &Response) -> usize {
+    // at least one valid source is always required
+    pricing_config
+        .with_overrides(&price_response.opts)
+        .min_sources
+        .max(1) as usize
+}
+
+/// Get the proof of the attributes of the feed a direct proof of a currency pair was gotten from
+async fn get_feed_info_proof(
+    base: &str,
+    quote: &str,
+    direct_proof: &ProofTypes,
+) -> anyhow::Result<ProofTypes> {
+    let ProofTypes::PythBatch { feed_id, .. } = direct_proof else {
+        anyhow::bail!("direct proofs are expected to be pyth batch proofs");
+    };
+
+    Pyth::get_feed_info_proof(base, quote, feed_id).await
+}
+
+/// Check if a source provides the price of the base token in the quote token directly
+async fn has_direct_feed(base: &str, quote: &str) -> bool {
+    PythFeedCatalog::get_feed_id(base, quote).await.is_ok()
+}
+
+/// Price the currency pairs which have neither a direct feed nor a feed for their base token
+/// through a route of feeds going through the configured intermediate assets e.g STETH/ETH/USD
+pub async fn resolve_routes(price_response: &mut Response) {
+    let config = Config::env();
+
+    for pair in price_response.pairs.iter_mut() {
+        if !pair.legs.is_empty() {
+            continue;
+        }
+
+        let base = pair.base.ticker.clone();
+        let quote = match &pair.quote {
+            Some(quote) => quote.ticker.clone(),
+            None => DEFAULT_QUOTE_CURRENCY.to_string(),
+        };
+        if has_direct_feed(&base, &quote).await
+            || has_direct_feed(&base, DEFAULT_QUOTE_CURRENCY).await
+        {
+            continue;
+        }
+
+        let route = match PythFeedCatalog::get().await {
+            Ok(catalog) => {
+                catalog.find_route(&base, &quote, &config.intermediate_assets, MAX_PRICE_LEGS)
+            }
+            Err(err) => {
+                error!("Failed to get the Pyth feed catalog: {}", err);
+                None
+            }
+        };
+        let Some(route) = route else {
+            continue;
+        };
+
+        let legs = route
+            .windows(2)
+            .map(|leg| {
+                CurrencyPair::try_from(format!("{}/{}", leg[0], leg[1]))
+                    .map(|leg| leg.with_requested_sources(pair.requested_sources.clone()))
+            })
+            .collect::<anyhow::Result<Vec<CurrencyPair>>>();
+        match legs {
+            Ok(legs) => pair.legs = legs,
+            Err(err) => error!("Invalid route {:?} for {}: {}", route, pair.repr, err),
+        }
+    }
+}
+
+/// Get the proof of a token from a source, unless the source was not requested for the currency pair
+async fn get_requested_proof<T: PricingDataSource>(
+    currency_pair: &CurrencyPair,
+    source: &str,
+    ticker: &str,
+) -> Option<(String, anyhow::Result<ProofTypes>)> {
+    if !currency_pair.uses_source(source) {
+        return None;
+    }
+
+    Some((source.to_string(), T::get_proof(ticker.to_string()).await))
+}
+
+/// Given a token of a currency pair, get proofs for the token price from the sources requested for the pair
+/// the proofs of the batched sources are taken from the shared proofs provided
+/// the sources which failed to provide a proof are returned alongside the valid proofs
+/// only the batched sources provide historical prices, so the other sources are not requested for them
+async fn collect_proof_from_sources(
+    token: &Token,
+    currency_pair: &CurrencyPair,
+    shared_proofs: &SharedProofs,
+) -> (Vec<ProofTypes>, Vec<SourceFailure>) {
+    let mut all_proofs = shared_proofs.get(&token.ticker, currency_pair).await;
+    if shared_proofs.at_timestamp.is_some() {
+        return split_proof_results(&token.ticker, all_proofs);
+    }
 
-    let (redstone_proof, pyth_proof) = tokio::join!(
-        Pyth::get_proof(token.ticker.clone()),
-        Redstone::get_proof(token.ticker.clone())
+    let (coinbase_proof, binance_proof, kraken_proof) = tokio::join!(
+        get_requested_proof::<Coinbase>(currency_pair, "coinbase", &token.ticker),
+        get_requested_proof::<Binance>(currency_pair, "binance", &token.ticker),
+        get_requested_proof::<Kraken>(currency_pair, "kraken", &token.ticker)
+    );
+    all_proofs.extend(
+        [coinbase_proof, binance_proof, kraken_proof]
+            .into_iter()
+            .flatten(),
     );
 
-    let all_proofs = vec![redstone_proof, pyth_proof];
+    // get proofs from the sources defined in the sources config file
+    let mut custom_proofs = JoinSet::new();
+    let custom_sources = get_custom_sources()
+        .into_iter()
+        .filter(|custom_source| currency_pair.uses_source(&custom_source.definition.name));
+    for custom_source in custom_sources {
+        let ticker = token.ticker.clone();
+        custom_proofs.spawn(async move {
+            let proof = custom_source.get_proof(ticker).await;
+            (custom_source.definition.name, proof)
+        });
+    }
+    while let Some(custom_proof) = custom_proofs.join_next().await {
+        match custom_proof {
+            Ok(custom_proof) => all_proofs.push(custom_proof),
+            Err(err) => error!("Failed to get custom source proof: {}", err),
+        }
+    }
 
-    let valid_proofs: Vec<ProofTypes> = all_proofs
-        .iter()
-        .filter_map(|proof_res| proof_res.as_ref().ok().cloned())
-        .collect();
+    split_proof_results(&token.ticker, all_proofs)
+}
 
-    if valid_proofs.len() < min_proofs_required {
-        anyhow::bail!("NOT ENOUGH PROOFS")
+/// Split the proofs gotten for a ticker into the valid proofs and the failures of the sources which did not provide one
+fn split_proof_results(
+    ticker: &str,
+    proof_results: Vec<(String, anyhow::Result<ProofTypes>)>,
+) -> (Vec<ProofTypes>, Vec<SourceFailure>) {
+    let mut valid_proofs: Vec<ProofTypes> = vec![];
+    let mut failed_sources: Vec<SourceFailure> = vec![];
+    for (source, proof_res) in proof_results {
+        match proof_res {
+            Ok(proof) => valid_proofs.push(proof),
+            Err(err) => {
+                failed_sources.push(SourceFailure::new(&source, ticker, to_adc_error(&err)))
+            }
+        }
     }
 
-    Ok(valid_proofs)
+    (valid_proofs, failed_sources)
 }
 
-/// For a given currency pair fetch the proofs for the base token
-/// and the quote token if it exists
-pub async fn fetch_proofs(currency_pair: &mut CurrencyPair) -> anyhow::Result<()> {
+/// For a given currency pair fetch the proofs of each of its legs if it is priced through a route
+/// otherwise fetch the proofs of the pair itself, each token needs proofs from at least `min_sources` sources
+pub async fn fetch_proofs(
+    currency_pair: &mut CurrencyPair,
+    shared_proofs: &SharedProofs,
+    min_sources: usize,
+) -> anyhow::Result<()> {
+    if currency_pair.legs.is_empty() {
+        return fetch_pair_proofs(currency_pair, shared_proofs, min_sources).await;
+    }
+
+    for leg in currency_pair.legs.iter_mut() {
+        fetch_pair_proofs(leg, shared_proofs, min_sources).await?;
+        // the pair cannot be priced if any of its legs cannot be
+        if let Some(err) = &leg.error {
+            currency_pair.error = Some(err.clone());
+        }
+    }
+    currency_pair.route = Some(PriceRoute::MultiHop {
+        via: currency_pair.legs[1..]
+            .iter()
+            .map(|leg| leg.base.ticker.clone())
+            .collect(),
+    });
+
+    Ok(())
+}
+
+/// For a given currency pair fetch the proofs of its direct feed if one exists
+/// otherwise fetch the proofs for the base token and the quote token if it exists
+/// the sources which failed to provide a proof and the route used are recorded on the currency pair
+/// and the pair is marked as failed if a token does not get proofs from at least `min_sources` sources
+async fn fetch_pair_proofs(
+    currency_pair: &mut CurrencyPair,
+    shared_proofs: &SharedProofs,
+    min_sources: usize,
+) -> anyhow::Result<()> {
     let base = currency_pair.base.clone();
     let quote = currency_pair.quote.clone();
 
-    let base_proofs = collect_proof_from_sources(&base).await;
-    // if theres an error with the proofs, then set the error flag to be true
-    // otherwise sace the proofs
-    if base_proofs.is_err() {
-        currency_pair.error = base_proofs.map_err(|e| e.to_string()).err();
+    // prefer the direct feed of the pair, and fall back to triangulation if it failed,
+    // only pyth provides direct feeds so the pair is also triangulated when more sources are required
+    // for its price to go through the outlier rejection and the quorum of the processor
+    let uses_direct_feed = currency_pair.uses_source("pyth") && min_sources <= 1;
+    if let Some(quote) = quote.as_ref().filter(|_| uses_direct_feed) {
+        match shared_proofs.get_direct(&base.ticker, &quote.ticker) {
+            // the processor only trusts the direct feed along with a proof that it is a feed of the pair
+            Some(Ok(direct_proof)) => {
+                match get_feed_info_proof(&base.ticker, &quote.ticker, &direct_proof).await {
+                    Ok(feed_info_proof) => {
+                        currency_pair.base.proofs = Some(vec![direct_proof, feed_info_proof]);
+                        currency_pair.route = Some(PriceRoute::Direct);
+                        return Ok(());
+                    }
+                    Err(err) => currency_pair.failed_sources.push(SourceFailure::new(
+                        "pyth",
+                        &currency_pair.repr,
+                        to_adc_error(&err),
+                    )),
+                }
+            }
+            Some(Err(err)) => currency_pair.failed_sources.push(SourceFailure::new(
+                "pyth",
+                &currency_pair.repr,
+                err,
+            )),
+            None => {}
+        }
+    }
+    currency_pair.route = Some(match &quote {
+        Some(_) => PriceRoute::Triangulated {
+            via: DEFAULT_QUOTE_CURRENCY.to_string(),
+        },
+        None => PriceRoute::Direct,
+    });
+
+    let (base_proofs, failed_sources) =
+        collect_proof_from_sources(&base, currency_pair, shared_proofs).await;
+    // if there are not enough proofs, then set the error flag to be true
+    // otherwise save the proofs
+    if base_proofs.len() < min_sources {
+        currency_pair.error = Some(ADCError::insufficient_sources(
+            &base.ticker,
+            base_proofs.len(),
+            min_sources,
+            &failed_sources,
+        ));
+        currency_pair.failed_sources.extend(failed_sources);
         return Ok(());
     } else {
-        currency_pair.base.proofs = Some(base_proofs.unwrap());
+        currency_pair.base.proofs = Some(base_proofs);
     }
+    currency_pair.failed_sources.extend(failed_sources);
 
     // get the proofs for the quote if it exists
     if quote.is_some() {
         let quote = quote.unwrap();
-        let quote_proofs = collect_proof_from_sources(&quote).await;
-        // if theres an error with the proofs, then set the error flag to be true
+        let (quote_proofs, failed_sources) =
+            collect_proof_from_sources(&quote, currency_pair, shared_proofs).await;
+        // if there are not enough proofs, then set the error flag to be true
         // otherwise save the proof
-        if quote_proofs.is_err() {
-            currency_pair.error = quote_proofs.map_err(|e| e.to_string()).err();
+        if quote_proofs.len() < min_sources {
+            currency_pair.error = Some(ADCError::insufficient_sources(
+                &quote.ticker,
+                quote_proofs.len(),
+                min_sources,
+                &failed_sources,
+            ));
         } else {
             currency_pair.quote = Some(Token {
                 ticker: quote.ticker,
-                proofs: Some(quote_proofs?),
+                proofs: Some(quote_proofs),
             })
         }
+        currency_pair.failed_sources.extend(failed_sources);
     }
 
     Ok(())
 }
 
+/// For a given currency pair fetch the proofs of the market data of its base token,
+/// the proof already fetched for the price of the token is reused by the sources which provide both
+/// the sources which failed to provide a proof are recorded on the currency pair
+async fn fetch_market_data_proofs(currency_pair: &mut CurrencyPair) {
+    let ticker = currency_pair.base.ticker.clone();
+    let binance_price_proof = currency_pair
+        .base
+        .proofs
+        .iter()
+        .flatten()
+        .find(|proof| matches!(proof, ProofTypes::Binance(_)))
+        .cloned();
+
+    let (coingecko_proof, binance_proof) = tokio::join!(
+        get_requested_proof::<CoinGecko>(currency_pair, "coingecko", &ticker),
+        async {
+            match binance_price_proof {
+                Some(proof) => Some(("binance".to_string(), Ok(proof))),
+                None => get_requested_proof::<Binance>(currency_pair, "binance", &ticker).await,
+            }
+        }
+    );
+
+    let mut market_data_proofs: Vec<ProofTypes> = vec![];
+    for (source, proof_res) in [coingecko_proof, binance_proof].into_iter().flatten() {
+        match proof_res {
+            Ok(proof) => market_data_proofs.push(proof),
+            Err(err) => currency_pair.failed_sources.push(SourceFailure::new(
+                &source,
+                &ticker,
+                to_adc_error(&err),
+            )),
+        }
+    }
+
+    currency_pair.market_data_proofs = Some(market_data_proofs);
+}
+
+/// For a given currency pair fetch the proofs of the price series of its base token over the window
+/// ending at `at_timestamp` or now, the sources which failed to provide a proof are recorded on the currency pair
+async fn fetch_series_proofs(
+    currency_pair: &mut CurrencyPair,
+    series_opts: &SeriesOpts,
+    at_timestamp: Option<u64>,
+) {
+    let ticker = currency_pair.base.ticker.clone();
+    let to = at_timestamp.unwrap_or_else(get_utc_timestamp);
+    let from = to.saturating_sub(series_opts.window);
+
+    let uses_redstone = currency_pair.uses_source("redstone");
+    let uses_binance = currency_pair.uses_source("binance");
+    let (redstone_proof, binance_proof) = tokio::join!(
+        async {
+            match uses_redstone {
+                true => Some((
+                    "redstone".to_string(),
+                    Redstone::get_series_proof(&ticker, from, to).await,
+                )),
+                false => None,
+            }
+        },
+        async {
+            match uses_binance {
+                true => Some((
+                    "binance".to_string(),
+                    Binance::get_series_proof(&ticker, from, to).await,
+                )),
+                false => None,
+            }
+        }
+    );
+
+    let (series_proofs, failed_sources) = split_proof_results(
+        &ticker,
+        [redstone_proof, binance_proof]
+            .into_iter()
+            .flatten()
+            .collect(),
+    );
+    currency_pair.series_proofs = Some(series_proofs);
+    currency_pair.series_to = Some(to);
+    currency_pair.failed_sources.extend(failed_sources);
+}
+
 /// For a given price response potentially containig multiple currency pairs
 /// go through all the currency pairs and get the proofs from various sources
-pub async fn process_proofs(price_response: &mut Response) -> anyhow::Result<()> {
+/// for the price, the market data and the price series requested
+/// the minimum number of sources of the pricing config of the canister applies unless the request overrides it
+pub async fn process_proofs(
+    price_response: &mut Response,
+    shared_proofs: &SharedProofs,
+    pricing_config: &PricingConfig,
+) -> anyhow::Result<()> {
+    let min_sources = get_min_sources(pricing_config, price_response);
     for pair in &mut price_response.pairs {
-        fetch_proofs(pair).await?; // Assuming fetch_data returns a Future
+        if price_response.opts.price {
+            fetch_proofs(pair, shared_proofs, min_sources).await?; // Assuming fetch_data returns a Future
+        }
+        // the market data is only available for the latest prices
+        if price_response.opts.wants_market_data() && price_response.opts.at_timestamp.is_none() {
+            fetch_market_data_proofs(pair).await;
+        }
+        if let Some(series_opts) = &price_response.opts.series {
+            fetch_series_proofs(pair, series_opts, price_response.opts.at_timestamp).await;
+        }
     }
 
     price_response.processed = true;
@@ -9,7 +9,7 @@ use std::{
     sync::Arc,
 };
 use tracing::{debug, error, info};
-use types::{ErrorResponse, ResponseV2};
+use types::{ADCError, ErrorResponse, ResponseV2};
 
 /// Define a default base currency for the price pair when one is nor provided
 pub const DEFAULT_BASE_CURRENCY: &str = "USDT";
@@ -127,7 +127,7 @@ pub async fn resolve_data(event_logs: Vec<EventUrlLog>) -> Vec<ResponseResult> {
                     responses.push(Err(ErrorResponse::new(
                         request.id,
                         request.owner,
-                        msg.to_string(),
+                        ADCError::Other(msg.to_string()),
                     )));
                 }
                 Ok(verify_response) => {
@@ -1,9 +1,12 @@
 use std::env;
 use std::sync::Arc;
 
+use crate::handlers::price::sources::custom::load_custom_sources;
+use crate::handlers::price::sources::pyth_catalog::PythFeedCatalog;
+use crate::handlers::price::subscriptions;
 use crate::{config::Config, handlers::batch_handler, helpers::cron::CronJob};
 use tokio_cron_scheduler::Job;
-use tracing::info;
+use tracing::{error, info};
 
 pub async fn load_cron() -> anyhow::Result<CronJob> {
     let cronjob = CronJob::new().await;
@@ -12,6 +15,9 @@ pub async fn load_cron() -> anyhow::Result<CronJob> {
     info!("ADC_CANISTER: {:?}", env::var("ADC_CANISTER"));
     // get the connected notary public key here and pass it to the price handler
     let notary_information = Arc::new(config.get_connected_notary().await?.clone());
+    let subscription_notary_information = Arc::clone(&notary_information);
+    let custom_sources = load_custom_sources();
+    info!("Loaded {} custom sources", custom_sources.len());
 
     // add jobs to the cronjob
     cronjob
@@ -20,6 +26,27 @@ pub async fn load_cron() -> anyhow::Result<CronJob> {
             Box::pin(async { batch_handler(notary_information).await })
         })?)
         .await?;
+    cronjob
+        .add_job(Job::new_async(
+            &config.pyth_catalog_schedule[..],
+            move |_, _| {
+                Box::pin(async {
+                    if let Err(err) = PythFeedCatalog::refresh().await {
+                        error!("Failed to refresh the Pyth feed catalog: {}", err);
+                    }
+                })
+            },
+        )?)
+        .await?;
+    cronjob
+        .add_job(Job::new_async(
+            &config.subscription_schedule[..],
+            move |_, _| {
+                let notary_information = Arc::clone(&subscription_notary_information);
+                Box::pin(async { subscriptions::handler(notary_information).await })
+            },
+        )?)
+        .await?;
     // add jobs to the cronjob
 
     return Ok(cronjob);
@@ -0,0 +1,109 @@
+use types::{ADCError, AccessMode, AccessRule, Endpoint};
+
+/// Check if a principal can make a request through an endpoint under the access mode of the processor,
+/// from the rule it is whitelisted with if it is whitelisted and whether it is blacklisted
+pub fn check_access(
+    mode: &AccessMode,
+    endpoint: &Endpoint,
+    whitelist_rule: Option<&AccessRule>,
+    is_blacklisted: bool,
+) -> Result<(), ADCError> {
+    match mode {
+        AccessMode::Open => Ok(()),
+        AccessMode::Whitelist => match whitelist_rule {
+            Some(rule) => check_rule(rule, endpoint),
+            None => Err(ADCError::AccessDenied(
+                "the caller is not whitelisted".to_string(),
+            )),
+        },
+        AccessMode::Blacklist if is_blacklisted => Err(ADCError::AccessDenied(
+            "the caller is blacklisted".to_string(),
+        )),
+        AccessMode::Blacklist => Ok(()),
+    }
+}
+
+/// Check if the rule a principal is whitelisted with lets a request be made through an endpoint
+pub fn check_rule(rule: &AccessRule, endpoint: &Endpoint) -> Result<(), ADCError> {
+    if let Some(endpoints) = &rule.endpoints {
+        if !endpoints.contains(endpoint) {
+            return Err(ADCError::AccessDenied(format!(
+                "{} requests are not allowed",
+                endpoint
+            )));
+        }
+    }
+    if rule.quota == Some(0) {
+        return Err(ADCError::AccessDenied(
+            "the request quota is exhausted".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Count a request made against the quota of the rule a principal is whitelisted with
+pub fn use_quota(rule: &mut AccessRule) {
+    if let Some(quota) = rule.quota.as_mut() {
+        *quota = quota.saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_access_rule() {
+        let mut rule = AccessRule {
+            endpoints: Some(vec![Endpoint::Price]),
+            quota: Some(1),
+        };
+
+        assert!(check_rule(&rule, &Endpoint::Price).is_ok());
+        assert!(check_rule(&rule, &Endpoint::Url).is_err());
+        use_quota(&mut rule);
+        assert_eq!(rule.quota, Some(0));
+        assert!(check_rule(&rule, &Endpoint::Price).is_err());
+        assert!(check_rule(&AccessRule::default(), &Endpoint::Url).is_ok());
+    }
+
+    #[test]
+    fn test_check_access() {
+        let price_only = AccessRule {
+            endpoints: Some(vec![Endpoint::Price]),
+            quota: None,
+        };
+
+        assert!(check_access(&AccessMode::Open, &Endpoint::Url, None, true).is_ok());
+
+        assert!(check_access(&AccessMode::Whitelist, &Endpoint::Price, None, false).is_err());
+        assert!(check_access(
+            &AccessMode::Whitelist,
+            &Endpoint::Price,
+            Some(&price_only),
+            false
+        )
+        .is_ok());
+        assert!(check_access(
+            &AccessMode::Whitelist,
+            &Endpoint::Url,
+            Some(&price_only),
+            false
+        )
+        .is_err());
+
+        assert!(check_access(&AccessMode::Blacklist, &Endpoint::Url, None, false).is_ok());
+        assert_eq!(
+            check_access(
+                &AccessMode::Blacklist,
+                &Endpoint::Url,
+                Some(&price_only),
+                true
+            ),
+            Err(ADCError::AccessDenied(
+                "the caller is blacklisted".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,754 @@
+use std::cmp::Ordering;
+
+use types::fixed_point::mul_div;
+use types::{
+    AggregationStrategy, Candle, FixedPointPrice, MarketData, PriceSeries, PricingConfig,
+    RequestOpts, SeriesKind, SeriesValue, SourceMarketData, SourcePrice, SourceWeight,
+    FIXED_POINT_DECIMALS,
+};
+
+use crate::sources::PricePoint;
+
+/// The precision source weights are converted to integers with when combining exact prices
+const WEIGHT_PRECISION: f64 = 1_000_000.0;
+/// How far (in seconds) the clock of a source may be ahead of the canister's,
+/// the prices published further in the future are rejected
+pub const MAX_CLOCK_SKEW: u64 = 10;
+
+/// Combine the prices gotten from several sources into a single price
+/// using the aggregation strategy of the pricing config provided
+pub fn aggregate_prices(
+    source_prices: &[SourcePrice],
+    config: &PricingConfig,
+) -> anyhow::Result<f64> {
+    config.validate()?;
+
+    if source_prices.is_empty() {
+        anyhow::bail!("No source prices to aggregate")
+    }
+    if source_prices.iter().any(|sp| !sp.price.is_finite()) {
+        anyhow::bail!("Invalid source price")
+    }
+
+    let prices: Vec<f64> = source_prices.iter().map(|sp| sp.price).collect();
+    let aggregated_price = match &config.aggregation {
+        AggregationStrategy::Mean => mean(&prices),
+        AggregationStrategy::Median => median(&prices),
+        AggregationStrategy::TrimmedMean(fraction) => trimmed_mean(&prices, *fraction),
+        AggregationStrategy::WeightedMean(weights) => weighted_mean(source_prices, weights)?,
+    };
+
+    Ok(aggregated_price)
+}
+
+/// Combine the exact prices gotten from several sources into a single exact price
+/// with the same strategy as `aggregate_prices` but without going through floating point,
+/// no price is derived if any of the sources did not provide an exact price
+pub fn aggregate_fixed_prices(
+    source_prices: &[SourcePrice],
+    config: &PricingConfig,
+) -> Option<FixedPointPrice> {
+    if source_prices.is_empty() {
+        return None;
+    }
+
+    let prices = source_prices
+        .iter()
+        .map(|sp| {
+            Some(
+                sp.fixed_price
+                    .as_ref()?
+                    .rescale(FIXED_POINT_DECIMALS)?
+                    .value,
+            )
+        })
+        .collect::<Option<Vec<u128>>>()?;
+    let aggregated_price = match &config.aggregation {
+        AggregationStrategy::Mean => fixed_mean(&prices),
+        AggregationStrategy::Median => fixed_median(&prices),
+        AggregationStrategy::TrimmedMean(fraction) => fixed_trimmed_mean(&prices, *fraction),
+        AggregationStrategy::WeightedMean(weights) => {
+            fixed_weighted_mean(source_prices, &prices, weights)
+        }
+    }?;
+
+    Some(FixedPointPrice::new(aggregated_price, FIXED_POINT_DECIMALS))
+}
+
+/// Combine the market data gotten from several sources by taking the median of each value requested,
+/// no market data is derived if none of the sources provided any of the values requested
+pub fn aggregate_market_data(
+    source_market_data: Vec<SourceMarketData>,
+    opts: &RequestOpts,
+) -> Option<MarketData> {
+    let combine = |requested: Option<bool>, value: fn(&SourceMarketData) -> Option<f64>| {
+        if !requested.unwrap_or(false) {
+            return None;
+        }
+        let values: Vec<f64> = source_market_data
+            .iter()
+            .filter_map(value)
+            .filter(|value| value.is_finite())
+            .collect();
+        (!values.is_empty()).then(|| median(&values))
+    };
+
+    let volume = combine(opts.volume, |sd| sd.volume);
+    let market_cap = combine(opts.market_cap, |sd| sd.market_cap);
+    let change_24h = combine(opts.change_24h, |sd| sd.change_24h);
+    if volume.is_none() && market_cap.is_none() && change_24h.is_none() {
+        return None;
+    }
+
+    Some(MarketData {
+        volume,
+        market_cap,
+        change_24h,
+        sources: source_market_data,
+    })
+}
+
+/// Compute the series requested from the price points of each source within the window `from` to `to`,
+/// the time and volume weighted averages are the median of the averages of each source
+/// and the candles are built from the source which provided the most points within the window,
+/// no series is derived if none of the sources provided a valid point within the window
+pub fn aggregate_series(
+    source_points: Vec<(String, Vec<PricePoint>)>,
+    kind: &SeriesKind,
+    from: u64,
+    to: u64,
+) -> Option<PriceSeries> {
+    let source_points: Vec<(String, Vec<PricePoint>)> = source_points
+        .into_iter()
+        .map(|(source, mut points)| {
+            points
+                .retain(|point| (from..=to).contains(&point.timestamp) && point.price.is_finite());
+            points.sort_by_key(|point| point.timestamp);
+            (source, points)
+        })
+        .filter(|(_, points)| !points.is_empty())
+        .collect();
+
+    let average = |points: &[PricePoint]| match kind {
+        SeriesKind::Twap => time_weighted_average(points, to),
+        _ => volume_weighted_average(points),
+    };
+    let (value, used_points): (SeriesValue, Vec<&(String, Vec<PricePoint>)>) = match kind {
+        SeriesKind::Ohlc { candle_interval } => {
+            let used = source_points
+                .iter()
+                .max_by_key(|(_, points)| points.len())?;
+            let candles = build_candles(&used.1, from, *candle_interval);
+            (SeriesValue::Ohlc(candles), vec![used])
+        }
+        SeriesKind::Twap | SeriesKind::Vwap => {
+            let (used, averages): (Vec<_>, Vec<f64>) = source_points
+                .iter()
+                .filter_map(|source| Some((source, average(&source.1)?)))
+                .unzip();
+            if averages.is_empty() {
+                return None;
+            }
+
+            let value = match kind {
+                SeriesKind::Twap => SeriesValue::Twap(median(&averages)),
+                _ => SeriesValue::Vwap(median(&averages)),
+            };
+            (value, used)
+        }
+    };
+
+    Some(PriceSeries {
+        from,
+        to,
+        value,
+        sources: used_points
+            .iter()
+            .map(|(source, _)| source.to_string())
+            .collect(),
+        points: used_points
+            .iter()
+            .map(|(_, points)| points.len() as u32)
+            .sum(),
+    })
+}
+
+/// Compute the time weighted average of price points sorted by time,
+/// each price is weighted by the time until the next point and the last one by the time until `to`
+pub fn time_weighted_average(points: &[PricePoint], to: u64) -> Option<f64> {
+    if points.is_empty() {
+        return None;
+    }
+
+    let mut weighted_sum = 0.0;
+    let mut total_duration = 0.0;
+    for (index, point) in points.iter().enumerate() {
+        let end = points
+            .get(index + 1)
+            .map(|next_point| next_point.timestamp)
+            .unwrap_or(to);
+        let duration = end.saturating_sub(point.timestamp) as f64;
+        weighted_sum += point.price * duration;
+        total_duration += duration;
+    }
+
+    // all the points are at the end of the window
+    if total_duration == 0.0 {
+        let prices: Vec<f64> = points.iter().map(|point| point.price).collect();
+        return Some(mean(&prices));
+    }
+
+    Some(weighted_sum / total_duration)
+}
+
+/// Compute the volume weighted average of price points,
+/// no average is derived if any of the points does not have a volume or nothing was traded
+pub fn volume_weighted_average(points: &[PricePoint]) -> Option<f64> {
+    let mut weighted_sum = 0.0;
+    let mut total_volume = 0.0;
+    for point in points {
+        let volume = point.volume.filter(|volume| volume.is_finite())?;
+        weighted_sum += point.price * volume;
+        total_volume += volume;
+    }
+
+    (total_volume > 0.0).then(|| weighted_sum / total_volume)
+}
+
+/// Split price points sorted by time into consecutive candles of `candle_interval` seconds starting at `from`,
+/// the points which close a candle of the source are placed by the time it opens and keep its open, high and low prices,
+/// the candles without any point are left out
+pub fn build_candles(points: &[PricePoint], from: u64, candle_interval: u64) -> Vec<Candle> {
+    let mut candles: Vec<Candle> = vec![];
+    for point in points {
+        let (point_time, open, high, low) = match &point.candle {
+            Some(candle) => (candle.open_time, candle.open, candle.high, candle.low),
+            None => (point.timestamp, point.price, point.price, point.price),
+        };
+        let open_time = from + point_time.saturating_sub(from) / candle_interval * candle_interval;
+        match candles.last_mut() {
+            Some(candle) if candle.open_time == open_time => {
+                candle.high = candle.high.max(high).max(point.price);
+                candle.low = candle.low.min(low).min(point.price);
+                candle.close = point.price;
+                candle.volume = candle.volume.zip(point.volume).map(|(a, b)| a + b);
+            }
+            _ => candles.push(Candle {
+                open_time,
+                open,
+                high: high.max(point.price),
+                low: low.min(point.price),
+                close: point.price,
+                volume: point.volume,
+            }),
+        }
+    }
+
+    candles
+}
+
+/// Split the prices into the ones whose relative deviation from the median of all prices
+/// is within `max_deviation` and the ones which deviate more than that
+pub fn reject_outliers(
+    source_prices: Vec<SourcePrice>,
+    max_deviation: f64,
+) -> (Vec<SourcePrice>, Vec<SourcePrice>) {
+    if source_prices.is_empty() {
+        return (vec![], vec![]);
+    }
+
+    let prices: Vec<f64> = source_prices.iter().map(|sp| sp.price).collect();
+    let median_price = median(&prices);
+
+    source_prices
+        .into_iter()
+        .partition(|sp| relative_deviation(sp.price, median_price) <= max_deviation)
+}
+
+/// Split the prices into the ones published within `max_age` seconds of `now`
+/// and the ones which are older, published after `now` beyond `MAX_CLOCK_SKEW` or do not have a timestamp
+pub fn reject_stale_prices(
+    source_prices: Vec<SourcePrice>,
+    max_age: u64,
+    now: u64,
+) -> (Vec<SourcePrice>, Vec<SourcePrice>) {
+    source_prices.into_iter().partition(|sp| {
+        sp.timestamp
+            .map(|timestamp| {
+                timestamp <= now.saturating_add(MAX_CLOCK_SKEW)
+                    && now.saturating_sub(timestamp) <= max_age
+            })
+            .unwrap_or(false)
+    })
+}
+
+/// Split the prices into the ones published within `tolerance` seconds of `at_timestamp`
+/// and the ones which are further away or do not have a timestamp
+pub fn reject_off_timestamp_prices(
+    source_prices: Vec<SourcePrice>,
+    at_timestamp: u64,
+    tolerance: u64,
+) -> (Vec<SourcePrice>, Vec<SourcePrice>) {
+    source_prices.into_iter().partition(|sp| {
+        sp.timestamp
+            .map(|timestamp| timestamp.abs_diff(at_timestamp) <= tolerance)
+            .unwrap_or(false)
+    })
+}
+
+/// Split the prices into the ones whose confidence interval relative to the price is within
+/// `max_confidence_ratio` and the ones which are wider, prices without a confidence interval are accepted
+pub fn reject_wide_confidence(
+    source_prices: Vec<SourcePrice>,
+    max_confidence_ratio: f64,
+) -> (Vec<SourcePrice>, Vec<SourcePrice>) {
+    source_prices.into_iter().partition(|sp| {
+        sp.confidence
+            .map(|confidence| (confidence / sp.price).abs() <= max_confidence_ratio)
+            .unwrap_or(true)
+    })
+}
+
+/// The deviation of `price` from `reference` relative to `reference`
+pub fn relative_deviation(price: f64, reference: f64) -> f64 {
+    if reference == 0.0 {
+        return if price == 0.0 { 0.0 } else { f64::INFINITY };
+    }
+
+    ((price - reference) / reference).abs()
+}
+
+fn sorted(prices: &[f64]) -> Vec<f64> {
+    let mut sorted_prices = prices.to_vec();
+    sorted_prices.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    sorted_prices
+}
+
+fn mean(prices: &[f64]) -> f64 {
+    let sum: f64 = prices.iter().sum();
+    sum / prices.len() as f64
+}
+
+fn median(prices: &[f64]) -> f64 {
+    let sorted_prices = sorted(prices);
+    let middle = sorted_prices.len() / 2;
+
+    if sorted_prices.len() % 2 == 0 {
+        (sorted_prices[middle - 1] + sorted_prices[middle]) / 2.0
+    } else {
+        sorted_prices[middle]
+    }
+}
+
+fn trimmed_mean(prices: &[f64], fraction: f64) -> f64 {
+    let sorted_prices = sorted(prices);
+    let trim_count = (sorted_prices.len() as f64 * fraction).floor() as usize;
+
+    // fall back to the median when trimming would not leave any price behind
+    if sorted_prices.len() <= trim_count * 2 {
+        return median(prices);
+    }
+
+    mean(&sorted_prices[trim_count..sorted_prices.len() - trim_count])
+}
+
+fn weighted_mean(source_prices: &[SourcePrice], weights: &[SourceWeight]) -> anyhow::Result<f64> {
+    let get_weight = |source: &String| {
+        weights
+            .iter()
+            .find(|w| w.source.eq_ignore_ascii_case(source))
+            .map(|w| w.weight)
+            .unwrap_or(1.0)
+    };
+
+    let (weighted_sum, total_weight) =
+        source_prices
+            .iter()
+            .fold((0.0, 0.0), |(weighted_sum, total_weight), sp| {
+                let weight = get_weight(&sp.source);
+                (weighted_sum + sp.price * weight, total_weight + weight)
+            });
+
+    if total_weight <= 0.0 {
+        anyhow::bail!("Total weight of the source prices must be greater than 0")
+    }
+
+    Ok(weighted_sum / total_weight)
+}
+
+fn fixed_mean(prices: &[u128]) -> Option<u128> {
+    let sum = prices
+        .iter()
+        .try_fold(0u128, |sum, price| sum.checked_add(*price))?;
+    mul_div(sum, 1, prices.len() as u128)
+}
+
+fn fixed_median(prices: &[u128]) -> Option<u128> {
+    let mut sorted_prices = prices.to_vec();
+    sorted_prices.sort();
+    let middle = sorted_prices.len() / 2;
+
+    if sorted_prices.len() % 2 == 0 {
+        let (lower, upper) = (sorted_prices[middle - 1], sorted_prices[middle]);
+        lower.checked_add(mul_div(upper - lower, 1, 2)?)
+    } else {
+        Some(sorted_prices[middle])
+    }
+}
+
+fn fixed_trimmed_mean(prices: &[u128], fraction: f64) -> Option<u128> {
+    let mut sorted_prices = prices.to_vec();
+    sorted_prices.sort();
+    let trim_count = (sorted_prices.len() as f64 * fraction).floor() as usize;
+
+    // fall back to the median when trimming would not leave any price behind
+    if sorted_prices.len() <= trim_count * 2 {
+        return fixed_median(prices);
+    }
+
+    fixed_mean(&sorted_prices[trim_count..sorted_prices.len() - trim_count])
+}
+
+fn fixed_weighted_mean(
+    source_prices: &[SourcePrice],
+    prices: &[u128],
+    weights: &[SourceWeight],
+) -> Option<u128> {
+    let get_weight = |source: &String| {
+        let weight = weights
+            .iter()
+            .find(|w| w.source.eq_ignore_ascii_case(source))
+            .map(|w| w.weight)
+            .unwrap_or(1.0);
+        (weight * WEIGHT_PRECISION).round() as u128
+    };
+
+    let mut weighted_sum: u128 = 0;
+    let mut total_weight: u128 = 0;
+    for (sp, price) in source_prices.iter().zip(prices) {
+        let weight = get_weight(&sp.source);
+        weighted_sum = weighted_sum.checked_add(price.checked_mul(weight)?)?;
+        total_weight = total_weight.checked_add(weight)?;
+    }
+
+    mul_div(weighted_sum, 1, total_weight)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::PointCandle;
+
+    fn prices(values: &[(&str, f64)]) -> Vec<SourcePrice> {
+        values
+            .iter()
+            .map(|(source, price)| SourcePrice {
+                source: source.to_string(),
+                ticker: "BTC".to_string(),
+                price: *price,
+                fixed_price: FixedPointPrice::from_decimal_str(
+                    &price.to_string(),
+                    FIXED_POINT_DECIMALS,
+                ),
+                timestamp: None,
+                confidence: None,
+                proof_hash: String::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_median_ignores_single_bad_source() {
+        let source_prices = prices(&[("pyth", 100.0), ("redstone", 101.0), ("bad", 1000.0)]);
+        let config = PricingConfig {
+            aggregation: AggregationStrategy::Median,
+            ..Default::default()
+        };
+
+        let price = aggregate_prices(&source_prices, &config).unwrap();
+        assert_eq!(price, 101.0);
+    }
+
+    #[test]
+    fn test_mean_is_the_default_strategy() {
+        let source_prices = prices(&[("pyth", 100.0), ("redstone", 101.0), ("bad", 1000.0)]);
+
+        let price = aggregate_prices(&source_prices, &PricingConfig::default()).unwrap();
+        assert_eq!(price, 400.0);
+    }
+
+    #[test]
+    fn test_max_deviation_rejects_outliers() {
+        let source_prices = prices(&[("pyth", 100.0), ("redstone", 102.0), ("bad", 150.0)]);
+
+        let (accepted, rejected) = reject_outliers(source_prices, 0.05);
+        assert_eq!(accepted.len(), 2);
+        assert_eq!(rejected[0].source, "bad");
+
+        let config = PricingConfig {
+            aggregation: AggregationStrategy::Mean,
+            ..Default::default()
+        };
+        let price = aggregate_prices(&accepted, &config).unwrap();
+        assert_eq!(price, 101.0);
+    }
+
+    #[test]
+    fn test_max_deviation_rejects_all_when_sources_disagree() {
+        let source_prices = prices(&[("pyth", 100.0), ("redstone", 200.0)]);
+
+        let (accepted, rejected) = reject_outliers(source_prices, 0.1);
+        assert!(accepted.is_empty());
+        assert_eq!(rejected.len(), 2);
+    }
+
+    #[test]
+    fn test_stale_prices_are_rejected() {
+        let mut source_prices = prices(&[("pyth", 100.0), ("redstone", 101.0), ("other", 99.0)]);
+        source_prices[0].timestamp = Some(1_000);
+        source_prices[1].timestamp = Some(900);
+
+        let (accepted, rejected) = reject_stale_prices(source_prices, 60, 1_030);
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(accepted[0].source, "pyth");
+        // prices without a timestamp cannot be proven to be fresh
+        assert_eq!(rejected.len(), 2);
+    }
+
+    #[test]
+    fn test_future_prices_are_rejected() {
+        let mut source_prices = prices(&[("pyth", 100.0), ("redstone", 101.0)]);
+        source_prices[0].timestamp = Some(1_000 + MAX_CLOCK_SKEW);
+        source_prices[1].timestamp = Some(1_000 + MAX_CLOCK_SKEW + 1);
+
+        let (accepted, rejected) = reject_stale_prices(source_prices, 60, 1_000);
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(accepted[0].source, "pyth");
+        assert_eq!(rejected[0].source, "redstone");
+    }
+
+    #[test]
+    fn test_off_timestamp_prices_are_rejected() {
+        let mut source_prices = prices(&[("pyth", 100.0), ("redstone", 101.0), ("other", 99.0)]);
+        source_prices[0].timestamp = Some(1_020);
+        source_prices[1].timestamp = Some(900);
+
+        let (accepted, rejected) = reject_off_timestamp_prices(source_prices, 1_000, 60);
+        assert_eq!(accepted.len(), 1);
+        assert_eq!(accepted[0].source, "pyth");
+        // prices without a timestamp cannot be proven to be from the time requested
+        assert_eq!(rejected.len(), 2);
+    }
+
+    #[test]
+    fn test_wide_confidence_prices_are_rejected() {
+        let mut source_prices = prices(&[("pyth", 100.0), ("other", 100.0), ("redstone", 101.0)]);
+        source_prices[0].confidence = Some(0.5);
+        source_prices[1].confidence = Some(5.0);
+
+        let (accepted, rejected) = reject_wide_confidence(source_prices, 0.01);
+        assert_eq!(accepted.len(), 2);
+        assert_eq!(rejected[0].source, "other");
+    }
+
+    #[test]
+    fn test_trimmed_mean() {
+        let source_prices = prices(&[
+            ("a", 1.0),
+            ("b", 10.0),
+            ("c", 11.0),
+            ("d", 12.0),
+            ("e", 100.0),
+        ]);
+        let config = PricingConfig {
+            aggregation: AggregationStrategy::TrimmedMean(0.2),
+            ..Default::default()
+        };
+
+        let price = aggregate_prices(&source_prices, &config).unwrap();
+        assert_eq!(price, 11.0);
+    }
+
+    #[test]
+    fn test_weighted_mean() {
+        let source_prices = prices(&[("pyth", 100.0), ("redstone", 200.0)]);
+        let config = PricingConfig {
+            aggregation: AggregationStrategy::WeightedMean(vec![SourceWeight {
+                source: "PYTH".to_string(),
+                weight: 3.0,
+            }]),
+            ..Default::default()
+        };
+
+        let price = aggregate_prices(&source_prices, &config).unwrap();
+        assert_eq!(price, 125.0);
+
+        let fixed_price = aggregate_fixed_prices(&source_prices, &config).unwrap();
+        assert_eq!(fixed_price.rescale(0).unwrap().value, 125);
+    }
+
+    #[test]
+    fn test_fixed_prices_are_exact() {
+        let source_prices = prices(&[("pyth", 0.1), ("redstone", 0.2), ("other", 0.3)]);
+        let config = PricingConfig {
+            aggregation: AggregationStrategy::Mean,
+            ..Default::default()
+        };
+
+        // the floating point mean is not exactly 0.2 while the fixed point mean is
+        let fixed_price = aggregate_fixed_prices(&source_prices, &config).unwrap();
+        assert_eq!(fixed_price.rescale(1).unwrap().value, 2);
+        assert_eq!(fixed_price.value, 2 * 10u128.pow(FIXED_POINT_DECIMALS - 1));
+
+        // no exact price can be derived if a source did not provide one
+        let mut source_prices = source_prices;
+        source_prices[0].fixed_price = None;
+        assert!(aggregate_fixed_prices(&source_prices, &config).is_none());
+    }
+
+    #[test]
+    fn test_market_data_only_provides_requested_values() {
+        let market_data =
+            |source: &str, volume: Option<f64>, change_24h: Option<f64>| SourceMarketData {
+                source: source.to_string(),
+                ticker: "BTC".to_string(),
+                volume,
+                market_cap: Some(1000.0),
+                change_24h,
+                proof_hash: String::new(),
+            };
+        let source_market_data = vec![
+            market_data("coingecko", Some(500.0), Some(2.0)),
+            market_data("binance", None, Some(3.0)),
+        ];
+        let opts = RequestOpts {
+            volume: Some(true),
+            change_24h: Some(true),
+            ..Default::default()
+        };
+
+        let aggregated = aggregate_market_data(source_market_data.clone(), &opts).unwrap();
+        assert_eq!(aggregated.volume, Some(500.0));
+        assert_eq!(aggregated.change_24h, Some(2.5));
+        assert_eq!(aggregated.market_cap, None);
+        assert_eq!(aggregated.sources.len(), 2);
+
+        // no market data is derived when none of the values requested are provided
+        let opts = RequestOpts {
+            volume: Some(true),
+            ..Default::default()
+        };
+        assert!(aggregate_market_data(source_market_data[1..].to_vec(), &opts).is_none());
+    }
+
+    fn points(values: &[(u64, f64, Option<f64>)]) -> Vec<PricePoint> {
+        values
+            .iter()
+            .map(|(timestamp, price, volume)| PricePoint {
+                timestamp: *timestamp,
+                price: *price,
+                volume: *volume,
+                candle: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_time_weighted_average() {
+        let price_points = points(&[(0, 100.0, None), (30, 200.0, None), (40, 300.0, None)]);
+
+        // 100 for 30s, 200 for 10s and 300 for 20s
+        let twap = time_weighted_average(&price_points, 60).unwrap();
+        assert!((twap - 183.333333).abs() < 1e-6);
+
+        assert_eq!(time_weighted_average(&price_points[2..], 40), Some(300.0));
+        assert_eq!(time_weighted_average(&[], 60), None);
+    }
+
+    #[test]
+    fn test_volume_weighted_average() {
+        let price_points = points(&[(0, 100.0, Some(3.0)), (30, 200.0, Some(1.0))]);
+        assert_eq!(volume_weighted_average(&price_points), Some(125.0));
+
+        // every point needs a volume
+        let price_points = points(&[(0, 100.0, Some(3.0)), (30, 200.0, None)]);
+        assert_eq!(volume_weighted_average(&price_points), None);
+    }
+
+    #[test]
+    fn test_aggregate_series_candles() {
+        let source_points = vec![
+            (
+                "redstone".to_string(),
+                points(&[
+                    (1_000, 10.0, None),
+                    (1_030, 12.0, None),
+                    (1_050, 9.0, None),
+                    (1_130, 11.0, None),
+                    // outside of the window
+                    (900, 50.0, None),
+                ]),
+            ),
+            ("binance".to_string(), points(&[(1_010, 10.5, Some(1.0))])),
+        ];
+        let kind = SeriesKind::Ohlc {
+            candle_interval: 60,
+        };
+
+        let series = aggregate_series(source_points, &kind, 1_000, 1_180).unwrap();
+        assert_eq!(series.sources, vec!["redstone".to_string()]);
+        assert_eq!(series.points, 4);
+        let SeriesValue::Ohlc(candles) = series.value else {
+            panic!("expected candles");
+        };
+        // the candle between 1060 and 1120 has no points
+        assert_eq!(candles.len(), 2);
+        assert_eq!(
+            candles[0],
+            Candle {
+                open_time: 1_000,
+                open: 10.0,
+                high: 12.0,
+                low: 9.0,
+                close: 9.0,
+                volume: None,
+            }
+        );
+        assert_eq!(candles[1].open_time, 1_120);
+
+        assert!(aggregate_series(vec![], &SeriesKind::Twap, 1_000, 1_180).is_none());
+    }
+
+    #[test]
+    fn test_build_candles_from_source_candles() {
+        let source_candle = |open_time: u64, close: f64, high: f64, low: f64| PricePoint {
+            timestamp: open_time + 60,
+            price: close,
+            volume: Some(1.0),
+            candle: Some(PointCandle {
+                open_time,
+                open: 10.0,
+                high,
+                low,
+            }),
+        };
+        // the points close at the end of each source candle but open within the first candle
+        let price_points = vec![
+            source_candle(1_000, 11.0, 15.0, 8.0),
+            source_candle(1_060, 12.0, 13.0, 9.0),
+        ];
+
+        let candles = build_candles(&price_points, 1_000, 120);
+        assert_eq!(
+            candles,
+            vec![Candle {
+                open_time: 1_000,
+                open: 10.0,
+                high: 15.0,
+                low: 8.0,
+                close: 12.0,
+                volume: Some(2.0),
+            }]
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use types::{ADCError, FeeSchedule, LedgerAccount, ProxyRequest, Request};
+
+/// Add cycles to the prepaid balance of an account
+pub fn deposit(account: &mut LedgerAccount, amount: u128) {
+    account.balance = account.balance.saturating_add(amount);
+    account.deposited = account.deposited.saturating_add(amount);
+}
+
+/// Record a fee paid with the cycles attached to a request
+pub fn record_fee(account: &mut LedgerAccount, fee: u128) {
+    account.fees_paid = account.fees_paid.saturating_add(fee);
+}
+
+/// Charge a fee from the prepaid balance of an account
+pub fn charge(account: &mut LedgerAccount, fee: u128) -> Result<(), ADCError> {
+    debit(account, fee)?;
+    record_fee(account, fee);
+    Ok(())
+}
+
+/// Credit cycles back to the prepaid balance of an account
+pub fn refund(account: &mut LedgerAccount, amount: u128) {
+    account.balance = account.balance.saturating_add(amount);
+    account.refunded = account.refunded.saturating_add(amount);
+}
+
+/// Take cycles out of the prepaid balance of an account to be sent back to its owner
+pub fn withdraw(account: &mut LedgerAccount, amount: u128) -> Result<(), ADCError> {
+    debit(account, amount)?;
+    account.withdrawn = account.withdrawn.saturating_add(amount);
+    Ok(())
+}
+
+/// Put back cycles withdrawn which could not be sent to the owner of an account
+pub fn cancel_withdrawal(account: &mut LedgerAccount, amount: u128) {
+    account.balance = account.balance.saturating_add(amount);
+    account.withdrawn = account.withdrawn.saturating_sub(amount);
+}
+
+fn debit(account: &mut LedgerAccount, amount: u128) -> Result<(), ADCError> {
+    if account.balance < amount {
+        return Err(ADCError::InsufficientBalance {
+            required: amount,
+            available: account.balance,
+        });
+    }
+
+    account.balance -= amount;
+    Ok(())
+}
+
+/// A fee schedule which charges the same fee for every request
+pub fn flat_fee_schedule(fee: u128) -> FeeSchedule {
+    FeeSchedule {
+        base: fee,
+        ..Default::default()
+    }
+}
+
+/// Get the fee of a price request, where the pairs with no sources requested
+/// are priced from all the `available_sources`
+pub fn price_request_fee(
+    fee_schedule: &FeeSchedule,
+    request: &Request,
+    available_sources: usize,
+) -> u128 {
+    request.pairs.iter().fold(fee_schedule.base, |fee, pair| {
+        let sources = request
+            .sources
+            .get(pair)
+            .map_or(available_sources, |sources| sources.len());
+        fee.saturating_add(fee_schedule.per_pair)
+            .saturating_add(fee_schedule.per_source.saturating_mul(sources as u128))
+    })
+}
+
+/// Get the fee of a URL request
+pub fn url_request_fee(fee_schedule: &FeeSchedule, proxy_request: &ProxyRequest) -> u128 {
+    let kilobytes = proxy_request.payload_size().div_ceil(1024);
+    fee_schedule
+        .base
+        .saturating_add(fee_schedule.per_kb.saturating_mul(kilobytes as u128))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
+    use types::{PairSpec, RequestOpts};
+
+    #[test]
+    fn test_ledger_account() {
+        let mut account = LedgerAccount::default();
+        deposit(&mut account, 100);
+        charge(&mut account, 30).unwrap();
+        record_fee(&mut account, 10);
+        refund(&mut account, 30);
+
+        assert_eq!(account.balance, 100);
+        assert_eq!(account.fees_paid, 40);
+        assert_eq!(account.refunded, 30);
+
+        assert_eq!(
+            withdraw(&mut account, 150),
+            Err(ADCError::InsufficientBalance {
+                required: 150,
+                available: 100
+            })
+        );
+        withdraw(&mut account, 100).unwrap();
+        assert_eq!(account.balance, 0);
+        assert_eq!(account.withdrawn, 100);
+    }
+
+    #[test]
+    fn test_fee_schedule() {
+        let fee_schedule = FeeSchedule {
+            base: 100,
+            per_pair: 10,
+            per_source: 1,
+            per_kb: 50,
+        };
+        let request = Request::from_pair_specs(
+            "1".to_string(),
+            Principal::anonymous(),
+            vec![
+                PairSpec {
+                    base: "ETH".to_string(),
+                    quote: None,
+                    sources: Some(vec!["pyth".to_string(), "coinbase".to_string()]),
+                },
+                PairSpec {
+                    base: "BTC".to_string(),
+                    quote: None,
+                    sources: None,
+                },
+            ],
+            RequestOpts::default(),
+        );
+        // both pairs plus 2 sources requested for ETH and the 6 available ones for BTC
+        assert_eq!(price_request_fee(&fee_schedule, &request, 6), 100 + 20 + 8);
+
+        let proxy_request = |body: String| {
+            ProxyRequest::new(
+                "1".to_string(),
+                "https://example.com".to_string(),
+                "GET".to_string(),
+                String::new(),
+                vec![],
+                body,
+                Principal::anonymous(),
+            )
+        };
+        assert_eq!(
+            url_request_fee(&fee_schedule, &proxy_request(String::new())),
+            150
+        );
+        assert_eq!(
+            url_request_fee(&fee_schedule, &proxy_request("x".repeat(1024))),
+            200
+        );
+        assert_eq!(
+            url_request_fee(&flat_fee_schedule(10), &proxy_request("x".repeat(1024))),
+            10
+        );
+    }
+}
@@ -1,26 +1,45 @@
 use candid::Principal;
 use core::panic;
+use ic_cdk::api::call::{msg_cycles_accept128, msg_cycles_available128};
+use ic_cdk::api::management_canister::main::{self as management_canister, CanisterIdRecord};
 use ic_cdk::{println, storage};
+use ledger::{flat_fee_schedule, price_request_fee, url_request_fee};
 use sources::request_proof_verification;
-use state::REQUEST_RESPONSE_BUFFER;
 use std::collections::HashMap;
+use std::time::Duration;
+use subscriptions::{is_update_due, record_update};
 use types::{
-    ADCResponse, ADCResponseV2, ErrorResponse, Headers, ProxyRequest, Request, RequestOpts,
-    Response, ResponseV2,
+    ADCError, ADCResponse, ADCResponseV2, AccessMode, AccessRule, CachedPrice, CurrencyPair,
+    Endpoint, ErrorResponse, FeeQuoteRequest, FeeSchedule, Headers, LedgerAccount, PairSpec,
+    PricingConfig, ProxyRequest, Quota, RateLimitConfig, Request, RequestError, RequestKind,
+    RequestOpts, RequestRecord, RequestStatus, RequestTimeoutConfig, Response, ResponseV2,
+    SourceDefinition, Subscription, BUILTIN_SOURCES, MIN_SUBSCRIPTION_HEARTBEAT,
 };
 use utils::{
-    check_gas, generate_request_url, get_currency_pair_price, send_adc_response,
-    send_adc_response_v2,
+    cache_currency_pair_price, charge_fee, check_caller_access, check_rate_limit,
+    expire_timed_out_requests, generate_request_url, get_cached_response,
+    get_currency_pair_market_data, get_currency_pair_price, get_currency_pair_series,
+    get_current_timestamp, record_request_outcome, refund_request_fee, send_adc_response,
+    send_adc_response_v2, set_currency_pair_price, use_request_quota, validate_request_opts,
+    verify_currency_pair_proofs,
 };
-use verity_ic::{owner, whitelist};
+use verity_ic::owner;
 
+pub mod access;
+pub mod aggregation;
+pub mod ledger;
+pub mod memory;
+pub mod rate_limit;
 pub mod sources;
 pub mod state;
+pub mod subscriptions;
 pub mod utils;
 
 /// use this variable to control the max number of currency pairs
 /// that can be contained in one request
 const REQUEST_CURRENCY_PAIR_LIMIT: usize = 10;
+/// the interval at which the requests not served in time are expired
+const REQUEST_EXPIRY_INTERVAL: Duration = Duration::from_secs(60);
 
 // @dev testing command
 #[ic_cdk::query]
@@ -30,21 +49,107 @@ fn name() -> String {
 
 #[ic_cdk::init]
 async fn init(verifier_canister: Option<Principal>) {
+    memory::migrate_schema();
     owner::init_owner();
     state::set_verifier_canister(verifier_canister);
-    state::set_transaction_fee(10);
+    state::set_fee_schedule(flat_fee_schedule(10));
+    start_request_expiry_timer();
 }
 
+/// set who can make requests, which is every principal by default
+#[ic_cdk::update]
+async fn set_access_mode(access_mode: AccessMode) {
+    owner::only_owner();
+    state::set_access_mode(access_mode);
+}
+
+#[ic_cdk::query]
+async fn get_access_mode() -> AccessMode {
+    state::get_access_mode()
+}
+
+/// whitelist a principal to make any request without any quota
 #[ic_cdk::update]
 async fn add_to_whitelist(principal: Principal) {
     owner::only_owner();
-    whitelist::add_to_whitelist(principal)
+    state::set_whitelist_rule(&principal, AccessRule::default());
+}
+
+/// whitelist a principal with the requests it can make, or replace the rule it is whitelisted with
+#[ic_cdk::update]
+async fn set_access_rule(principal: Principal, access_rule: AccessRule) {
+    owner::only_owner();
+    state::set_whitelist_rule(&principal, access_rule);
 }
 
 #[ic_cdk::update]
 async fn remove_from_whitelist(principal: Principal) {
     owner::only_owner();
-    whitelist::add_to_whitelist(principal)
+    state::remove_from_whitelist(&principal);
+}
+
+#[ic_cdk::query]
+/// Get the whitelisted principals along with the requests they can make
+async fn get_whitelist() -> Vec<(Principal, AccessRule)> {
+    owner::only_owner();
+    state::get_whitelist()
+}
+
+#[ic_cdk::update]
+async fn add_to_blacklist(principal: Principal) {
+    owner::only_owner();
+    state::add_to_blacklist(&principal);
+}
+
+#[ic_cdk::update]
+async fn remove_from_blacklist(principal: Principal) {
+    owner::only_owner();
+    state::remove_from_blacklist(&principal);
+}
+
+#[ic_cdk::query]
+async fn get_blacklist() -> Vec<Principal> {
+    owner::only_owner();
+    state::get_blacklist()
+}
+
+/// set the rate limit and daily quota of the principals which are not given their own,
+/// requests are not limited by default
+#[ic_cdk::update]
+async fn set_rate_limit_config(rate_limit_config: RateLimitConfig) {
+    owner::only_owner();
+    if let Err(err) = rate_limit::validate_config(&rate_limit_config) {
+        panic!("Invalid rate limit config: {}", err);
+    }
+    state::set_rate_limit_config(rate_limit_config);
+}
+
+#[ic_cdk::query]
+async fn get_rate_limit_config() -> RateLimitConfig {
+    state::get_rate_limit_config()
+}
+
+/// give a principal its own rate limit and daily quota, or remove them when not set
+#[ic_cdk::update]
+async fn set_principal_rate_limit_config(
+    principal: Principal,
+    rate_limit_config: Option<RateLimitConfig>,
+) {
+    owner::only_owner();
+    if let Some(Err(err)) = rate_limit_config.as_ref().map(rate_limit::validate_config) {
+        panic!("Invalid rate limit config: {}", err);
+    }
+    state::set_principal_rate_limit_config(&principal, rate_limit_config);
+}
+
+/// Get the rate limit and daily quota of a principal along with what is left of them
+#[ic_cdk::query]
+async fn get_quota(principal: Principal) -> Quota {
+    rate_limit::get_quota(
+        state::get_quota_usage(&principal),
+        state::get_principal_rate_limit_config(&principal),
+        get_current_timestamp(),
+    )
 }
 
 #[ic_cdk::update]
@@ -53,10 +158,62 @@ async fn set_verifier_canister(verifier_canister_principal: Principal) {
     state::set_verifier_canister(Some(verifier_canister_principal));
 }
 
+/// set the base fee of the fee schedule, charged for every request
 #[ic_cdk::update]
 async fn set_transaction_fee(transaction_fee: u128) {
     owner::only_owner();
-    state::set_transaction_fee(transaction_fee);
+    state::set_fee_schedule(FeeSchedule {
+        base: transaction_fee,
+        ..state::get_fee_schedule()
+    });
+}
+
+/// set the fees charged for a request based on its pairs and sources or on its size
+#[ic_cdk::update]
+async fn set_fee_schedule(fee_schedule: FeeSchedule) {
+    owner::only_owner();
+    state::set_fee_schedule(fee_schedule);
+}
+
+#[ic_cdk::query]
+async fn get_fee_schedule() -> FeeSchedule {
+    state::get_fee_schedule()
+}
+
+/// set the default pricing configuration used for requests which do not override it
+#[ic_cdk::update]
+async fn set_pricing_config(pricing_config: PricingConfig) {
+    owner::only_owner();
+    if let Err(err) = pricing_config.validate() {
+        panic!("Invalid pricing config: {}", err);
+    }
+    state::set_pricing_config(pricing_config);
+}
+
+#[ic_cdk::query]
+async fn get_pricing_config() -> PricingConfig {
+    state::get_pricing_config()
+}
+
+/// register a declarative pricing source, or replace the one with the same name
+#[ic_cdk::update]
+async fn register_source(source_definition: SourceDefinition) {
+    owner::only_owner();
+    if let Err(err) = source_definition.validate() {
+        panic!("Invalid source definition: {}", err);
+    }
+    state::add_source_definition(source_definition);
+}
+
+#[ic_cdk::update]
+async fn remove_source(name: String) {
+    owner::only_owner();
+    state::remove_source_definition(&name);
+}
+
+#[ic_cdk::query]
+async fn get_sources() -> Vec<SourceDefinition> {
+    state::get_source_definitions().into_values().collect()
 }
 
 #[ic_cdk::query]
@@ -64,9 +221,56 @@ async fn get_verifier_canister() -> Option<Principal> {
     state::get_verifier_canister()
 }
 
+/// Get the base fee of the fee schedule, charged for every request
 #[ic_cdk::query]
 async fn get_transaction_fee() -> u128 {
-    state::get_transaction_fee()
+    state::get_fee_schedule().base
+}
+
+/// Get the fee (in cycles) a request would be charged, which is to be attached to it
+#[ic_cdk::query]
+async fn quote_fee(request: FeeQuoteRequest) -> Result<u128, RequestError> {
+    match request {
+        FeeQuoteRequest::Price { pairs } => {
+            PairSpec::validate_all(
+                &pairs,
+                &get_available_sources(),
+                REQUEST_CURRENCY_PAIR_LIMIT,
+            )?;
+            let price_request = Request::from_pair_specs(
+                String::new(),
+                ic_cdk::caller(),
+                pairs,
+                RequestOpts::default(),
+            );
+            Ok(get_price_request_fee(&price_request))
+        }
+        FeeQuoteRequest::Url {
+            target_url,
+            method,
+            redacted,
+            headers,
+            body,
+        } => {
+            let proxy_request = ProxyRequest::new(
+                String::new(),
+                target_url,
+                method,
+                redacted,
+                headers,
+                body,
+                ic_cdk::caller(),
+            );
+            Ok(url_request_fee(&state::get_fee_schedule(), &proxy_request))
+        }
+    }
+}
+
+/// Get the last verified price of a currency pair e.g "ETH/BTC", if its latest price has been requested before
+#[ic_cdk::query]
+async fn get_latest_price(pair: String) -> Option<CachedPrice> {
+    let currency_pair = CurrencyPair::try_from(pair.trim().to_string()).ok()?;
+    state::get_cached_price(&currency_pair.repr)
 }
 
 /// requests prices from the orchestrator
@@ -81,20 +285,9 @@ async fn request_data(currency_pairs: String, opts: RequestOpts) -> String {
     );
     let caller_principal = ic_cdk::caller();
 
-    // derive the request id
-    let request_id = generate_request_url().await;
-
-    check_gas().await;
-
-    // if !whitelist::is_whitelisted(caller_principal) {
-    //     panic!(
-    //         "canister with principal:{} is not allowed to call this method",
-    //         caller_principal
-    //     );
-    // }
-    // creates a price request object with an arb id
+    // creates a price request object, whose id is set once it is derived
     // include the caller canister's id to let adc know where to send a response to
-    let price_request = Request::new(request_id.clone(), caller_principal, currency_pairs, opts);
+    let mut price_request = Request::new(String::new(), caller_principal, currency_pairs, opts);
 
     // validate that this request for data contains a maximum of 10 pairs
     if price_request.pairs.len() > REQUEST_CURRENCY_PAIR_LIMIT {
@@ -103,14 +296,126 @@ async fn request_data(currency_pairs: String, opts: RequestOpts) -> String {
             REQUEST_CURRENCY_PAIR_LIMIT
         );
     };
-    let price_request_stringified = serde_json::to_string(&price_request).unwrap();
+    if let Err(err) = validate_request_opts(&price_request.opts, &price_request.pairs) {
+        ic_cdk::api::trap(&err.to_string());
+    }
+
+    // derive the request id
+    let request_id = generate_request_url().await;
+    price_request.id = request_id.clone();
+
+    // the access is checked right before its quota is used, without awaiting in between,
+    // so that concurrent requests cannot all pass the check of the last request of a quota
+    if let Err(err) = check_caller_access(&caller_principal, &Endpoint::Price) {
+        ic_cdk::api::trap(&err.to_string());
+    }
+    let quota_usage = match check_rate_limit(&caller_principal) {
+        Ok(quota_usage) => quota_usage,
+        Err(err) => ic_cdk::api::trap(&err.to_string()),
+    };
+    // the request is answered from the cache when every pair has a price fresh enough
+    let cached_response = get_cached_response(&price_request);
+    let fee_paid = match charge_fee(caller_principal, get_price_request_fee(&price_request)) {
+        Ok(fee_paid) => fee_paid,
+        Err(err) => ic_cdk::api::trap(&err.to_string()),
+    };
+    use_request_quota(&caller_principal);
+    state::set_quota_usage(&caller_principal, quota_usage);
+
+    // the cached response is sent without waiting for the orchestrator,
+    // but only once the request id is returned so that the caller knows it when the response arrives
+    if let Some(cached_response) = cached_response {
+        let mut request_record = new_request_record(&price_request, fee_paid);
+        request_record.status = RequestStatus::Fulfilled;
+        request_record.completed_at = Some(request_record.created_at);
+        state::set_request_record(request_record);
+
+        ic_cdk_timers::set_timer(Duration::ZERO, move || {
+            // the logs are read by the orchestrator so an undelivered notification is dropped like on expiry
+            let _ = send_adc_response(caller_principal, Ok(cached_response));
+        });
+        return request_id;
+    }
+    submit_price_request(&price_request, fee_paid);
+
+    return request_id;
+}
+
+/// requests prices from the orchestrator for a list of structured pairs
+/// each pair is validated before the request is made and if any of them is invalid
+/// the request is rejected with the list of rejected pairs, no fee is charged for a rejected request
+#[ic_cdk::update]
+async fn request_data_v2(pairs: Vec<PairSpec>, opts: RequestOpts) -> Result<String, RequestError> {
+    assert!(
+        state::get_verifier_canister().is_some(),
+        "VERIFIER_CANISTER_NOT_SET"
+    );
+    let caller_principal = ic_cdk::caller();
+
+    PairSpec::validate_all(
+        &pairs,
+        &get_available_sources(),
+        REQUEST_CURRENCY_PAIR_LIMIT,
+    )?;
+    let mut price_request = Request::from_pair_specs(String::new(), caller_principal, pairs, opts);
+    validate_request_opts(&price_request.opts, &price_request.pairs)
+        .map_err(|err| RequestError::InvalidOpts(err.to_string()))?;
+
+    // derive the request id
+    let request_id = generate_request_url().await;
+    price_request.id = request_id.clone();
+    // the access is checked right before its quota is used, without awaiting in between,
+    // so that concurrent requests cannot all pass the check of the last request of a quota
+    check_caller_access(&caller_principal, &Endpoint::Price).map_err(RequestError::Rejected)?;
+    let quota_usage = check_rate_limit(&caller_principal).map_err(RequestError::Rejected)?;
+    let fee_paid = charge_fee(caller_principal, get_price_request_fee(&price_request))
+        .map_err(RequestError::Rejected)?;
+    use_request_quota(&caller_principal);
+    state::set_quota_usage(&caller_principal, quota_usage);
+    submit_price_request(&price_request, fee_paid);
+
+    Ok(request_id)
+}
+
+/// Get the names of the sources prices can be requested from
+fn get_available_sources() -> Vec<String> {
+    BUILTIN_SOURCES
+        .iter()
+        .map(|source| source.to_string())
+        .chain(state::get_source_definitions().into_keys())
+        .collect()
+}
+
+/// Get the fee of a price request from the fee schedule
+fn get_price_request_fee(price_request: &Request) -> u128 {
+    price_request_fee(
+        &state::get_fee_schedule(),
+        price_request,
+        get_available_sources().len(),
+    )
+}
+
+/// Log a price request to be picked up by the orchestrator and record it as pending
+fn submit_price_request(price_request: &Request, fee_paid: u128) {
+    let price_request_stringified = serde_json::to_string(price_request).unwrap();
 
     // log the price request to be picked up by the orchestrator
     println!("{}", price_request_stringified);
 
-    REQUEST_RESPONSE_BUFFER.with(|rc| rc.borrow_mut().insert(request_id.clone(), true));
+    state::add_pending_request(new_request_record(price_request, fee_paid));
+}
 
-    return request_id;
+/// Create the record of a price request which was charged `fee_paid`
+fn new_request_record(price_request: &Request, fee_paid: u128) -> RequestRecord {
+    RequestRecord::new(
+        price_request.id.clone(),
+        price_request.owner,
+        RequestKind::Price {
+            pairs: price_request.pairs.clone(),
+        },
+        get_current_timestamp(),
+        fee_paid,
+    )
 }
 
 /// This function allows you to request data from any target URL.
@@ -151,7 +456,6 @@ async fn request_data_url(
 ) -> String {
     // derive the request id
     let request_id = generate_request_url().await;
-    check_gas().await;
 
     let proxy_request = ProxyRequest::new(
         request_id.clone(),
@@ -162,13 +466,38 @@ async fn request_data_url(
         body,
         ic_cdk::caller(),
     );
+    // the access is checked right before its quota is used, without awaiting in between,
+    // so that concurrent requests cannot all pass the check of the last request of a quota
+    if let Err(err) = check_caller_access(&ic_cdk::caller(), &Endpoint::Url) {
+        ic_cdk::api::trap(&err.to_string());
+    }
+    let quota_usage = match check_rate_limit(&ic_cdk::caller()) {
+        Ok(quota_usage) => quota_usage,
+        Err(err) => ic_cdk::api::trap(&err.to_string()),
+    };
+    let fee = url_request_fee(&state::get_fee_schedule(), &proxy_request);
+    let fee_paid = match charge_fee(ic_cdk::caller(), fee) {
+        Ok(fee_paid) => fee_paid,
+        Err(err) => ic_cdk::api::trap(&err.to_string()),
+    };
+    use_request_quota(&ic_cdk::caller());
+    state::set_quota_usage(&ic_cdk::caller(), quota_usage);
 
     let price_request_stringified = serde_json::to_string(&proxy_request).unwrap();
 
     // log the price request to be picked up by the orchestrator
     println!("{}", price_request_stringified);
 
-    REQUEST_RESPONSE_BUFFER.with(|rc| rc.borrow_mut().insert(request_id.clone(), true));
+    state::add_pending_request(RequestRecord::new(
+        request_id.clone(),
+        proxy_request.owner,
+        RequestKind::Url {
+            target_url: proxy_request.target_url,
+            method: proxy_request.method,
+        },
+        get_current_timestamp(),
+        fee_paid,
+    ));
 
     return request_id;
 }
@@ -190,42 +519,98 @@ async fn receive_orchestrator_response(response: ADCResponse, notary_pubkey: Str
     };
 
     // validate that id is present in buffer
-    if !REQUEST_RESPONSE_BUFFER.with(|rc| rc.borrow().contains_key(&id)) {
+    if !state::is_pending_request(&id) {
         panic!("invalid response")
     }
     // remove ID from buffer
-    REQUEST_RESPONSE_BUFFER.with(|rc| rc.borrow_mut().remove(&id));
+    state::remove_pending_request(&id);
 
-    // if we get an error response then return that
+    // if we get an error response then return that, and credit the fee back as the request was not served
     if response.is_err() {
+        record_request_outcome(&id, &response);
+        refund_request_fee(&id);
         send_adc_response(response_owner, response).unwrap();
         return;
     }
 
     // otherwise get the request and process it
-    let mut response = response.unwrap();
+    let adc_response = process_response(response.unwrap(), &notary_pubkey).await;
+
+    record_request_outcome(&id, &adc_response);
+    // the proofs could not be verified so the request was not served either
+    if adc_response.is_err() {
+        refund_request_fee(&id);
+    }
+    send_adc_response(response_owner, adc_response).unwrap();
+}
+
+/// Verify the proofs attached to a response and derive the data requested for each of its currency pairs,
+/// an error response is returned if the proofs cannot be verified
+async fn process_response(mut response: Response, notary_pubkey: &String) -> ADCResponse {
+    // the options provided with the request take precedence over the default pricing config
+    let pricing_config = state::get_pricing_config().with_overrides(&response.opts);
 
     // iterate through each of the currency pairs and then get the price consensus
     // or errors (if any), and attach it to the object
     // and return the response to the calling canister
+    // the proofs of all the pairs are verified together so that shared proofs are only verified once
+    let verified_proofs = match verify_currency_pair_proofs(&response.pairs, notary_pubkey).await {
+        Ok(verified_proofs) => verified_proofs,
+        Err(err) => return Err(ErrorResponse::new(response.id, response.owner, err)),
+    };
     let mut processed_pairs = vec![];
     for mut currency_pair in response.pairs.clone() {
-        // only get the price of a particular pair if it does not have any existing errors
-        if currency_pair.error.is_none() {
-            let pair_price = get_currency_pair_price(&currency_pair, &notary_pubkey).await;
+        // only get the price of a particular pair if it is requested and does not have any existing errors
+        if response.opts.price && currency_pair.error.is_none() {
+            let pair_price = get_currency_pair_price(
+                &mut currency_pair,
+                &verified_proofs,
+                &pricing_config,
+                response.opts.at_timestamp,
+            );
             match pair_price {
-                Ok(price) => {
-                    currency_pair.price = Some(price);
+                Ok(price) => set_currency_pair_price(&mut currency_pair, &price, &pricing_config),
+                Err(err) => currency_pair.error = Some(err),
+            }
+            cache_currency_pair_price(&currency_pair, &response.opts);
+        }
+        // the market data is provided independently of the price,
+        // its failure is only reported as the error of the pair when the price is not requested
+        if response.opts.wants_market_data() {
+            let market_data =
+                get_currency_pair_market_data(&mut currency_pair, &verified_proofs, &response.opts);
+            match market_data {
+                Ok(market_data) => currency_pair.market_data = Some(market_data),
+                Err(err) if !response.opts.price => currency_pair.error = Some(err),
+                Err(_) => {}
+            }
+        }
+        // the series ends at the time priced or at the end of the window its proofs were fetched over,
+        // which can not be later than the time of the processor,
+        // and its failure is only reported as the error of the pair when nothing else is requested
+        if let Some(series_opts) = &response.opts.series {
+            let now = get_current_timestamp();
+            let to = response
+                .opts
+                .at_timestamp
+                .or(currency_pair.series_to)
+                .map_or(now, |to| to.min(now));
+            let series =
+                get_currency_pair_series(&mut currency_pair, &verified_proofs, series_opts, to);
+            match series {
+                Ok(series) => currency_pair.series = Some(series),
+                Err(err) if !response.opts.price && !response.opts.wants_market_data() => {
+                    currency_pair.error = Some(err)
                 }
-                Err(err) => currency_pair.error = Some(err.to_string()),
+                Err(_) => {}
             }
         }
         processed_pairs.push(currency_pair);
     }
 
-    response.pairs = processed_pairs.clone();
+    response.pairs = processed_pairs;
 
-    send_adc_response(response_owner, Ok(response)).unwrap();
+    Ok(response)
 }
 
 #[ic_cdk::update]
@@ -245,14 +630,16 @@ async fn receive_orchestrator_data(response: ADCResponseV2, notary_pubkey: Strin
     };
 
     // validate that id is present in buffer
-    if !REQUEST_RESPONSE_BUFFER.with(|rc| rc.borrow().contains_key(&id)) {
+    if !state::is_pending_request(&id) {
         panic!("invalid response")
     }
     // remove ID from buffer
-    REQUEST_RESPONSE_BUFFER.with(|rc| rc.borrow_mut().remove(&id));
+    state::remove_pending_request(&id);
 
-    // if we get an error response then return that
+    // if we get an error response then return that, and credit the fee back as the request was not served
     if response.is_err() {
+        record_request_outcome(&id, &response);
+        refund_request_fee(&id);
         send_adc_response_v2(response_owner, response).unwrap();
         return;
     }
@@ -264,51 +651,305 @@ async fn receive_orchestrator_data(response: ADCResponseV2, notary_pubkey: Strin
     // or errors (if any), and attach it to the object
     // and return the response to the calling canister
 
-    let contents: Vec<String> =
-        request_proof_verification(&response.proof_requests, &notary_pubkey)
-            .await
-            .iter()
-            .map(|proof| proof.get_content().to_string())
-            .collect();
+    let verification_response_proofs =
+        match request_proof_verification(&response.proof_requests, &notary_pubkey).await {
+            Ok(verification_response_proofs) => verification_response_proofs,
+            Err(err) => {
+                let error_response = Err(ErrorResponse::new(id.clone(), response_owner, err));
+                record_request_outcome(&id, &error_response);
+                // the proofs could not be verified so the request was not served either
+                refund_request_fee(&id);
+                send_adc_response_v2(response_owner, error_response).unwrap();
+                return;
+            }
+        };
+    let contents: Vec<String> = verification_response_proofs
+        .iter()
+        .map(|proof| proof.get_content().to_string())
+        .collect();
 
     response.contents = contents;
-    send_adc_response_v2(response_owner, Ok(response)).unwrap();
+    let adc_response = Ok(response);
+    record_request_outcome(&id, &adc_response);
+    send_adc_response_v2(response_owner, adc_response).unwrap();
+}
+
+/// subscribes the caller to the prices of a list of structured pairs, which are pushed to it through `receive_adc_response`
+/// every `heartbeat` seconds or as soon as a price deviates from the last one pushed by `deviation_threshold`
+/// all the cycles attached are kept as the balance of the subscription, each update is charged the fee of a price request for its pairs
+#[ic_cdk::update]
+async fn subscribe(
+    pairs: Vec<PairSpec>,
+    heartbeat: u64,
+    deviation_threshold: Option<f64>,
+) -> Result<String, RequestError> {
+    assert!(
+        state::get_verifier_canister().is_some(),
+        "VERIFIER_CANISTER_NOT_SET"
+    );
+    let caller_principal = ic_cdk::caller();
+
+    if heartbeat < MIN_SUBSCRIPTION_HEARTBEAT {
+        return Err(RequestError::InvalidOpts(format!(
+            "heartbeat must be at least {}s",
+            MIN_SUBSCRIPTION_HEARTBEAT
+        )));
+    }
+    if let Some(threshold) = deviation_threshold {
+        if !threshold.is_finite() || threshold <= 0.0 {
+            return Err(RequestError::InvalidOpts(
+                "deviation_threshold must be a positive number".to_string(),
+            ));
+        }
+    }
+    PairSpec::validate_all(
+        &pairs,
+        &get_available_sources(),
+        REQUEST_CURRENCY_PAIR_LIMIT,
+    )?;
+
+    // derive the subscription id
+    let subscription_id = generate_request_url().await;
+    // the access is checked right before its quota is used, without awaiting in between,
+    // so that concurrent requests cannot all pass the check of the last request of a quota
+    check_caller_access(&caller_principal, &Endpoint::Price).map_err(RequestError::Rejected)?;
+    let quota_usage = check_rate_limit(&caller_principal).map_err(RequestError::Rejected)?;
+
+    let mut subscription = Subscription {
+        id: subscription_id.clone(),
+        owner: caller_principal,
+        pairs,
+        heartbeat,
+        deviation_threshold,
+        balance: 0,
+        last_prices: HashMap::new(),
+        last_update: None,
+    };
+
+    // the balance must cover at least the first update
+    let fee = get_subscription_update_fee(&subscription);
+    let available_cycles = msg_cycles_available128();
+    if available_cycles < fee {
+        return Err(RequestError::Rejected(ADCError::FeeTooLow {
+            required: fee,
+            provided: available_cycles,
+        }));
+    }
+    subscription.balance = msg_cycles_accept128(available_cycles);
+    state::set_subscription(subscription);
+    use_request_quota(&caller_principal);
+    state::set_quota_usage(&caller_principal, quota_usage);
+
+    Ok(subscription_id)
+}
+
+/// adds the cycles attached to the balance of a subscription of the caller and returns the new balance
+#[ic_cdk::update]
+async fn top_up_subscription(id: String) -> Result<u128, RequestError> {
+    let mut subscription = get_caller_subscription(&id)?;
+
+    subscription.balance += msg_cycles_accept128(msg_cycles_available128());
+    let balance = subscription.balance;
+    state::set_subscription(subscription);
+
+    Ok(balance)
+}
+
+/// cancels a subscription of the caller and credits the cycles left in its balance to the prepaid balance of the caller,
+/// returns the cycles refunded
+#[ic_cdk::update]
+async fn unsubscribe(id: String) -> Result<u128, RequestError> {
+    let subscription = get_caller_subscription(&id)?;
+    state::remove_subscription(&id);
+
+    let mut account = state::get_ledger_account(&subscription.owner);
+    ledger::refund(&mut account, subscription.balance);
+    state::set_ledger_account(&subscription.owner, account);
+
+    Ok(subscription.balance)
+}
+
+/// Get the fee each update of a subscription is charged
+fn get_subscription_update_fee(subscription: &Subscription) -> u128 {
+    get_price_request_fee(&subscription.to_request())
+}
+
+/// Get a subscription owned by the caller
+fn get_caller_subscription(id: &str) -> Result<Subscription, RequestError> {
+    state::get_subscription(id)
+        .filter(|subscription| subscription.owner == ic_cdk::caller())
+        .ok_or_else(|| RequestError::UnknownSubscription(id.to_string()))
+}
+
+#[ic_cdk::query]
+/// Get the subscriptions of the caller
+async fn get_subscriptions() -> Vec<Subscription> {
+    let caller_principal = ic_cdk::caller();
+    state::get_subscriptions()
+        .into_values()
+        .filter(|subscription| subscription.owner == caller_principal)
+        .collect()
+}
+
+#[ic_cdk::query]
+/// Get the subscriptions whose balance covers another update, for the orchestrator to refresh
+async fn get_active_subscriptions() -> Vec<Subscription> {
+    owner::only_owner();
+    state::get_subscriptions()
+        .into_values()
+        .filter(|subscription| subscription.balance >= get_subscription_update_fee(subscription))
+        .collect()
+}
+
+#[ic_cdk::update]
+/// this function is going to be called by the orchestrator which would be authenticated with the 'owner' keys
+/// it would receive the refreshed prices of a subscription and push them to the subscriber
+/// if its heartbeat has elapsed or any of its prices deviates enough, charging the subscription for the update
+async fn receive_subscription_update(response: ADCResponse, notary_pubkey: String) {
+    assert!(
+        state::get_verifier_canister().is_some(),
+        "VERIFIER_CANISTER_NOT_SET"
+    );
+    // only owner(orchestrator) can call
+    owner::only_owner();
+
+    // prices which could not be fetched are not pushed, they are refreshed again on the next run
+    let Ok(response) = response else {
+        return;
+    };
+    if !state::get_subscription(&response.id).is_some_and(|subscription| {
+        subscription.balance >= get_subscription_update_fee(&subscription)
+    }) {
+        return;
+    }
+    let Ok(response) = process_response(response, &notary_pubkey).await else {
+        return;
+    };
+
+    // the subscription may have been updated or cancelled while the proofs were being verified
+    let Some(mut subscription) = state::get_subscription(&response.id) else {
+        return;
+    };
+    let now = get_current_timestamp();
+    let fee = get_subscription_update_fee(&subscription);
+    if subscription.balance < fee || !is_update_due(&subscription, &response.pairs, now) {
+        return;
+    }
+    subscription.balance -= fee;
+    record_update(&mut subscription, &response.pairs, now);
+    let subscriber = subscription.owner;
+    state::set_subscription(subscription);
+
+    let mut account = state::get_ledger_account(&subscriber);
+    ledger::record_fee(&mut account, fee);
+    state::set_ledger_account(&subscriber, account);
+
+    send_adc_response(subscriber, Ok(response)).unwrap();
+}
+
+/// adds the cycles attached to the prepaid balance of the caller, which the fees of its requests are charged from
+/// when not enough cycles are attached to them, returns the new balance
+#[ic_cdk::update]
+async fn deposit_cycles() -> u128 {
+    let caller_principal = ic_cdk::caller();
+    let mut account = state::get_ledger_account(&caller_principal);
+    ledger::deposit(
+        &mut account,
+        msg_cycles_accept128(msg_cycles_available128()),
+    );
+    let balance = account.balance;
+    state::set_ledger_account(&caller_principal, account);
+
+    balance
+}
+
+#[ic_cdk::query]
+/// Get the prepaid balance of the caller
+async fn get_balance() -> u128 {
+    state::get_ledger_account(&ic_cdk::caller()).balance
+}
+
+#[ic_cdk::query]
+/// Get the prepaid balance of the caller along with the fees it paid and the refunds it was credited
+async fn get_ledger_account() -> LedgerAccount {
+    state::get_ledger_account(&ic_cdk::caller())
+}
+
+/// sends `amount` cycles from the prepaid balance of the caller back to it, returns the balance left
+#[ic_cdk::update]
+async fn withdraw(amount: u128) -> Result<u128, RequestError> {
+    let caller_principal = ic_cdk::caller();
+    let mut account = state::get_ledger_account(&caller_principal);
+    ledger::withdraw(&mut account, amount).map_err(RequestError::Rejected)?;
+    let balance = account.balance;
+    // the balance is debited before the transfer so that it cannot be withdrawn twice meanwhile
+    state::set_ledger_account(&caller_principal, account);
+
+    let canister_id = CanisterIdRecord {
+        canister_id: caller_principal,
+    };
+    if let Err((_, message)) = management_canister::deposit_cycles(canister_id, amount).await {
+        let mut account = state::get_ledger_account(&caller_principal);
+        ledger::cancel_withdrawal(&mut account, amount);
+        state::set_ledger_account(&caller_principal, account);
+        return Err(RequestError::Rejected(ADCError::Other(format!(
+            "failed to send the cycles withdrawn: {}",
+            message
+        ))));
+    }
+
+    Ok(balance)
+}
+
+#[ic_cdk::query]
+/// Get the record of a request made by the caller, whether it is still pending or completed
+/// the records of completed requests are only kept for `REQUEST_RETENTION` seconds
+async fn get_request_status(id: String) -> Option<RequestRecord> {
+    state::get_request_record(&id).filter(|request_record| request_record.owner == ic_cdk::caller())
+}
+
+/// set how long requests can wait for the orchestrator and whether their fee is refunded when they expire
+#[ic_cdk::update]
+async fn set_request_timeout_config(request_timeout_config: RequestTimeoutConfig) {
+    owner::only_owner();
+    if let Err(err) = request_timeout_config.validate() {
+        panic!("Invalid request timeout config: {}", err);
+    }
+    state::set_request_timeout_config(request_timeout_config);
+}
+
+#[ic_cdk::query]
+async fn get_request_timeout_config() -> RequestTimeoutConfig {
+    state::get_request_timeout_config()
+}
+
+/// Periodically expire the requests the orchestrator did not serve in time,
+/// the timers do not survive upgrades so this is called on init and after every upgrade
+fn start_request_expiry_timer() {
+    ic_cdk_timers::set_timer_interval(REQUEST_EXPIRY_INTERVAL, expire_timed_out_requests);
 }
 
 #[ic_cdk::query]
 /// Check if this canister is whitelisted
 async fn is_canister_whitelisted(principal: Principal) -> bool {
     owner::only_owner();
-    whitelist::is_whitelisted(principal)
+    state::get_whitelist_rule(&principal).is_some()
 }
 
 // --------------------------- upgrade hooks ------------------------- //
-#[ic_cdk::pre_upgrade]
-/// backup state variables from canister
-fn pre_upgrade() {
-    let cloned_buffer = state::get_buffer();
-    let cloned_verifier = state::get_verifier_canister();
-    let cloned_whitelist = whitelist::WHITE_LIST.with(|rc| rc.borrow().clone());
-    let cloned_fee = state::get_transaction_fee();
-
-    storage::stable_save((cloned_buffer, cloned_whitelist, cloned_verifier, cloned_fee)).unwrap()
-}
 #[ic_cdk::post_upgrade]
-/// restore state variables from backup
+/// the state is kept in stable memory across upgrades and only needs to be migrated to the current schema,
+/// the state serialized by the versions before stable structures is restored once
 async fn post_upgrade() {
-    let (cached_buffer, cached_whitelist, cached_verifier, cached_fee): (
-        HashMap<String, bool>,
-        HashMap<Principal, bool>,
-        Option<Principal>,
-        u128,
-    ) = storage::stable_restore().unwrap();
+    if memory::has_legacy_state() {
+        let legacy_state: state::LegacyState =
+            storage::stable_restore().expect("failed to restore the legacy state");
+        state::migrate_legacy_state(legacy_state, ic_cdk::api::time());
+    }
+    memory::migrate_schema();
 
     owner::init_owner();
-    whitelist::WHITE_LIST.with(|store| *store.borrow_mut() = cached_whitelist);
-
-    state::set_buffer(cached_buffer);
-    state::set_verifier_canister(cached_verifier);
-    state::set_transaction_fee(cached_fee);
+    start_request_expiry_timer();
 }
 // --------------------------- upgrade hooks ------------------------- //
 
@@ -320,9 +961,12 @@ mod tests {
     fn test_whitelist() {
         let dummy_principal = Principal::anonymous();
 
-        whitelist::add_to_whitelist(dummy_principal);
+        state::set_whitelist_rule(&dummy_principal, AccessRule::default());
+
+        let is_whitelisted = state::get_whitelist_rule(&dummy_principal).is_some();
+        assert_eq!(is_whitelisted, true);
 
-        let is_whitelisted = whitelist::is_whitelisted(dummy_principal);
-        assert_eq!(is_whitelisted, true)
+        state::remove_from_whitelist(&dummy_principal);
+        assert!(state::get_whitelist_rule(&dummy_principal).is_none());
     }
 }
@@ -0,0 +1,109 @@
+use candid::{CandidType, Deserialize};
+use ic_cdk::api::stable::{stable_read, stable_size};
+use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{DefaultMemoryImpl, StableCell, Storable};
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+pub type Memory = VirtualMemory<DefaultMemoryImpl>;
+
+/// The version of the layout of the state kept in stable memory,
+/// to be increased with a migration in `migrate_schema` whenever a change cannot be decoded from the previous layout
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// The magic bytes the memory manager starts the stable memory with
+const MEMORY_MANAGER_MAGIC: &[u8; 3] = b"MGR";
+
+pub const SCHEMA_VERSION_MEMORY_ID: MemoryId = MemoryId::new(0);
+pub const REQUEST_RESPONSE_BUFFER_MEMORY_ID: MemoryId = MemoryId::new(1);
+pub const VERIFIER_CANISTER_MEMORY_ID: MemoryId = MemoryId::new(2);
+pub const FEE_MEMORY_ID: MemoryId = MemoryId::new(3);
+pub const PRICING_CONFIG_MEMORY_ID: MemoryId = MemoryId::new(4);
+pub const SOURCE_DEFINITIONS_MEMORY_ID: MemoryId = MemoryId::new(5);
+pub const PRICE_CACHE_MEMORY_ID: MemoryId = MemoryId::new(6);
+pub const SUBSCRIPTIONS_MEMORY_ID: MemoryId = MemoryId::new(7);
+pub const WHITELIST_MEMORY_ID: MemoryId = MemoryId::new(8);
+pub const REQUESTS_MEMORY_ID: MemoryId = MemoryId::new(9);
+pub const REQUEST_TIMEOUT_CONFIG_MEMORY_ID: MemoryId = MemoryId::new(10);
+pub const LEDGER_MEMORY_ID: MemoryId = MemoryId::new(11);
+pub const FEE_SCHEDULE_MEMORY_ID: MemoryId = MemoryId::new(12);
+pub const ACCESS_MODE_MEMORY_ID: MemoryId = MemoryId::new(13);
+pub const ACCESS_WHITELIST_MEMORY_ID: MemoryId = MemoryId::new(14);
+pub const ACCESS_BLACKLIST_MEMORY_ID: MemoryId = MemoryId::new(15);
+pub const RATE_LIMIT_CONFIG_MEMORY_ID: MemoryId = MemoryId::new(16);
+pub const PRINCIPAL_RATE_LIMIT_CONFIGS_MEMORY_ID: MemoryId = MemoryId::new(17);
+pub const QUOTA_USAGE_MEMORY_ID: MemoryId = MemoryId::new(18);
+
+thread_local! {
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
+        RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+    static STORED_SCHEMA_VERSION: RefCell<StableCell<u32, Memory>> = RefCell::new(
+        StableCell::init(get_memory(SCHEMA_VERSION_MEMORY_ID), 0)
+            .expect("failed to initialize the schema version"),
+    );
+}
+
+/// Get the region of stable memory a state variable is kept in
+pub fn get_memory(id: MemoryId) -> Memory {
+    MEMORY_MANAGER.with(|mm| mm.borrow().get(id))
+}
+
+/// A value kept in stable memory in its candid encoding,
+/// so that the `Option` fields added to its type are decoded as `None` from the values stored before
+#[derive(Clone, Debug, Default)]
+pub struct Candid<T>(pub T);
+
+impl<T> Storable for Candid<T>
+where
+    T: CandidType + for<'de> Deserialize<'de>,
+{
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(&self.0).expect("failed to encode a stable value"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Self(candid::decode_one(&bytes).expect("failed to decode a stable value"))
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+/// Check if the stable memory holds the state serialized with `stable_save` by the versions
+/// before the state was kept in stable structures, it must be restored before any state variable is accessed
+/// as the memory manager takes over the stable memory when it is initialized
+pub fn has_legacy_state() -> bool {
+    if stable_size() == 0 {
+        return false;
+    }
+
+    let mut magic = [0u8; 3];
+    stable_read(0, &mut magic);
+    &magic != MEMORY_MANAGER_MAGIC
+}
+
+/// Bring the state kept in stable memory up to the current schema version,
+/// a canister which was just created or restored from its legacy state is already at the current version
+pub fn migrate_schema() {
+    let stored_version = STORED_SCHEMA_VERSION.with(|sv| *sv.borrow().get());
+    if stored_version > SCHEMA_VERSION {
+        ic_cdk::trap(&format!(
+            "stable memory schema version {} is newer than the version {} supported",
+            stored_version, SCHEMA_VERSION
+        ));
+    }
+    if stored_version == 1 {
+        // version 2 replaced the flat transaction fee with a fee schedule
+        crate::state::migrate_flat_fee();
+    }
+    if matches!(stored_version, 1 | 2) {
+        // version 3 replaced the copy of the whitelist of `verity_ic` with the access lists
+        crate::state::migrate_whitelist();
+    }
+
+    STORED_SCHEMA_VERSION.with(|sv| {
+        sv.borrow_mut()
+            .set(SCHEMA_VERSION)
+            .expect("failed to update the schema version")
+    });
+}
@@ -0,0 +1,169 @@
+use types::{ADCError, Quota, QuotaUsage, RateLimitConfig};
+
+/// The length (in seconds) of the period the daily quotas apply to
+pub const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Check that a rate limit config lets requests be made,
+/// principals are denied any request through the access lists instead
+pub fn validate_config(config: &RateLimitConfig) -> anyhow::Result<()> {
+    if let Some(rate_limit) = &config.rate_limit {
+        if rate_limit.capacity == 0 {
+            anyhow::bail!("capacity must be at least 1")
+        }
+        if rate_limit.refill_interval == 0 {
+            anyhow::bail!("refill_interval must be at least 1s")
+        }
+    }
+    if config.daily_quota == Some(0) {
+        anyhow::bail!("daily_quota must be at least 1")
+    }
+
+    Ok(())
+}
+
+/// Refill the tokens of a principal for the time elapsed, up to the capacity of its rate limit,
+/// and start the count of its requests over on a new day
+pub fn refresh_usage(usage: &mut QuotaUsage, config: &RateLimitConfig, now: u64) {
+    if let Some(rate_limit) = &config.rate_limit {
+        let refills = now.saturating_sub(usage.refilled_at) / rate_limit.refill_interval;
+        let tokens = (usage.tokens as u64).saturating_add(refills);
+        if tokens >= rate_limit.capacity as u64 {
+            usage.tokens = rate_limit.capacity;
+            usage.refilled_at = now;
+        } else {
+            usage.tokens = tokens as u32;
+            // the time elapsed since the last refill counts towards the next one
+            usage.refilled_at += refills * rate_limit.refill_interval;
+        }
+    }
+
+    let day = now / SECONDS_PER_DAY;
+    if usage.day != day {
+        usage.day = day;
+        usage.requests_today = 0;
+    }
+}
+
+/// Count a request against the rate limit and the daily quota of a principal,
+/// the request is rejected when the principal is rate limited or has used up its quota for the day
+pub fn count_request(
+    usage: &mut QuotaUsage,
+    config: &RateLimitConfig,
+    now: u64,
+) -> Result<(), ADCError> {
+    refresh_usage(usage, config, now);
+
+    if let Some(daily_quota) = config.daily_quota {
+        if usage.requests_today >= daily_quota {
+            return Err(ADCError::QuotaExceeded {
+                quota: daily_quota,
+                resets_at: (usage.day + 1) * SECONDS_PER_DAY,
+            });
+        }
+    }
+    if let Some(rate_limit) = &config.rate_limit {
+        if usage.tokens == 0 {
+            return Err(ADCError::RateLimited {
+                retry_after: (usage.refilled_at + rate_limit.refill_interval).saturating_sub(now),
+            });
+        }
+        usage.tokens -= 1;
+    }
+    usage.requests_today += 1;
+
+    Ok(())
+}
+
+/// Get what is left of the rate limit and the daily quota of a principal
+pub fn get_quota(mut usage: QuotaUsage, config: RateLimitConfig, now: u64) -> Quota {
+    refresh_usage(&mut usage, &config, now);
+
+    Quota {
+        available_requests: config.rate_limit.as_ref().map(|_| usage.tokens),
+        requests_today: usage.requests_today,
+        remaining_today: config
+            .daily_quota
+            .map(|daily_quota| daily_quota.saturating_sub(usage.requests_today)),
+        resets_at: (usage.day + 1) * SECONDS_PER_DAY,
+        config,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use types::RateLimit;
+
+    fn config(daily_quota: Option<u64>) -> RateLimitConfig {
+        RateLimitConfig {
+            rate_limit: Some(RateLimit {
+                capacity: 2,
+                refill_interval: 10,
+            }),
+            daily_quota,
+        }
+    }
+
+    #[test]
+    fn test_validate_config() {
+        let rate_limit_config = |capacity: u32, refill_interval: u64| RateLimitConfig {
+            rate_limit: Some(RateLimit {
+                capacity,
+                refill_interval,
+            }),
+            daily_quota: Some(100),
+        };
+
+        assert!(validate_config(&RateLimitConfig::default()).is_ok());
+        assert!(validate_config(&rate_limit_config(10, 6)).is_ok());
+        assert!(validate_config(&rate_limit_config(0, 6)).is_err());
+        assert!(validate_config(&rate_limit_config(10, 0)).is_err());
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let config = config(None);
+        let mut usage = QuotaUsage::default();
+        let now = 1_000_000;
+
+        assert!(count_request(&mut usage, &config, now).is_ok());
+        assert!(count_request(&mut usage, &config, now).is_ok());
+        assert_eq!(
+            count_request(&mut usage, &config, now + 4),
+            Err(ADCError::RateLimited { retry_after: 6 })
+        );
+        // a single token is refilled after the refill interval
+        assert!(count_request(&mut usage, &config, now + 10).is_ok());
+        assert!(count_request(&mut usage, &config, now + 15).is_err());
+        // the tokens are never refilled beyond the capacity
+        assert_eq!(
+            get_quota(usage, config, now + 1000).available_requests,
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_daily_quota() {
+        let config = config(Some(2));
+        let mut usage = QuotaUsage::default();
+        let day_start = 100 * SECONDS_PER_DAY;
+
+        assert!(count_request(&mut usage, &config, day_start).is_ok());
+        assert!(count_request(&mut usage, &config, day_start + 100).is_ok());
+        assert_eq!(
+            count_request(&mut usage, &config, day_start + 200),
+            Err(ADCError::QuotaExceeded {
+                quota: 2,
+                resets_at: 101 * SECONDS_PER_DAY
+            })
+        );
+        assert_eq!(
+            get_quota(usage.clone(), config.clone(), day_start + 200).remaining_today,
+            Some(0)
+        );
+
+        // the quota is reset on the next day
+        assert!(count_request(&mut usage, &config, 101 * SECONDS_PER_DAY).is_ok());
+        assert_eq!(usage.requests_today, 1);
+    }
+}
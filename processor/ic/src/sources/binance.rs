@@ -0,0 +1,135 @@
+use anyhow::Context;
+use serde_json::Value;
+
+use super::{
+    MarketDataPoint, MarketDataSource, PointCandle, PricePoint, PricingDataSource, SeriesDataSource,
+};
+use types::{SourceDefinition, TimestampFormat};
+
+#[derive(Debug)]
+pub struct Binance {}
+
+impl PricingDataSource for Binance {
+    /// The binance 24hr ticker provides the last price and the close time of the window in milliseconds
+    fn definition() -> SourceDefinition {
+        SourceDefinition {
+            name: "binance".to_string(),
+            url_template: "https://api.binance.us/api/v3/ticker/24hr?symbol={ticker}USD"
+                .to_string(),
+            ticker_mapping: vec![],
+            price_path: "lastPrice".to_string(),
+            exponent_path: None,
+            timestamp_path: Some("closeTime".to_string()),
+            timestamp_format: TimestampFormat::Milliseconds,
+            confidence_path: None,
+        }
+    }
+}
+
+impl MarketDataSource for Binance {
+    /// Extract the binance price change from a string representation of the body of the http response,
+    /// the volume is only the one traded on binance so it is not provided
+    fn get_market_data(http_response_string: String) -> anyhow::Result<MarketDataPoint> {
+        // Parse the JSON response
+        let data: Value = serde_json::from_str(&http_response_string)?;
+
+        // Access the 'priceChangePercent' property and parse it
+        let change_24h: f64 = data["priceChangePercent"]
+            .as_str()
+            .context("Price change not available: JSON structure changed")?
+            .parse()?;
+
+        Ok(MarketDataPoint {
+            change_24h: Some(change_24h),
+            ..Default::default()
+        })
+    }
+}
+
+impl SeriesDataSource for Binance {
+    /// Extract the prices and volume of each candle from a string representation of the body of the http response,
+    /// each candle is a point at its close price
+    fn get_price_points(http_response_string: String) -> anyhow::Result<Vec<PricePoint>> {
+        // Parse the JSON response
+        let data: Value = serde_json::from_str(&http_response_string)?;
+
+        // the prices and the volume of a candle are provided as strings
+        let parse_value = |candle: &Value, index: usize| -> anyhow::Result<f64> {
+            Ok(candle[index]
+                .as_str()
+                .context("Candle value not available: JSON structure changed")?
+                .parse()?)
+        };
+
+        data.as_array()
+            .context("Candles not available: JSON structure changed")?
+            .iter()
+            .map(|candle| {
+                let open_time = candle[0]
+                    .as_u64()
+                    .context("Candle open time not available: JSON structure changed")?;
+                // the close time is the last millisecond of the candle
+                let close_time = candle[6]
+                    .as_u64()
+                    .context("Candle close time not available: JSON structure changed")?;
+
+                Ok(PricePoint {
+                    timestamp: (close_time + 1) / 1000,
+                    price: parse_value(candle, 4)?,
+                    volume: Some(parse_value(candle, 5)?),
+                    candle: Some(PointCandle {
+                        open_time: open_time / 1000,
+                        open: parse_value(candle, 1)?,
+                        high: parse_value(candle, 2)?,
+                        low: parse_value(candle, 3)?,
+                    }),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_price() {
+        let http_body = include_str!("fixtures/binance.json").to_string();
+
+        let price_data = Binance::get_price(http_body).unwrap();
+        assert_eq!(price_data.price, 67012.34);
+        assert_eq!(price_data.timestamp, Some(1728988361));
+    }
+
+    #[test]
+    fn test_get_market_data() {
+        let http_body = include_str!("fixtures/binance.json").to_string();
+
+        let market_data = Binance::get_market_data(http_body).unwrap();
+        assert_eq!(market_data.change_24h, Some(2.137));
+        assert_eq!(market_data.volume, None);
+    }
+
+    #[test]
+    fn test_get_price_points() {
+        let http_body = include_str!("fixtures/binance_series.json").to_string();
+
+        let price_points = Binance::get_price_points(http_body).unwrap();
+        assert_eq!(price_points.len(), 3);
+        assert_eq!(
+            price_points[0],
+            PricePoint {
+                timestamp: 1728987000,
+                price: 2610.0,
+                volume: Some(1200.5),
+                candle: Some(PointCandle {
+                    open_time: 1728986400,
+                    open: 2600.0,
+                    high: 2612.0,
+                    low: 2598.0,
+                }),
+            }
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use super::PricingDataSource;
+use types::{SourceDefinition, TimestampFormat};
+
+#[derive(Debug)]
+pub struct Coinbase {}
+
+impl PricingDataSource for Coinbase {
+    /// The coinbase ticker provides the last trade price and its time as an RFC 3339 string
+    fn definition() -> SourceDefinition {
+        SourceDefinition {
+            name: "coinbase".to_string(),
+            url_template: "https://api.exchange.coinbase.com/products/{ticker}-USD/ticker"
+                .to_string(),
+            ticker_mapping: vec![],
+            price_path: "price".to_string(),
+            exponent_path: None,
+            timestamp_path: Some("time".to_string()),
+            timestamp_format: TimestampFormat::Rfc3339,
+            confidence_path: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_price() {
+        let http_body = include_str!("fixtures/coinbase.json").to_string();
+
+        let price_data = Coinbase::get_price(http_body).unwrap();
+        assert_eq!(price_data.price, 67012.34);
+        assert_eq!(price_data.timestamp, Some(1728988361));
+    }
+}
@@ -0,0 +1,102 @@
+use anyhow::Context;
+use serde_json::Value;
+
+use super::{MarketDataPoint, MarketDataSource, PriceData, PricingDataSource};
+use types::{SourceDefinition, TimestampFormat};
+
+#[derive(Debug)]
+pub struct CoinGecko {}
+
+impl CoinGecko {
+    /// Get the market data of the token with the largest market cap in the response,
+    /// as several tokens can share the same symbol
+    fn get_token_data(http_response_string: &str) -> anyhow::Result<Value> {
+        // Parse the JSON response
+        let data: Value = serde_json::from_str(http_response_string)?;
+
+        // the order of the response is not relied on, the tokens without a market cap come last
+        data.as_array()
+            .and_then(|tokens| {
+                tokens.iter().max_by(|a, b| {
+                    let market_cap = |token: &Value| token["market_cap"].as_f64().unwrap_or(-1.0);
+                    market_cap(a).total_cmp(&market_cap(b))
+                })
+            })
+            .cloned()
+            .context("Market data not available: JSON structure changed")
+    }
+}
+
+impl PricingDataSource for CoinGecko {
+    /// The market data of a token provides its price and the time it was updated as an RFC 3339 string
+    fn definition() -> SourceDefinition {
+        SourceDefinition {
+            name: "coingecko".to_string(),
+            url_template:
+                "https://api.coingecko.com/api/v3/coins/markets?vs_currency=usd&order=market_cap_desc&symbols={ticker}"
+                    .to_string(),
+            ticker_mapping: vec![],
+            price_path: "current_price".to_string(),
+            exponent_path: None,
+            timestamp_path: Some("last_updated".to_string()),
+            timestamp_format: TimestampFormat::Rfc3339,
+            confidence_path: None,
+        }
+    }
+
+    /// Extract the coingecko price from a string representation of the body of the http response
+    fn get_price(http_response_string: String) -> anyhow::Result<PriceData> {
+        let data = Self::get_token_data(&http_response_string)?;
+
+        Ok(Self::definition().extract_value(&data)?.into())
+    }
+}
+
+impl MarketDataSource for CoinGecko {
+    /// Extract the coingecko market data from a string representation of the body of the http response
+    fn get_market_data(http_response_string: String) -> anyhow::Result<MarketDataPoint> {
+        let data = Self::get_token_data(&http_response_string)?;
+
+        Ok(MarketDataPoint {
+            volume: data["total_volume"].as_f64(),
+            market_cap: data["market_cap"].as_f64(),
+            change_24h: data["price_change_percentage_24h"].as_f64(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_price() {
+        let http_body = include_str!("fixtures/coingecko.json").to_string();
+
+        let price_data = CoinGecko::get_price(http_body).unwrap();
+        assert_eq!(price_data.price, 67012.34);
+        assert_eq!(price_data.timestamp, Some(1728988361));
+    }
+
+    #[test]
+    fn test_get_market_data() {
+        let http_body = include_str!("fixtures/coingecko.json").to_string();
+
+        let market_data = CoinGecko::get_market_data(http_body).unwrap();
+        assert_eq!(market_data.volume, Some(38420145123.0));
+        assert_eq!(market_data.market_cap, Some(1324563823746.0));
+        assert_eq!(market_data.change_24h, Some(2.137));
+    }
+
+    #[test]
+    fn test_get_price_of_largest_market_cap() {
+        let http_body = r#"[
+            {"symbol": "eth", "current_price": 2.1, "market_cap": 1000, "last_updated": "2024-10-15T10:32:41.000Z"},
+            {"symbol": "eth", "current_price": 2612.8, "market_cap": 314000000000, "last_updated": "2024-10-15T10:32:41.000Z"},
+            {"symbol": "eth", "current_price": 0.5, "market_cap": null, "last_updated": "2024-10-15T10:32:41.000Z"}
+        ]"#;
+
+        let price_data = CoinGecko::get_price(http_body.to_string()).unwrap();
+        assert_eq!(price_data.price, 2612.8);
+    }
+}
@@ -0,0 +1,13 @@
+use anyhow::Context;
+
+use super::PriceData;
+use crate::state;
+
+/// Extract the price from the body of an http response
+/// using the definition registered for the source
+pub fn get_price(source: &str, http_response_string: String) -> anyhow::Result<PriceData> {
+    let source_definition = state::get_source_definition(source)
+        .with_context(|| format!("Unknown source: {}", source))?;
+
+    Ok(source_definition.extract(&http_response_string)?.into())
+}
@@ -0,0 +1,53 @@
+use super::PricingDataSource;
+use types::{SourceDefinition, TickerMapping};
+
+#[derive(Debug)]
+pub struct Kraken {}
+
+impl PricingDataSource for Kraken {
+    /// The kraken ticker is keyed by kraken's name for the pair e.g XXBTZUSD
+    /// and provides the last trade closed price but not its time,
+    /// an error response does not have a result so it is rejected
+    fn definition() -> SourceDefinition {
+        SourceDefinition {
+            name: "kraken".to_string(),
+            url_template: "https://api.kraken.com/0/public/Ticker?pair={ticker}USD".to_string(),
+            ticker_mapping: vec![
+                TickerMapping {
+                    ticker: "BTC".to_string(),
+                    source_ticker: "XBT".to_string(),
+                },
+                TickerMapping {
+                    ticker: "DOGE".to_string(),
+                    source_ticker: "XDG".to_string(),
+                },
+            ],
+            price_path: "result.*.c.0".to_string(),
+            exponent_path: None,
+            timestamp_path: None,
+            timestamp_format: Default::default(),
+            confidence_path: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_price() {
+        let http_body = include_str!("fixtures/kraken.json").to_string();
+
+        let price_data = Kraken::get_price(http_body).unwrap();
+        assert_eq!(price_data.price, 67015.5);
+        assert_eq!(price_data.timestamp, None);
+    }
+
+    #[test]
+    fn test_get_price_with_error() {
+        let http_body = r#"{"error":["EQuery:Unknown asset pair"]}"#.to_string();
+
+        assert!(Kraken::get_price(http_body).is_err());
+    }
+}
@@ -1,62 +1,479 @@
+use binance::Binance;
+use coinbase::Coinbase;
+use coingecko::CoinGecko;
+use kraken::Kraken;
 use pyth::Pyth;
 use redstone::Redstone;
-use types::ProofTypes;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use types::fixed_point::mul_div;
+use types::{
+    ADCError, ExtractedPrice, FixedPointPrice, MarketData, PriceSeries, PricingConfig, ProofTypes,
+    RequestOpts, SeriesOpts, SourceDefinition, SourceFailure, SourceMarketData, SourcePrice,
+    FIXED_POINT_DECIMALS,
+};
 use verity_ic::verify::types::ProofResponse;
 
+use crate::aggregation::{
+    aggregate_fixed_prices, aggregate_market_data, aggregate_prices, aggregate_series,
+    reject_off_timestamp_prices, reject_outliers, reject_stale_prices, reject_wide_confidence,
+    MAX_CLOCK_SKEW,
+};
 use crate::state;
+use crate::utils::get_current_timestamp;
 
+pub mod binance;
+pub mod coinbase;
+pub mod coingecko;
+pub mod custom;
+pub mod kraken;
 pub mod pyth;
 pub mod redstone;
 
+/// The data extracted from the http response body of a pricing source
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PriceData {
+    pub price: f64,
+    /// the exact price with `FIXED_POINT_DECIMALS` decimals, if the source provides it exactly
+    pub fixed_price: Option<FixedPointPrice>,
+    /// the time (in seconds) the price was published by the source
+    pub timestamp: Option<u64>,
+    /// the confidence interval around the price
+    pub confidence: Option<f64>,
+}
+
+impl From<ExtractedPrice> for PriceData {
+    fn from(extracted_price: ExtractedPrice) -> Self {
+        Self {
+            price: extracted_price.price,
+            fixed_price: extracted_price.fixed_price,
+            timestamp: extracted_price.timestamp,
+            confidence: extracted_price.confidence,
+        }
+    }
+}
+
+/// The market data extracted from the http response body of a market data source
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MarketDataPoint {
+    /// the volume traded across all markets over the last 24 hours in USD
+    pub volume: Option<f64>,
+    /// the market capitalization in USD
+    pub market_cap: Option<f64>,
+    /// the relative price change over the last 24 hours in percent
+    pub change_24h: Option<f64>,
+}
+
+/// A price of a token at a point in time extracted from the http response body of a series source
+#[derive(Debug, Clone, PartialEq)]
+pub struct PricePoint {
+    /// the time (in seconds) of the price
+    pub timestamp: u64,
+    pub price: f64,
+    /// the volume traded since the previous point, if the source provides it
+    pub volume: Option<f64>,
+    /// the candle the price is the close of, if the source provides candles
+    pub candle: Option<PointCandle>,
+}
+
+/// The prices of a candle provided by a series source, which closes at the price of a point
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointCandle {
+    /// the time (in seconds) the candle opens at
+    pub open_time: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+}
+
+/// The verification responses of proofs indexed by the stringified proof
+pub type VerifiedProofs = HashMap<String, ProofResponse>;
+
+/// The price of a token combined from the prices provided by several sources
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenPrice {
+    pub price: f64,
+    /// the exact price with `FIXED_POINT_DECIMALS` decimals, if every source price used is exact
+    pub fixed_price: Option<FixedPointPrice>,
+}
+
+impl TokenPrice {
+    /// Get the price of this token in terms of the quote token,
+    /// the exact price is divided in integer arithmetic
+    pub fn div(&self, quote: &TokenPrice) -> TokenPrice {
+        let fixed_price = match (&self.fixed_price, &quote.fixed_price) {
+            (Some(base), Some(quote)) => {
+                mul_div(base.value, fixed_point_one(), quote.value).map(fixed_point_price)
+            }
+            _ => None,
+        };
+
+        TokenPrice {
+            price: self.price / quote.price,
+            fixed_price,
+        }
+    }
+
+    /// Multiply this price by the price of another pair, e.g STETH/ETH * ETH/USD
+    pub fn mul(&self, other: &TokenPrice) -> TokenPrice {
+        let fixed_price = match (&self.fixed_price, &other.fixed_price) {
+            (Some(price), Some(other)) => {
+                mul_div(price.value, other.value, fixed_point_one()).map(fixed_point_price)
+            }
+            _ => None,
+        };
+
+        TokenPrice {
+            price: self.price * other.price,
+            fixed_price,
+        }
+    }
+
+    /// A price of exactly one, which prices can be multiplied into
+    pub fn one() -> TokenPrice {
+        TokenPrice {
+            price: 1.0,
+            fixed_price: Some(fixed_point_price(fixed_point_one())),
+        }
+    }
+}
+
+fn fixed_point_one() -> u128 {
+    10u128.pow(FIXED_POINT_DECIMALS)
+}
+
+fn fixed_point_price(value: u128) -> FixedPointPrice {
+    FixedPointPrice::new(value, FIXED_POINT_DECIMALS)
+}
+
 pub trait PricingDataSource {
-    fn get_price(http_body: String) -> anyhow::Result<f64>;
+    /// The definition of the source, which describes where the price is in its http responses
+    fn definition() -> SourceDefinition;
+
+    /// Extract the price from a string representation of the body of the http response
+    fn get_price(http_body: String) -> anyhow::Result<PriceData> {
+        Ok(Self::definition().extract(&http_body)?.into())
+    }
 }
 
-/// Given a proof type and a corresponding proof response
+pub trait MarketDataSource {
+    fn get_market_data(http_body: String) -> anyhow::Result<MarketDataPoint>;
+}
+
+pub trait SeriesDataSource {
+    fn get_price_points(http_body: String) -> anyhow::Result<Vec<PricePoint>>;
+}
+
+/// Get the hex encoded sha256 hash of a proof
+pub fn get_proof_hash(proof: &ProofTypes) -> String {
+    hex::encode(Sha256::digest(proof.to_string().as_bytes()))
+}
+
+/// Given the proof types of a token and the verification responses of the proofs
 /// parse the http response json and
 /// get the actual price for the asset which is contained in the response
+/// the prices from all the valid sources are then combined using the pricing config provided,
+/// as of `at_timestamp` for historical prices.
+/// The prices used are recorded in `sources`
+/// and the sources which could not be parsed or were discarded are recorded in `failed_sources`
 pub fn get_asset_price_from_proofs(
-    proof_types: &Vec<ProofTypes>,
-    verification_response_proofs: &Vec<ProofResponse>,
-) -> anyhow::Result<f64> {
-    let source_prices: Vec<anyhow::Result<f64>> = verification_response_proofs
-        .iter()
-        .enumerate()
-        .map(|(index, res)| {
-            let http_body = res.get_http_response_body();
-
-            // get the proof's request source to know how to parse its response
-            let proof_type = &proof_types[index];
-            let price = match proof_type {
-                ProofTypes::Pyth(_) => Pyth::get_price(http_body),
-                ProofTypes::Redstone(_) => Redstone::get_price(http_body),
+    ticker: &str,
+    proof_types: &[ProofTypes],
+    verified_proofs: &VerifiedProofs,
+    pricing_config: &PricingConfig,
+    at_timestamp: Option<u64>,
+    sources: &mut Vec<SourcePrice>,
+    failed_sources: &mut Vec<SourceFailure>,
+) -> Result<TokenPrice, ADCError> {
+    let mut source_prices: Vec<SourcePrice> = vec![];
+    // the failures of this ticker are kept apart from the ones already recorded
+    let mut ticker_failures: Vec<SourceFailure> = vec![];
+
+    for proof_type in proof_types {
+        let Some(res) = verified_proofs.get(&proof_type.to_string()) else {
+            ticker_failures.push(SourceFailure::new(
+                proof_type.source(),
+                ticker,
+                ADCError::VerificationFailed(
+                    "Proof verification response not available".to_string(),
+                ),
+            ));
+            continue;
+        };
+        let http_body = res.get_http_response_body();
+
+        // get the proof's request source to know how to parse its response
+        let price_data = match proof_type {
+            ProofTypes::Pyth(_) => Pyth::get_price(http_body),
+            ProofTypes::Redstone(_) => Redstone::get_price(http_body),
+            ProofTypes::PythBatch { feed_id, .. } => Pyth::get_batch_price(feed_id, http_body),
+            ProofTypes::RedstoneBatch { symbol, .. } => {
+                Redstone::get_batch_price(symbol, http_body)
+            }
+            ProofTypes::Coinbase(_) => Coinbase::get_price(http_body),
+            ProofTypes::Binance(_) => Binance::get_price(http_body),
+            ProofTypes::Kraken(_) => Kraken::get_price(http_body),
+            ProofTypes::CoinGecko(_) => CoinGecko::get_price(http_body),
+            ProofTypes::RedstoneSeries(_) | ProofTypes::BinanceSeries(_) => Err(anyhow::anyhow!(
+                "Series proofs do not provide a single price"
+            )),
+            ProofTypes::PythFeedInfo { .. } => {
+                Err(anyhow::anyhow!("Feed info proofs do not provide a price"))
+            }
+            ProofTypes::Custom { source, .. } => custom::get_price(source, http_body),
+        };
+
+        match price_data {
+            Ok(price_data) => source_prices.push(SourcePrice {
+                source: proof_type.source().to_string(),
+                ticker: ticker.to_string(),
+                price: price_data.price,
+                fixed_price: price_data.fixed_price,
+                timestamp: price_data.timestamp,
+                confidence: price_data.confidence,
+                proof_hash: get_proof_hash(proof_type),
+            }),
+            Err(err) => ticker_failures.push(SourceFailure::new(
+                proof_type.source(),
+                ticker,
+                ADCError::InvalidResponse(err.to_string()),
+            )),
+        }
+    }
+
+    let token_price = combine_source_prices(
+        ticker,
+        source_prices,
+        pricing_config,
+        at_timestamp,
+        get_current_timestamp(),
+        sources,
+        &mut ticker_failures,
+    );
+    failed_sources.extend(ticker_failures);
+
+    token_price
+}
+
+/// Discard the prices of a token which do not meet the pricing config at time `now` (in seconds),
+/// or which were not published close enough to `at_timestamp` for historical prices,
+/// and combine the remaining ones if enough sources are left.
+/// The prices discarded are recorded in `failed_sources`, which only holds the failures of the token
+fn combine_source_prices(
+    ticker: &str,
+    mut source_prices: Vec<SourcePrice>,
+    pricing_config: &PricingConfig,
+    at_timestamp: Option<u64>,
+    now: u64,
+    sources: &mut Vec<SourcePrice>,
+    failed_sources: &mut Vec<SourceFailure>,
+) -> Result<TokenPrice, ADCError> {
+    // discard the historical prices which were not published close enough to the time requested
+    if let Some(at_timestamp) = at_timestamp {
+        let tolerance = pricing_config.get_timestamp_tolerance();
+        let (matching_prices, rejected_prices) =
+            reject_off_timestamp_prices(source_prices, at_timestamp, tolerance);
+        for rejected_price in rejected_prices {
+            let error = ADCError::TimestampOutOfRange {
+                timestamp: rejected_price.timestamp,
+                requested: at_timestamp,
+                tolerance,
             };
+            failed_sources.push(SourceFailure::new(&rejected_price.source, ticker, error));
+        }
+        source_prices = matching_prices;
+    } else if let Some(max_age) = pricing_config.max_age {
+        // otherwise discard the prices which were published too long ago
+        let (fresh_prices, stale_prices) = reject_stale_prices(source_prices, max_age, now);
+        for stale_price in stale_prices {
+            let error = match stale_price.timestamp {
+                // the prices published in the future are reported as such rather than as fresh
+                Some(timestamp) if timestamp > now => ADCError::TimestampOutOfRange {
+                    timestamp: Some(timestamp),
+                    requested: now,
+                    tolerance: MAX_CLOCK_SKEW,
+                },
+                timestamp => ADCError::StalePrice {
+                    age: timestamp.map(|timestamp| now.saturating_sub(timestamp)),
+                    max_age,
+                },
+            };
+            failed_sources.push(SourceFailure::new(&stale_price.source, ticker, error));
+        }
+        source_prices = fresh_prices;
+    }
+
+    // discard the prices whose confidence interval is too wide
+    if let Some(max_confidence_ratio) = pricing_config.max_confidence_ratio {
+        let (accepted_prices, rejected_prices) =
+            reject_wide_confidence(source_prices, max_confidence_ratio);
+        for rejected_price in rejected_prices {
+            failed_sources.push(SourceFailure::new(
+                &rejected_price.source,
+                ticker,
+                ADCError::ConfidenceTooWide {
+                    price: rejected_price.price,
+                    confidence: rejected_price.confidence.unwrap_or_default(),
+                    max_confidence_ratio,
+                },
+            ));
+        }
+        source_prices = accepted_prices;
+    }
+
+    // discard the prices which deviate too much from the others
+    if let Some(max_deviation) = pricing_config.max_deviation {
+        let (accepted_prices, rejected_prices) = reject_outliers(source_prices, max_deviation);
+        for rejected_price in rejected_prices {
+            failed_sources.push(SourceFailure::new(
+                &rejected_price.source,
+                ticker,
+                ADCError::DeviationTooHigh {
+                    price: rejected_price.price,
+                    max_deviation,
+                },
+            ));
+        }
+        source_prices = accepted_prices;
+    }
+
+    // make sure enough sources provided a valid price before combining them
+    let min_sources = pricing_config.min_sources.max(1) as usize;
+    if source_prices.len() < min_sources {
+        return Err(ADCError::insufficient_sources(
+            ticker,
+            source_prices.len(),
+            min_sources,
+            failed_sources,
+        ));
+    }
+
+    let asset_price = aggregate_prices(&source_prices, pricing_config)
+        .map_err(|err| ADCError::Other(err.to_string()))?;
+    // the exact price is only derived when it is requested
+    let fixed_price = pricing_config
+        .decimals
+        .and_then(|_| aggregate_fixed_prices(&source_prices, pricing_config));
+    sources.extend(source_prices);
+
+    Ok(TokenPrice {
+        price: asset_price,
+        fixed_price,
+    })
+}
 
-            price
-        })
-        .collect();
+/// Given the market data proofs of a token and the verification responses of the proofs
+/// get the market data provided by each source and combine the values requested.
+/// The sources which could not be parsed are recorded in `failed_sources`
+pub fn get_market_data_from_proofs(
+    ticker: &str,
+    proof_types: &[ProofTypes],
+    verified_proofs: &VerifiedProofs,
+    opts: &RequestOpts,
+    failed_sources: &mut Vec<SourceFailure>,
+) -> Result<MarketData, ADCError> {
+    // the failures of this ticker start after the ones already recorded
+    let first_failure = failed_sources.len();
+    let mut source_market_data: Vec<SourceMarketData> = vec![];
 
-    if source_prices.iter().any(|res| res.is_err()) {
-        anyhow::bail!("Error getting assset price")
+    for proof_type in proof_types {
+        let Some(res) = verified_proofs.get(&proof_type.to_string()) else {
+            failed_sources.push(SourceFailure::new(
+                proof_type.source(),
+                ticker,
+                ADCError::VerificationFailed(
+                    "Proof verification response not available".to_string(),
+                ),
+            ));
+            continue;
+        };
+        let http_body = res.get_http_response_body();
+
+        let market_data = match proof_type {
+            ProofTypes::CoinGecko(_) => CoinGecko::get_market_data(http_body),
+            ProofTypes::Binance(_) => Binance::get_market_data(http_body),
+            // the other sources only provide prices
+            _ => continue,
+        };
+
+        match market_data {
+            Ok(market_data) => source_market_data.push(SourceMarketData {
+                source: proof_type.source().to_string(),
+                ticker: ticker.to_string(),
+                volume: market_data.volume,
+                market_cap: market_data.market_cap,
+                change_24h: market_data.change_24h,
+                proof_hash: get_proof_hash(proof_type),
+            }),
+            Err(err) => failed_sources.push(SourceFailure::new(
+                proof_type.source(),
+                ticker,
+                ADCError::InvalidResponse(err.to_string()),
+            )),
+        }
     }
 
-    // calculate the average of these responses
-    let source_prices_values: Vec<f64> = source_prices
-        .iter()
-        .map(|p| p.as_ref().unwrap().to_owned())
-        .collect();
-    let source_prices_sum: f64 = source_prices_values.iter().sum();
-    let count = source_prices_values.len() as f64;
-    let average_asset_price = source_prices_sum / count;
+    aggregate_market_data(source_market_data, opts).ok_or_else(|| {
+        ADCError::insufficient_sources(ticker, 0, 1, &failed_sources[first_failure..])
+    })
+}
+
+/// Given the series proofs of a token and the verification responses of the proofs
+/// get the price points provided by each source and compute the series requested over the window ending at `to`.
+/// The sources which could not be parsed are recorded in `failed_sources`
+pub fn get_series_from_proofs(
+    ticker: &str,
+    proof_types: &[ProofTypes],
+    verified_proofs: &VerifiedProofs,
+    series_opts: &SeriesOpts,
+    to: u64,
+    failed_sources: &mut Vec<SourceFailure>,
+) -> Result<PriceSeries, ADCError> {
+    // the failures of this ticker start after the ones already recorded
+    let first_failure = failed_sources.len();
+    let mut source_points: Vec<(String, Vec<PricePoint>)> = vec![];
+
+    for proof_type in proof_types {
+        let Some(res) = verified_proofs.get(&proof_type.to_string()) else {
+            failed_sources.push(SourceFailure::new(
+                proof_type.source(),
+                ticker,
+                ADCError::VerificationFailed(
+                    "Proof verification response not available".to_string(),
+                ),
+            ));
+            continue;
+        };
+        let http_body = res.get_http_response_body();
 
-    Ok(average_asset_price)
+        let price_points = match proof_type {
+            ProofTypes::RedstoneSeries(_) => Redstone::get_price_points(http_body),
+            ProofTypes::BinanceSeries(_) => Binance::get_price_points(http_body),
+            // the other sources only provide single prices
+            _ => continue,
+        };
+
+        match price_points {
+            Ok(price_points) => source_points.push((proof_type.source().to_string(), price_points)),
+            Err(err) => failed_sources.push(SourceFailure::new(
+                proof_type.source(),
+                ticker,
+                ADCError::InvalidResponse(err.to_string()),
+            )),
+        }
+    }
+
+    let from = to.saturating_sub(series_opts.window);
+    aggregate_series(source_points, &series_opts.kind, from, to).ok_or_else(|| {
+        ADCError::insufficient_sources(ticker, 0, 1, &failed_sources[first_failure..])
+    })
 }
 
 /// Request verification from the managed verifier response
 pub async fn request_proof_verification(
     stringified_proofs: &Vec<String>,
     notary_pubkey: &String,
-) -> Vec<ProofResponse> {
+) -> Result<Vec<ProofResponse>, ADCError> {
     let verifier_canister = state::get_verifier_canister().unwrap();
 
     // make a request to the managed verifier canister
@@ -67,7 +484,141 @@ pub async fn request_proof_verification(
         (stringified_proofs, notary_pubkey),
     )
     .await
-    .unwrap();
+    .map_err(|(code, message)| {
+        ADCError::VerificationFailed(format!(
+            "verifier canister rejected the call with {:?}: {}",
+            code, message
+        ))
+    })?;
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prices(values: &[(&str, f64, u64)]) -> Vec<SourcePrice> {
+        values
+            .iter()
+            .map(|(source, price, timestamp)| SourcePrice {
+                source: source.to_string(),
+                ticker: "BTC".to_string(),
+                price: *price,
+                fixed_price: None,
+                timestamp: Some(*timestamp),
+                confidence: None,
+                proof_hash: String::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_combine_source_prices_rejects_below_quorum() {
+        let pricing_config = PricingConfig {
+            min_sources: 2,
+            max_age: Some(60),
+            max_deviation: Some(0.05),
+            ..Default::default()
+        };
+        let source_prices = prices(&[
+            ("pyth", 100.0, 1_000),
+            ("redstone", 101.0, 1_000),
+            ("coinbase", 200.0, 1_000),
+            ("binance", 100.0, 900),
+        ]);
+        let mut sources = vec![];
+        let mut failed_sources = vec![];
 
-    response
+        let price = combine_source_prices(
+            "BTC",
+            source_prices.clone(),
+            &pricing_config,
+            None,
+            1_010,
+            &mut sources,
+            &mut failed_sources,
+        );
+        assert_eq!(price.map(|price| price.price), Ok(100.5));
+        assert_eq!(sources.len(), 2);
+        // the stale and the deviating prices are reported as failed sources
+        let failed: Vec<&str> = failed_sources
+            .iter()
+            .map(|failure| failure.source.as_str())
+            .collect();
+        assert_eq!(failed, vec!["binance", "coinbase"]);
+        assert!(matches!(
+            failed_sources[0].error,
+            ADCError::StalePrice {
+                age: Some(110),
+                max_age: 60
+            }
+        ));
+        assert!(matches!(
+            failed_sources[1].error,
+            ADCError::DeviationTooHigh { .. }
+        ));
+
+        // a single price is below the quorum
+        let mut sources = vec![];
+        let mut failed_sources = vec![];
+        let price = combine_source_prices(
+            "BTC",
+            source_prices[2..3].to_vec(),
+            &pricing_config,
+            None,
+            1_010,
+            &mut sources,
+            &mut failed_sources,
+        );
+        assert_eq!(
+            price,
+            Err(ADCError::InsufficientSources {
+                ticker: "BTC".to_string(),
+                available: 1,
+                required: 2,
+            })
+        );
+        assert!(sources.is_empty());
+
+        // the quorum missed is still reported when the other sources are discarded for the same reason
+        let price = combine_source_prices(
+            "BTC",
+            source_prices[2..].to_vec(),
+            &pricing_config,
+            None,
+            1_010,
+            &mut sources,
+            &mut failed_sources,
+        );
+        assert_eq!(
+            price,
+            Err(ADCError::InsufficientSources {
+                ticker: "BTC".to_string(),
+                available: 1,
+                required: 2,
+            })
+        );
+        assert_eq!(failed_sources.len(), 1);
+        assert_eq!(failed_sources[0].source, "binance");
+
+        // the failure shared by the sources discarded is reported when none of them is valid
+        let mut failed_sources = vec![];
+        let price = combine_source_prices(
+            "BTC",
+            source_prices[3..].to_vec(),
+            &pricing_config,
+            None,
+            1_010,
+            &mut sources,
+            &mut failed_sources,
+        );
+        assert_eq!(
+            price,
+            Err(ADCError::StalePrice {
+                age: Some(110),
+                max_age: 60
+            })
+        );
+    }
 }
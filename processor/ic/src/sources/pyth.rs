@@ -1,30 +1,153 @@
 use serde_json::Value;
 
-use super::PricingDataSource;
+use super::{PriceData, PricingDataSource};
 use anyhow::Context;
+use types::source_definition::select;
+use types::SourceDefinition;
 
 #[derive(Debug)]
 pub struct Pyth {}
 
+impl Pyth {
+    /// Extract the price of a single feed from a string representation of the body of
+    /// a batched http response containing several price feeds,
+    /// either the latest price feeds or the historical price updates in the `parsed` field
+    pub fn get_batch_price(
+        feed_id: &str,
+        http_response_string: String,
+    ) -> anyhow::Result<PriceData> {
+        // Parse the JSON response
+        let data: Value = serde_json::from_str(&http_response_string)?;
+        let feed_id = normalize_feed_id(feed_id);
+        let price_feed = select(&data, &format!("id={feed_id}|parsed.id={feed_id}"))
+            .with_context(|| format!("price feed {} is missing", feed_id))?;
+
+        Ok(Self::definition().extract_value(price_feed)?.into())
+    }
+
+    /// Check that a string representation of the body of the http response of the price feeds endpoint
+    /// describes the feed `feed_id` as providing the price of `base` in `quote`
+    pub fn check_feed_info(
+        feed_id: &str,
+        base: &str,
+        quote: &str,
+        http_response_string: String,
+    ) -> anyhow::Result<()> {
+        // Parse the JSON response
+        let data: Value = serde_json::from_str(&http_response_string)?;
+        let attributes = select(
+            &data,
+            &format!("id={}.attributes", normalize_feed_id(feed_id)),
+        )
+        .with_context(|| format!("price feed {} is missing", feed_id))?;
+
+        let feed_base = attributes["base"]
+            .as_str()
+            .context("attributes.base field is missing")?;
+        let feed_quote = attributes["quote_currency"]
+            .as_str()
+            .context("attributes.quote_currency field is missing")?;
+        if !feed_base.eq_ignore_ascii_case(base) || !feed_quote.eq_ignore_ascii_case(quote) {
+            anyhow::bail!(
+                "price feed {} is a {}/{} feed, not a {}/{} feed",
+                feed_id,
+                feed_base,
+                feed_quote,
+                base,
+                quote
+            )
+        }
+
+        Ok(())
+    }
+}
+
 impl PricingDataSource for Pyth {
+    /// A pyth price feed provides its price as an integer scaled by its exponent,
+    /// which the exact price is derived from without floats
+    fn definition() -> SourceDefinition {
+        SourceDefinition {
+            name: "pyth".to_string(),
+            url_template: "https://hermes.pyth.network/api/latest_price_feeds?ids[]={ticker}"
+                .to_string(),
+            ticker_mapping: vec![],
+            price_path: "price.price".to_string(),
+            exponent_path: Some("price.expo".to_string()),
+            timestamp_path: Some("price.publish_time".to_string()),
+            timestamp_format: Default::default(),
+            confidence_path: Some("price.conf".to_string()),
+        }
+    }
+
     /// Extract the pyth price from a string representation of the body of the http response
-    fn get_price(http_response_string: String) -> anyhow::Result<f64> {
+    fn get_price(http_response_string: String) -> anyhow::Result<PriceData> {
         // Parse the JSON response
         let data: Value = serde_json::from_str(&http_response_string)?;
-        let price = data[0]["price"]["price"]
-            .as_str()
-            .context("price.price field is missing")
-            .and_then(|price| Ok(price))?;
-        let exp = data[0]["price"]["expo"]
-            .as_i64()
-            .context("price.expo field is missing")
-            .and_then(|exp| Ok(exp))?;
-
-        // try parsing the price gotten to check for any errors
-        let price: f64 = price.parse()?;
-        let multiplier = 1.0 as f64 / (10 as f64).powf(exp.abs() as f64);
-        let asset_price: f64 = price * multiplier;
-
-        return Ok(asset_price);
+        let price_feed = data.get(0).context("price feed is missing")?;
+
+        Ok(Self::definition().extract_value(price_feed)?.into())
+    }
+}
+
+/// Feed IDs may or may not be prefixed by `0x` depending on the endpoint they are gotten from
+fn normalize_feed_id(feed_id: &str) -> String {
+    feed_id.trim_start_matches("0x").to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_batch_price() {
+        let http_body = include_str!("fixtures/pyth_batch.json").to_string();
+
+        let btc_price = Pyth::get_batch_price(
+            "0xE62DF6C8B4A85FE1A67DB44DC12DE5DB330F7AC66B72DC658AFEDF0F4A415B43",
+            http_body.clone(),
+        )
+        .unwrap();
+        assert!((btc_price.price - 67012.34).abs() < 1e-6);
+        assert_eq!(
+            btc_price.fixed_price.unwrap().value,
+            67_012_340_000_000_000_000_000
+        );
+        assert_eq!(btc_price.timestamp, Some(1728988361));
+
+        let eth_price = Pyth::get_batch_price(
+            "ff61491a931112ddf1bd8147cd1b641375f79f5825126d665480874634fd0ace",
+            http_body.clone(),
+        )
+        .unwrap();
+        assert!((eth_price.price - 2612.5).abs() < 1e-6);
+
+        assert!(Pyth::get_batch_price("unknown", http_body).is_err());
+    }
+
+    #[test]
+    fn test_check_feed_info() {
+        let http_body = r#"[
+            {"id": "c96458d393fe9deb7a7d63a0ac41e2898a67a7750dbd166673279e06c868df0a", "attributes": {"base": "ETH", "quote_currency": "BTC"}},
+            {"id": "e62df6c8b4a85fe1a67db44dc12de5db330f7ac66b72dc658afedf0f4a415b43", "attributes": {"base": "BTC", "quote_currency": "USD"}}
+        ]"#;
+        let feed_id = "0xc96458d393fe9deb7a7d63a0ac41e2898a67a7750dbd166673279e06c868df0a";
+
+        assert!(Pyth::check_feed_info(feed_id, "eth", "btc", http_body.to_string()).is_ok());
+        // a feed of another pair cannot be used as the direct feed of the pair
+        assert!(Pyth::check_feed_info(feed_id, "BTC", "USD", http_body.to_string()).is_err());
+        assert!(Pyth::check_feed_info("unknown", "ETH", "BTC", http_body.to_string()).is_err());
+    }
+
+    #[test]
+    fn test_get_historical_batch_price() {
+        let http_body = include_str!("fixtures/pyth_historical.json").to_string();
+
+        let btc_price = Pyth::get_batch_price(
+            "0xe62df6c8b4a85fe1a67db44dc12de5db330f7ac66b72dc658afedf0f4a415b43",
+            http_body,
+        )
+        .unwrap();
+        assert!((btc_price.price - 60542.1).abs() < 1e-6);
+        assert_eq!(btc_price.timestamp, Some(1727740800));
     }
 }
@@ -1,22 +1,116 @@
 use anyhow::Context;
 use serde_json::Value;
 
-use super::PricingDataSource;
+use super::{PriceData, PricePoint, PricingDataSource, SeriesDataSource};
+use types::{SourceDefinition, TimestampFormat};
 
 #[derive(Debug)]
 pub struct Redstone {}
 
+impl Redstone {
+    /// Extract the price of a single symbol from a string representation of the body of
+    /// a batched http response containing the prices of several symbols keyed by symbol
+    pub fn get_batch_price(
+        symbol: &str,
+        http_response_string: String,
+    ) -> anyhow::Result<PriceData> {
+        // Parse the JSON response
+        let data: Value = serde_json::from_str(&http_response_string)?;
+        let symbol_price = data
+            .as_object()
+            .context("Prices not available: JSON structure changed")?
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(symbol))
+            .map(|(_, symbol_price)| symbol_price)
+            .with_context(|| format!("Price of {} not available", symbol))?;
+
+        Self::parse_price(symbol_price)
+    }
+
+    /// Extract the price of a single redstone symbol
+    fn parse_price(symbol_price: &Value) -> anyhow::Result<PriceData> {
+        Ok(Self::definition().extract_value(symbol_price)?.into())
+    }
+}
+
 impl PricingDataSource for Redstone {
+    /// A redstone price provides its value and the time it was published in milliseconds
+    fn definition() -> SourceDefinition {
+        SourceDefinition {
+            name: "redstone".to_string(),
+            url_template:
+                "https://api.redstone.finance/prices?symbol={ticker}&provider=redstone&limit=1"
+                    .to_string(),
+            ticker_mapping: vec![],
+            price_path: "value".to_string(),
+            exponent_path: None,
+            timestamp_path: Some("timestamp".to_string()),
+            timestamp_format: TimestampFormat::Milliseconds,
+            confidence_path: None,
+        }
+    }
+
     /// Extract the redstone price from a string representation of the body of the http response
-    fn get_price(http_response_string: String) -> anyhow::Result<f64> {
+    fn get_price(http_response_string: String) -> anyhow::Result<PriceData> {
+        // Parse the JSON response
+        let data: Value = serde_json::from_str(&http_response_string)?;
+        let symbol_price = data
+            .get(0)
+            .context("Price not available: JSON structure changed")?;
+
+        Self::parse_price(symbol_price)
+    }
+}
+
+impl SeriesDataSource for Redstone {
+    /// Extract the price points from a string representation of the body of the http response
+    fn get_price_points(http_response_string: String) -> anyhow::Result<Vec<PricePoint>> {
         // Parse the JSON response
         let data: Value = serde_json::from_str(&http_response_string)?;
 
-        // Access the 'price' property and return it
-        let price = data[0]["value"]
-            .as_f64()
-            .context("Price not available: JSON structure changed")
-            .and_then(|val| Ok(val))?;
-        return Ok(price);
+        data.as_array()
+            .context("Prices not available: JSON structure changed")?
+            .iter()
+            .map(|symbol_price| {
+                let price_data = Self::parse_price(symbol_price)?;
+                let timestamp = price_data
+                    .timestamp
+                    .context("Timestamp not available: JSON structure changed")?;
+
+                Ok(PricePoint {
+                    timestamp,
+                    price: price_data.price,
+                    volume: None,
+                    candle: None,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_batch_price() {
+        let http_body = include_str!("fixtures/redstone_batch.json").to_string();
+
+        let price_data = Redstone::get_batch_price("eth", http_body.clone()).unwrap();
+        assert_eq!(price_data.price, 2612.8);
+        assert_eq!(price_data.timestamp, Some(1728988361));
+
+        assert!(Redstone::get_batch_price("SOL", http_body).is_err());
+    }
+
+    #[test]
+    fn test_get_price_points() {
+        let http_body = include_str!("fixtures/redstone_series.json").to_string();
+
+        let price_points = Redstone::get_price_points(http_body).unwrap();
+        assert_eq!(price_points.len(), 4);
+        assert_eq!(price_points[0].timestamp, 1728988200);
+        assert_eq!(price_points[3].price, 2600.0);
+        assert!(price_points.iter().all(|point| point.volume.is_none()));
     }
 }
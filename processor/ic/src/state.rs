@@ -1,41 +1,507 @@
+use crate::ledger::flat_fee_schedule;
+use crate::memory::{
+    get_memory, Candid, Memory, ACCESS_BLACKLIST_MEMORY_ID, ACCESS_MODE_MEMORY_ID,
+    ACCESS_WHITELIST_MEMORY_ID, FEE_MEMORY_ID, FEE_SCHEDULE_MEMORY_ID, LEDGER_MEMORY_ID,
+    PRICE_CACHE_MEMORY_ID, PRICING_CONFIG_MEMORY_ID, PRINCIPAL_RATE_LIMIT_CONFIGS_MEMORY_ID,
+    QUOTA_USAGE_MEMORY_ID, RATE_LIMIT_CONFIG_MEMORY_ID, REQUESTS_MEMORY_ID,
+    REQUEST_RESPONSE_BUFFER_MEMORY_ID, REQUEST_TIMEOUT_CONFIG_MEMORY_ID,
+    SOURCE_DEFINITIONS_MEMORY_ID, SUBSCRIPTIONS_MEMORY_ID, VERIFIER_CANISTER_MEMORY_ID,
+    WHITELIST_MEMORY_ID,
+};
 use candid::Principal;
+use ic_stable_structures::{StableBTreeMap, StableCell};
 use std::{cell::RefCell, collections::HashMap};
+use types::{
+    AccessMode, AccessRule, CachedPrice, FeeSchedule, LedgerAccount, PricingConfig, QuotaUsage,
+    RateLimitConfig, RequestRecord, RequestStatus, RequestTimeoutConfig, SourceDefinition,
+    Subscription,
+};
 
 thread_local! {
-    pub static REQUEST_RESPONSE_BUFFER: RefCell<HashMap<String, bool>> = RefCell::default();
-    pub static VERIFIER_CANISTER: RefCell<Option<Principal>> = RefCell::default();
-    pub static FEE: RefCell<u128> = RefCell::default();
+    /// the requests logged for the orchestrator which have not been answered yet,
+    /// with the time (in nanoseconds) they were submitted at
+    pub static REQUEST_RESPONSE_BUFFER: RefCell<StableBTreeMap<String, u64, Memory>> = RefCell::new(
+        StableBTreeMap::init(get_memory(REQUEST_RESPONSE_BUFFER_MEMORY_ID)),
+    );
+    pub static VERIFIER_CANISTER: RefCell<StableCell<Candid<Option<Principal>>, Memory>> = RefCell::new(
+        StableCell::init(get_memory(VERIFIER_CANISTER_MEMORY_ID), Candid::default())
+            .expect("failed to initialize the verifier canister"),
+    );
+    /// the flat fee charged for every request by the versions before the fee schedule,
+    /// only read to migrate it to the `FEE_SCHEDULE` state variable
+    pub static FEE: RefCell<StableCell<Candid<u128>, Memory>> = RefCell::new(
+        StableCell::init(get_memory(FEE_MEMORY_ID), Candid::default())
+            .expect("failed to initialize the fee"),
+    );
+    pub static FEE_SCHEDULE: RefCell<StableCell<Candid<FeeSchedule>, Memory>> = RefCell::new(
+        StableCell::init(get_memory(FEE_SCHEDULE_MEMORY_ID), Candid::default())
+            .expect("failed to initialize the fee schedule"),
+    );
+    pub static PRICING_CONFIG: RefCell<StableCell<Candid<PricingConfig>, Memory>> = RefCell::new(
+        StableCell::init(get_memory(PRICING_CONFIG_MEMORY_ID), Candid::default())
+            .expect("failed to initialize the pricing config"),
+    );
+    pub static SOURCE_DEFINITIONS: RefCell<StableBTreeMap<String, Candid<SourceDefinition>, Memory>> = RefCell::new(
+        StableBTreeMap::init(get_memory(SOURCE_DEFINITIONS_MEMORY_ID)),
+    );
+    pub static PRICE_CACHE: RefCell<StableBTreeMap<String, Candid<CachedPrice>, Memory>> = RefCell::new(
+        StableBTreeMap::init(get_memory(PRICE_CACHE_MEMORY_ID)),
+    );
+    pub static SUBSCRIPTIONS: RefCell<StableBTreeMap<String, Candid<Subscription>, Memory>> = RefCell::new(
+        StableBTreeMap::init(get_memory(SUBSCRIPTIONS_MEMORY_ID)),
+    );
+    /// the records of the requests made, keyed by their id which starts with the time they were made at
+    /// so that they are ordered from the oldest to the newest
+    pub static REQUESTS: RefCell<StableBTreeMap<String, Candid<RequestRecord>, Memory>> = RefCell::new(
+        StableBTreeMap::init(get_memory(REQUESTS_MEMORY_ID)),
+    );
+    pub static REQUEST_TIMEOUT_CONFIG: RefCell<StableCell<Candid<RequestTimeoutConfig>, Memory>> = RefCell::new(
+        StableCell::init(get_memory(REQUEST_TIMEOUT_CONFIG_MEMORY_ID), Candid::default())
+            .expect("failed to initialize the request timeout config"),
+    );
+    /// the ledger account of each caller, keyed by the text of its principal
+    pub static LEDGER: RefCell<StableBTreeMap<String, Candid<LedgerAccount>, Memory>> = RefCell::new(
+        StableBTreeMap::init(get_memory(LEDGER_MEMORY_ID)),
+    );
+    /// the copy of the whitelist of `verity_ic` kept by the versions before the access lists,
+    /// only read to migrate it to the `ACCESS_WHITELIST` state variable
+    pub static WHITELIST: RefCell<StableCell<Candid<HashMap<Principal, bool>>, Memory>> = RefCell::new(
+        StableCell::init(get_memory(WHITELIST_MEMORY_ID), Candid::default())
+            .expect("failed to initialize the whitelist"),
+    );
+    pub static ACCESS_MODE: RefCell<StableCell<Candid<AccessMode>, Memory>> = RefCell::new(
+        StableCell::init(get_memory(ACCESS_MODE_MEMORY_ID), Candid::default())
+            .expect("failed to initialize the access mode"),
+    );
+    /// the rule each whitelisted principal is whitelisted with, keyed by the text of its principal
+    pub static ACCESS_WHITELIST: RefCell<StableBTreeMap<String, Candid<AccessRule>, Memory>> = RefCell::new(
+        StableBTreeMap::init(get_memory(ACCESS_WHITELIST_MEMORY_ID)),
+    );
+    /// the blacklisted principals, keyed by their text
+    pub static ACCESS_BLACKLIST: RefCell<StableBTreeMap<String, bool, Memory>> = RefCell::new(
+        StableBTreeMap::init(get_memory(ACCESS_BLACKLIST_MEMORY_ID)),
+    );
+    /// the limits on the requests of the principals which are not given their own
+    pub static RATE_LIMIT_CONFIG: RefCell<StableCell<Candid<RateLimitConfig>, Memory>> = RefCell::new(
+        StableCell::init(get_memory(RATE_LIMIT_CONFIG_MEMORY_ID), Candid::default())
+            .expect("failed to initialize the rate limit config"),
+    );
+    /// the limits given to specific principals, keyed by the text of their principal
+    pub static PRINCIPAL_RATE_LIMIT_CONFIGS: RefCell<StableBTreeMap<String, Candid<RateLimitConfig>, Memory>> = RefCell::new(
+        StableBTreeMap::init(get_memory(PRINCIPAL_RATE_LIMIT_CONFIGS_MEMORY_ID)),
+    );
+    /// the requests each principal made within its limits, keyed by the text of its principal
+    pub static QUOTA_USAGE: RefCell<StableBTreeMap<String, Candid<QuotaUsage>, Memory>> = RefCell::new(
+        StableBTreeMap::init(get_memory(QUOTA_USAGE_MEMORY_ID)),
+    );
 }
 
-/// Getter for the `REQUEST_RESPONSE_BUFFER` state variable
-pub fn get_buffer() -> HashMap<String, bool> {
-    REQUEST_RESPONSE_BUFFER.with(|rc| rc.borrow().clone())
+/// The state serialized with `stable_save` by the versions before the state was kept in stable structures,
+/// which is the request buffer, the whitelist, the verifier canister and the flat fee
+pub type LegacyState = (
+    HashMap<String, bool>,
+    HashMap<Principal, bool>,
+    Option<Principal>,
+    u128,
+);
+
+/// Record a request as pending in the `REQUEST_RESPONSE_BUFFER` state variable
+/// and keep its record in the `REQUESTS` state variable
+pub fn add_pending_request(request_record: RequestRecord) {
+    REQUEST_RESPONSE_BUFFER.with(|rc| {
+        rc.borrow_mut()
+            .insert(request_record.id.clone(), ic_cdk::api::time())
+    });
+    set_request_record(request_record);
+}
+
+/// Check if a request is pending in the `REQUEST_RESPONSE_BUFFER` state variable
+pub fn is_pending_request(id: &str) -> bool {
+    REQUEST_RESPONSE_BUFFER.with(|rc| rc.borrow().contains_key(&id.to_string()))
+}
+
+/// Remove a request from the `REQUEST_RESPONSE_BUFFER` state variable once it is answered
+pub fn remove_pending_request(id: &str) {
+    REQUEST_RESPONSE_BUFFER.with(|rc| rc.borrow_mut().remove(&id.to_string()));
+}
+
+/// Get the ids of the requests in the `REQUEST_RESPONSE_BUFFER` state variable
+/// which were submitted before `cutoff` (in nanoseconds)
+pub fn get_pending_requests_before(cutoff: u64) -> Vec<String> {
+    REQUEST_RESPONSE_BUFFER.with(|rc| {
+        rc.borrow()
+            .iter()
+            .filter(|(_, submitted_at)| *submitted_at <= cutoff)
+            .map(|(id, _)| id)
+            .collect()
+    })
+}
+
+/// Getter for a single request record in the `REQUESTS` state variable
+pub fn get_request_record(id: &str) -> Option<RequestRecord> {
+    REQUESTS.with(|r| r.borrow().get(&id.to_string()).map(|r| r.0))
+}
+
+/// Add or replace a request record in the `REQUESTS` state variable
+pub fn set_request_record(request_record: RequestRecord) {
+    REQUESTS.with(|r| {
+        r.borrow_mut()
+            .insert(request_record.id.clone(), Candid(request_record))
+    });
+}
+
+/// Record the outcome of a request in the `REQUESTS` state variable,
+/// the requests made before their records were kept have no record to update
+pub fn set_request_status(id: &str, status: RequestStatus, now: u64) -> Option<RequestRecord> {
+    let mut request_record = get_request_record(id)?;
+    request_record.completed_at = (status != RequestStatus::Pending).then_some(now);
+    request_record.status = status;
+    set_request_record(request_record.clone());
+
+    Some(request_record)
+}
+
+/// Remove the records of the completed requests made before `cutoff` (in seconds) from the `REQUESTS` state variable
+pub fn prune_request_records(cutoff: u64) {
+    REQUESTS.with(|r| {
+        let mut requests = r.borrow_mut();
+        // the records are ordered by the time the requests were made at
+        let pruned: Vec<String> = requests
+            .iter()
+            .take_while(|(_, record)| record.0.created_at < cutoff)
+            .filter(|(_, record)| record.0.status != RequestStatus::Pending)
+            .map(|(id, _)| id)
+            .collect();
+        for id in pruned {
+            requests.remove(&id);
+        }
+    });
+}
+
+/// Getter for `REQUEST_TIMEOUT_CONFIG` state variable
+pub fn get_request_timeout_config() -> RequestTimeoutConfig {
+    REQUEST_TIMEOUT_CONFIG.with(|rtc| rtc.borrow().get().0.clone())
 }
 
-/// Setter for the `REQUEST_RESPONSE_BUFFER` state variable
-pub fn set_buffer(buffer: HashMap<String, bool>) {
-    REQUEST_RESPONSE_BUFFER.with(|store| *store.borrow_mut() = buffer);
+/// Setter for `REQUEST_TIMEOUT_CONFIG` state variable
+pub fn set_request_timeout_config(new_config: RequestTimeoutConfig) {
+    REQUEST_TIMEOUT_CONFIG.with(|rtc| {
+        rtc.borrow_mut()
+            .set(Candid(new_config))
+            .expect("failed to set the request timeout config")
+    });
 }
 
 /// Getter for `VERIFIER_CANISTER` state variable
 pub fn get_verifier_canister() -> Option<Principal> {
-    let verifier_canister = VERIFIER_CANISTER.with(|vc| vc.borrow().clone());
-    verifier_canister
+    VERIFIER_CANISTER.with(|vc| vc.borrow().get().0)
 }
 
-/// Getter for `FEE` state variable
-pub fn get_transaction_fee() -> u128 {
-    let fee = FEE.with(|vc| vc.borrow().clone());
-    fee
+/// Getter for `FEE_SCHEDULE` state variable
+pub fn get_fee_schedule() -> FeeSchedule {
+    FEE_SCHEDULE.with(|fs| fs.borrow().get().0.clone())
 }
 
 /// Setter for `VERIFIER_CANISTER` state variable
 pub fn set_verifier_canister(new_verifier_canister: Option<Principal>) {
-    VERIFIER_CANISTER
-        .with(|old_verifier_canister| *old_verifier_canister.borrow_mut() = new_verifier_canister);
+    VERIFIER_CANISTER.with(|vc| {
+        vc.borrow_mut()
+            .set(Candid(new_verifier_canister))
+            .expect("failed to set the verifier canister")
+    });
+}
+
+/// Setter for `FEE_SCHEDULE` state variable
+pub fn set_fee_schedule(new_fee_schedule: FeeSchedule) {
+    FEE_SCHEDULE.with(|fs| {
+        fs.borrow_mut()
+            .set(Candid(new_fee_schedule))
+            .expect("failed to set the fee schedule")
+    });
+}
+
+/// Replace the flat fee in the `FEE` state variable with a fee schedule charging the same fee for every request
+pub fn migrate_flat_fee() {
+    let fee = FEE.with(|fee| fee.borrow().get().0);
+    set_fee_schedule(flat_fee_schedule(fee));
+}
+
+/// Getter for `PRICING_CONFIG` state variable
+pub fn get_pricing_config() -> PricingConfig {
+    PRICING_CONFIG.with(|pc| pc.borrow().get().0.clone())
+}
+
+/// Setter for `PRICING_CONFIG` state variable
+pub fn set_pricing_config(new_pricing_config: PricingConfig) {
+    PRICING_CONFIG.with(|pc| {
+        pc.borrow_mut()
+            .set(Candid(new_pricing_config))
+            .expect("failed to set the pricing config")
+    });
+}
+
+/// Getter for a single source definition in the `SOURCE_DEFINITIONS` state variable
+pub fn get_source_definition(name: &str) -> Option<SourceDefinition> {
+    SOURCE_DEFINITIONS.with(|sd| sd.borrow().get(&name.to_string()).map(|sd| sd.0))
+}
+
+/// Getter for the `SOURCE_DEFINITIONS` state variable
+pub fn get_source_definitions() -> HashMap<String, SourceDefinition> {
+    SOURCE_DEFINITIONS.with(|sd| sd.borrow().iter().map(|(name, sd)| (name, sd.0)).collect())
+}
+
+/// Add or replace a source definition in the `SOURCE_DEFINITIONS` state variable
+pub fn add_source_definition(source_definition: SourceDefinition) {
+    SOURCE_DEFINITIONS.with(|sd| {
+        sd.borrow_mut()
+            .insert(source_definition.name.clone(), Candid(source_definition))
+    });
+}
+
+/// Remove a source definition from the `SOURCE_DEFINITIONS` state variable
+pub fn remove_source_definition(name: &str) -> Option<SourceDefinition> {
+    SOURCE_DEFINITIONS.with(|sd| sd.borrow_mut().remove(&name.to_string()).map(|sd| sd.0))
+}
+
+/// Getter for a single cached price in the `PRICE_CACHE` state variable
+pub fn get_cached_price(pair: &str) -> Option<CachedPrice> {
+    PRICE_CACHE.with(|pc| pc.borrow().get(&pair.to_string()).map(|cp| cp.0))
+}
+
+/// Add or replace the cached price of a pair in the `PRICE_CACHE` state variable
+pub fn set_cached_price(cached_price: CachedPrice) {
+    PRICE_CACHE.with(|pc| {
+        pc.borrow_mut()
+            .insert(cached_price.pair.clone(), Candid(cached_price))
+    });
+}
+
+/// Getter for a single subscription in the `SUBSCRIPTIONS` state variable
+pub fn get_subscription(id: &str) -> Option<Subscription> {
+    SUBSCRIPTIONS.with(|s| s.borrow().get(&id.to_string()).map(|s| s.0))
+}
+
+/// Add or replace a subscription in the `SUBSCRIPTIONS` state variable
+pub fn set_subscription(subscription: Subscription) {
+    SUBSCRIPTIONS.with(|s| {
+        s.borrow_mut()
+            .insert(subscription.id.clone(), Candid(subscription))
+    });
+}
+
+/// Remove a subscription from the `SUBSCRIPTIONS` state variable
+pub fn remove_subscription(id: &str) -> Option<Subscription> {
+    SUBSCRIPTIONS.with(|s| s.borrow_mut().remove(&id.to_string()).map(|s| s.0))
+}
+
+/// Getter for the `SUBSCRIPTIONS` state variable
+pub fn get_subscriptions() -> HashMap<String, Subscription> {
+    SUBSCRIPTIONS.with(|s| s.borrow().iter().map(|(id, s)| (id, s.0)).collect())
+}
+
+/// Getter for the account of a caller in the `LEDGER` state variable,
+/// a caller without an account has an empty one
+pub fn get_ledger_account(principal: &Principal) -> LedgerAccount {
+    LEDGER.with(|l| {
+        l.borrow()
+            .get(&principal.to_text())
+            .map(|account| account.0)
+            .unwrap_or_default()
+    })
+}
+
+/// Setter for the account of a caller in the `LEDGER` state variable
+pub fn set_ledger_account(principal: &Principal, account: LedgerAccount) {
+    LEDGER.with(|l| l.borrow_mut().insert(principal.to_text(), Candid(account)));
+}
+
+/// Getter for `ACCESS_MODE` state variable
+pub fn get_access_mode() -> AccessMode {
+    ACCESS_MODE.with(|am| am.borrow().get().0.clone())
+}
+
+/// Setter for `ACCESS_MODE` state variable
+pub fn set_access_mode(new_access_mode: AccessMode) {
+    ACCESS_MODE.with(|am| {
+        am.borrow_mut()
+            .set(Candid(new_access_mode))
+            .expect("failed to set the access mode")
+    });
+}
+
+/// Getter for the rule a principal is whitelisted with in the `ACCESS_WHITELIST` state variable,
+/// `None` when it is not whitelisted
+pub fn get_whitelist_rule(principal: &Principal) -> Option<AccessRule> {
+    ACCESS_WHITELIST.with(|wl| wl.borrow().get(&principal.to_text()).map(|rule| rule.0))
+}
+
+/// Whitelist a principal with a rule in the `ACCESS_WHITELIST` state variable,
+/// or replace the rule it is whitelisted with
+pub fn set_whitelist_rule(principal: &Principal, rule: AccessRule) {
+    ACCESS_WHITELIST.with(|wl| wl.borrow_mut().insert(principal.to_text(), Candid(rule)));
+}
+
+/// Remove a principal from the `ACCESS_WHITELIST` state variable
+pub fn remove_from_whitelist(principal: &Principal) {
+    ACCESS_WHITELIST.with(|wl| wl.borrow_mut().remove(&principal.to_text()));
+}
+
+/// Get the whitelisted principals along with their rules from the `ACCESS_WHITELIST` state variable
+pub fn get_whitelist() -> Vec<(Principal, AccessRule)> {
+    ACCESS_WHITELIST.with(|wl| {
+        wl.borrow()
+            .iter()
+            .map(|(principal, rule)| (principal_from_key(&principal), rule.0))
+            .collect()
+    })
+}
+
+/// Check if a principal is in the `ACCESS_BLACKLIST` state variable
+pub fn is_blacklisted(principal: &Principal) -> bool {
+    ACCESS_BLACKLIST.with(|bl| bl.borrow().contains_key(&principal.to_text()))
+}
+
+/// Add a principal to the `ACCESS_BLACKLIST` state variable
+pub fn add_to_blacklist(principal: &Principal) {
+    ACCESS_BLACKLIST.with(|bl| bl.borrow_mut().insert(principal.to_text(), true));
+}
+
+/// Remove a principal from the `ACCESS_BLACKLIST` state variable
+pub fn remove_from_blacklist(principal: &Principal) {
+    ACCESS_BLACKLIST.with(|bl| bl.borrow_mut().remove(&principal.to_text()));
 }
 
-/// Setter for `FEE` state variable
-pub fn set_transaction_fee(new_fee: u128) {
-    FEE.with(|old_fee| *old_fee.borrow_mut() = new_fee);
+/// Get the principals in the `ACCESS_BLACKLIST` state variable
+pub fn get_blacklist() -> Vec<Principal> {
+    ACCESS_BLACKLIST.with(|bl| {
+        bl.borrow()
+            .iter()
+            .map(|(principal, _)| principal_from_key(&principal))
+            .collect()
+    })
+}
+
+/// Getter for `RATE_LIMIT_CONFIG` state variable
+pub fn get_rate_limit_config() -> RateLimitConfig {
+    RATE_LIMIT_CONFIG.with(|rl| rl.borrow().get().0.clone())
+}
+
+/// Setter for `RATE_LIMIT_CONFIG` state variable
+pub fn set_rate_limit_config(new_rate_limit_config: RateLimitConfig) {
+    RATE_LIMIT_CONFIG.with(|rl| {
+        rl.borrow_mut()
+            .set(Candid(new_rate_limit_config))
+            .expect("failed to set the rate limit config")
+    });
+}
+
+/// Get the limits on the requests of a principal, which are the ones it is given in the
+/// `PRINCIPAL_RATE_LIMIT_CONFIGS` state variable or the ones of the `RATE_LIMIT_CONFIG` state variable
+pub fn get_principal_rate_limit_config(principal: &Principal) -> RateLimitConfig {
+    PRINCIPAL_RATE_LIMIT_CONFIGS
+        .with(|rl| rl.borrow().get(&principal.to_text()).map(|config| config.0))
+        .unwrap_or_else(get_rate_limit_config)
+}
+
+/// Give a principal its own limits in the `PRINCIPAL_RATE_LIMIT_CONFIGS` state variable,
+/// or remove them so that the limits of the `RATE_LIMIT_CONFIG` state variable apply to it
+pub fn set_principal_rate_limit_config(principal: &Principal, config: Option<RateLimitConfig>) {
+    PRINCIPAL_RATE_LIMIT_CONFIGS.with(|rl| {
+        let mut rl = rl.borrow_mut();
+        match config {
+            Some(config) => rl.insert(principal.to_text(), Candid(config)),
+            None => rl.remove(&principal.to_text()),
+        }
+    });
+}
+
+/// Getter for the usage of a principal in the `QUOTA_USAGE` state variable
+pub fn get_quota_usage(principal: &Principal) -> QuotaUsage {
+    QUOTA_USAGE.with(|qu| {
+        qu.borrow()
+            .get(&principal.to_text())
+            .map(|usage| usage.0)
+            .unwrap_or_default()
+    })
+}
+
+/// Setter for the usage of a principal in the `QUOTA_USAGE` state variable
+pub fn set_quota_usage(principal: &Principal, usage: QuotaUsage) {
+    QUOTA_USAGE.with(|qu| qu.borrow_mut().insert(principal.to_text(), Candid(usage)));
+}
+
+/// Get the principal a state variable keyed by the text of principals is keyed with
+fn principal_from_key(key: &str) -> Principal {
+    Principal::from_text(key).expect("invalid principal key")
+}
+
+/// Whitelist the principals of the copy of the whitelist of `verity_ic` in the `WHITELIST` state variable
+/// without any restriction, which is how they were whitelisted before the access lists
+pub fn migrate_whitelist() {
+    let whitelist = WHITELIST.with(|wl| wl.borrow().get().0.clone());
+    add_legacy_whitelist(whitelist);
+}
+
+fn add_legacy_whitelist(whitelist: HashMap<Principal, bool>) {
+    for (principal, is_whitelisted) in whitelist {
+        if is_whitelisted {
+            set_whitelist_rule(&principal, AccessRule::default());
+        }
+    }
+}
+
+/// Write the state serialized by the versions before the state was kept in stable structures
+/// into the state variables, the requests still pending are recorded as submitted at `now` (in nanoseconds).
+/// The state those versions did not have, e.g the pricing config, is left to its default
+pub fn migrate_legacy_state(legacy_state: LegacyState, now: u64) {
+    let (buffer, legacy_whitelist, verifier_canister, fee) = legacy_state;
+
+    // the owners of the requests pending before their records were kept are not known,
+    // they are only kept pending so that they can still be answered
+    REQUEST_RESPONSE_BUFFER.with(|rc| {
+        let mut rc = rc.borrow_mut();
+        for id in buffer.into_keys() {
+            rc.insert(id, now);
+        }
+    });
+    add_legacy_whitelist(legacy_whitelist);
+    set_verifier_canister(verifier_canister);
+    set_fee_schedule(flat_fee_schedule(fee));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_baseline_legacy_state() {
+        let verifier_canister = Principal::from_text("bkyz2-fmaaa-aaaaa-qaaaq-cai").unwrap();
+        let whitelisted = Principal::anonymous();
+        let baseline_state = (
+            HashMap::from([("1_abcde".to_string(), true)]),
+            HashMap::from([(whitelisted, true)]),
+            Some(verifier_canister),
+            10u128,
+        );
+        // `stable_restore` decodes the arguments encoded by `stable_save`
+        let bytes = candid::encode_args(baseline_state).unwrap();
+        let legacy_state: LegacyState = candid::decode_args(&bytes).unwrap();
+
+        migrate_legacy_state(legacy_state, 1_000);
+
+        assert!(is_pending_request("1_abcde"));
+        assert_eq!(
+            get_pending_requests_before(1_000),
+            vec!["1_abcde".to_string()]
+        );
+        assert_eq!(
+            get_whitelist_rule(&whitelisted),
+            Some(AccessRule::default())
+        );
+        assert_eq!(get_verifier_canister(), Some(verifier_canister));
+        assert_eq!(get_fee_schedule(), flat_fee_schedule(10));
+        assert_eq!(get_pricing_config(), PricingConfig::default());
+        assert!(get_source_definitions().is_empty());
+        assert!(get_subscriptions().is_empty());
+    }
 }
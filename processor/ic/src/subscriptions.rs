@@ -0,0 +1,116 @@
+use types::{CurrencyPair, Subscription};
+
+use crate::aggregation::relative_deviation;
+
+/// Check if the prices of a subscription should be pushed to the subscriber,
+/// which is the case when its heartbeat has elapsed since the last update
+/// or when the price of any of its pairs deviates from the last price pushed by at least its deviation threshold
+pub fn is_update_due(subscription: &Subscription, pairs: &[CurrencyPair], now: u64) -> bool {
+    if subscription.is_heartbeat_due(now) {
+        return true;
+    }
+
+    let Some(deviation_threshold) = subscription.deviation_threshold else {
+        return false;
+    };
+    pairs
+        .iter()
+        .filter(|pair| pair.error.is_none())
+        .filter_map(|pair| Some((pair.price?, subscription.last_prices.get(&pair.repr))))
+        .any(|(price, last_price)| match last_price {
+            Some(last_price) => relative_deviation(price, *last_price) >= deviation_threshold,
+            // a pair which could not be priced before is pushed as soon as it is
+            None => true,
+        })
+}
+
+/// Record the prices pushed to the subscriber as the reference for the next deviations
+pub fn record_update(subscription: &mut Subscription, pairs: &[CurrencyPair], now: u64) {
+    for pair in pairs.iter().filter(|pair| pair.error.is_none()) {
+        if let Some(price) = pair.price {
+            subscription.last_prices.insert(pair.repr.clone(), price);
+        }
+    }
+    subscription.last_update = Some(now);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candid::Principal;
+
+    fn subscription(last_update: Option<u64>, deviation_threshold: Option<f64>) -> Subscription {
+        Subscription {
+            id: "1".to_string(),
+            owner: Principal::anonymous(),
+            pairs: vec![],
+            heartbeat: 300,
+            deviation_threshold,
+            balance: 100,
+            last_prices: [("BTC".to_string(), 100.0)].into_iter().collect(),
+            last_update,
+        }
+    }
+
+    fn priced_pair(repr: &str, price: f64) -> CurrencyPair {
+        let mut pair = CurrencyPair::try_from(repr.to_string()).unwrap();
+        pair.price = Some(price);
+        pair
+    }
+
+    #[test]
+    fn test_update_due_on_heartbeat() {
+        let pairs = vec![priced_pair("BTC", 100.0)];
+
+        assert!(is_update_due(&subscription(None, None), &pairs, 1000));
+        assert!(!is_update_due(&subscription(Some(800), None), &pairs, 1000));
+        assert!(is_update_due(&subscription(Some(700), None), &pairs, 1000));
+    }
+
+    #[test]
+    fn test_update_due_on_deviation() {
+        let subscription = subscription(Some(900), Some(0.01));
+
+        assert!(!is_update_due(
+            &subscription,
+            &[priced_pair("BTC", 100.5)],
+            1000
+        ));
+        assert!(is_update_due(
+            &subscription,
+            &[priced_pair("BTC", 98.0)],
+            1000
+        ));
+        // a pair priced for the first time
+        assert!(is_update_due(
+            &subscription,
+            &[priced_pair("ETH", 5.0)],
+            1000
+        ));
+    }
+
+    #[test]
+    fn test_record_update() {
+        let mut subscription = subscription(None, Some(0.01));
+        let mut failed_pair = priced_pair("ETH", 5.0);
+        failed_pair.error = Some(types::ADCError::Other("failed".to_string()));
+
+        record_update(
+            &mut subscription,
+            &[priced_pair("BTC", 110.0), failed_pair],
+            1000,
+        );
+
+        assert_eq!(subscription.last_prices.get("BTC"), Some(&110.0));
+        assert_eq!(subscription.last_prices.get("ETH"), None);
+        assert_eq!(subscription.last_update, Some(1000));
+    }
+
+    #[test]
+    fn test_may_be_due() {
+        // only the subscriptions with a deviation threshold can be due before their heartbeat
+        assert!(!subscription(Some(800), None).may_be_due(1000));
+        assert!(subscription(Some(700), None).may_be_due(1000));
+        assert!(subscription(Some(800), Some(0.01)).may_be_due(1000));
+    }
+}
@@ -1,12 +1,23 @@
 use crate::{
-    sources::{get_asset_price_from_proofs, request_proof_verification},
+    access::{check_access, use_quota},
+    ledger,
+    rate_limit::count_request,
+    sources::{
+        get_asset_price_from_proofs, get_market_data_from_proofs, get_series_from_proofs,
+        pyth::Pyth, request_proof_verification, TokenPrice, VerifiedProofs,
+    },
     state,
 };
 use candid::Principal;
-use ic_cdk::api::call::{msg_cycles_accept128, RejectionCode};
+use ic_cdk::api::call::{msg_cycles_accept128, msg_cycles_available128, RejectionCode};
 use ic_cdk::api::time;
-use types::{ADCResponse, ADCResponseV2, CurrencyPair, ProofTypes, Token};
-use verity_ic::verify::types::ProofResponse;
+use std::collections::HashSet;
+use types::{
+    ADCError, ADCResponse, ADCResponseV2, AccessMode, CachedPrice, CurrencyPair, Endpoint,
+    ErrorResponse, MarketData, PriceRoute, PriceSeries, PricingConfig, ProofTypes, QuotaUsage,
+    Request, RequestKind, RequestOpts, RequestStatus, Response, SeriesOpts, SourceFailure,
+    SourcePrice, Token, REQUEST_RETENTION,
+};
 
 // send a response to the canister who requested pricing data
 pub fn send_adc_response(
@@ -25,53 +36,449 @@ pub fn send_adc_response_v2(
     return canister_response;
 }
 
-pub async fn get_proof_response(
-    proof_types: &Vec<ProofTypes>,
+/// Verify the proofs attached to all the currency pairs of a response at once,
+/// proofs shared by several tokens (e.g from batched requests) are only verified once
+pub async fn verify_currency_pair_proofs(
+    currency_pairs: &[CurrencyPair],
     notary_pubkey: &String,
-) -> Vec<ProofResponse> {
-    let stringified_proofs: Vec<String> = proof_types
-        .iter()
-        .map(|pt: &ProofTypes| pt.to_string())
-        .collect();
+) -> Result<VerifiedProofs, ADCError> {
+    let mut seen_proofs: HashSet<String> = HashSet::new();
+    let mut stringified_proofs: Vec<String> = vec![];
+    for currency_pair in currency_pairs {
+        // the proofs of a pair priced through legs are attached to the tokens of its legs
+        // and the proofs of a pair which cannot be priced are not verified
+        let priced_pairs: &[CurrencyPair] =
+            match (currency_pair.error.is_none(), currency_pair.legs.is_empty()) {
+                (false, _) => &[],
+                (true, true) => std::slice::from_ref(currency_pair),
+                (true, false) => &currency_pair.legs[..],
+            };
+        let tokens = priced_pairs
+            .iter()
+            .flat_map(|pair| std::iter::once(&pair.base).chain(pair.quote.as_ref()));
+        let price_proofs = tokens.filter_map(|token| token.proofs.as_ref()).flatten();
+        let data_proofs = currency_pair
+            .market_data_proofs
+            .iter()
+            .chain(currency_pair.series_proofs.iter())
+            .flatten();
+        for proof_type in price_proofs.chain(data_proofs) {
+            let proof = proof_type.to_string();
+            if seen_proofs.insert(proof.clone()) {
+                stringified_proofs.push(proof);
+            }
+        }
+    }
+
+    if stringified_proofs.is_empty() {
+        return Ok(VerifiedProofs::new());
+    }
 
     // request proof response from verification canister
-    request_proof_verification(&stringified_proofs, notary_pubkey).await
+    // the responses are provided in the same order as the proofs
+    let verification_response_proofs =
+        request_proof_verification(&stringified_proofs, notary_pubkey).await?;
+
+    Ok(stringified_proofs
+        .into_iter()
+        .zip(verification_response_proofs)
+        .collect())
 }
-// Provided a token and a notary public key for the notary used to generate the proofs attached to the tokens
-// verify/decrypt the proofs and come to a concensus on the token price
-pub async fn get_token_price(token: &Token, notary_pubkey: &String) -> anyhow::Result<f64> {
-    // get the proofs in a stringified form
+
+// Provided a token and the verification responses of the proofs attached to the tokens
+// parse the proofs and come to a concensus on the token price, as of `at_timestamp` for historical prices
+// the prices used are recorded in `sources` and the sources which failed are recorded in `failed_sources`
+pub fn get_token_price(
+    token: &Token,
+    verified_proofs: &VerifiedProofs,
+    pricing_config: &PricingConfig,
+    at_timestamp: Option<u64>,
+    sources: &mut Vec<SourcePrice>,
+    failed_sources: &mut Vec<SourceFailure>,
+) -> Result<TokenPrice, ADCError> {
     let proof_types = token.proofs.as_ref().unwrap();
 
-    let verification_response_proofs = get_proof_response(proof_types, notary_pubkey).await;
     // parse the proof response based on the corresponding proof type
     // to get the price in the response of the http response body of the verified request
-    // use the proof type to parse the json as either a pyth or redstone proof
-    let asset_price = get_asset_price_from_proofs(proof_types, &verification_response_proofs);
+    let asset_price = get_asset_price_from_proofs(
+        &token.ticker,
+        proof_types,
+        verified_proofs,
+        pricing_config,
+        at_timestamp,
+        sources,
+        failed_sources,
+    );
 
     asset_price
 }
 
 /// Derive the prices for both the base and quote token(if exists)
 /// And calculate the pair price as a whole
-pub async fn get_currency_pair_price(
-    currency_pair: &CurrencyPair,
-    notary_pubkey: &String,
-) -> anyhow::Result<f64> {
-    // get base price
+/// unless the proofs of the base token provide the price of the pair directly,
+/// which is only trusted when its feed is proven to be a feed of the pair
+/// the prices are the ones published close to `at_timestamp` when historical prices are requested
+/// the prices used and the sources which failed to provide a valid price are recorded on the currency pair
+pub fn get_currency_pair_price(
+    currency_pair: &mut CurrencyPair,
+    verified_proofs: &VerifiedProofs,
+    pricing_config: &PricingConfig,
+    at_timestamp: Option<u64>,
+) -> Result<TokenPrice, ADCError> {
+    if !currency_pair.legs.is_empty() {
+        return get_multi_hop_price(currency_pair, verified_proofs, pricing_config, at_timestamp);
+    }
+
+    let is_direct = matches!(currency_pair.route, Some(PriceRoute::Direct));
+
+    // get base price, which is the price of the pair itself for a direct route
     let base_token = &currency_pair.base;
-    let mut token_price = get_token_price(base_token, notary_pubkey).await?;
+    let mut token_price = if is_direct {
+        let direct_proofs = match &currency_pair.quote {
+            Some(quote_token) => get_direct_feed_proofs(base_token, quote_token, verified_proofs)?,
+            None => base_token.proofs.clone().unwrap_or_default(),
+        };
+        get_asset_price_from_proofs(
+            &currency_pair.repr,
+            &direct_proofs,
+            verified_proofs,
+            pricing_config,
+            at_timestamp,
+            &mut currency_pair.sources,
+            &mut currency_pair.failed_sources,
+        )?
+    } else {
+        get_token_price(
+            base_token,
+            verified_proofs,
+            pricing_config,
+            at_timestamp,
+            &mut currency_pair.sources,
+            &mut currency_pair.failed_sources,
+        )?
+    };
 
     // get quote price and potentially divide the base price by it
-    if let Some(quote_token) = &currency_pair.quote {
-        let quote_token_price = get_token_price(quote_token, notary_pubkey).await?;
+    if let Some(quote_token) = currency_pair.quote.as_ref().filter(|_| !is_direct) {
+        let quote_token_price = get_token_price(
+            quote_token,
+            verified_proofs,
+            pricing_config,
+            at_timestamp,
+            &mut currency_pair.sources,
+            &mut currency_pair.failed_sources,
+        )?;
 
-        token_price /= quote_token_price;
+        token_price = token_price.div(&quote_token_price);
     }
 
+    // record the age of the oldest price used
+    let now = get_current_timestamp();
+    currency_pair.age = currency_pair
+        .sources
+        .iter()
+        .filter_map(|source| source.timestamp)
+        .map(|timestamp| now.saturating_sub(timestamp))
+        .max();
+
     Ok(token_price)
 }
 
+/// Get the price proofs of the direct feed of a currency pair,
+/// after checking with the feed info proof of each feed that it provides the price of the base in the quote
+fn get_direct_feed_proofs(
+    base_token: &Token,
+    quote_token: &Token,
+    verified_proofs: &VerifiedProofs,
+) -> Result<Vec<ProofTypes>, ADCError> {
+    let (feed_info_proofs, price_proofs): (Vec<ProofTypes>, Vec<ProofTypes>) = base_token
+        .proofs
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .partition(|proof| matches!(proof, ProofTypes::PythFeedInfo { .. }));
+
+    for price_proof in &price_proofs {
+        let ProofTypes::PythBatch { feed_id, .. } = price_proof else {
+            return Err(ADCError::InvalidResponse(format!(
+                "{} does not provide a direct feed",
+                price_proof.source()
+            )));
+        };
+        let feed_info = feed_info_proofs
+            .iter()
+            .find(|proof| match proof {
+                ProofTypes::PythFeedInfo {
+                    feed_id: info_feed_id,
+                    ..
+                } => info_feed_id == feed_id,
+                _ => false,
+            })
+            .and_then(|proof| verified_proofs.get(&proof.to_string()))
+            .ok_or_else(|| {
+                ADCError::VerificationFailed(format!(
+                    "Feed info of the price feed {} not available",
+                    feed_id
+                ))
+            })?;
+
+        Pyth::check_feed_info(
+            feed_id,
+            &base_token.ticker,
+            &quote_token.ticker,
+            feed_info.get_http_response_body(),
+        )
+        .map_err(|err| ADCError::InvalidResponse(err.to_string()))?;
+    }
+
+    Ok(price_proofs)
+}
+
+/// Derive the price of each leg of a currency pair and multiply them to get the pair price
+/// the price of each leg is recorded on the leg and the prices used by all the legs
+/// are recorded on the currency pair as well, the legs must form a route from the base to the quote
+fn get_multi_hop_price(
+    currency_pair: &mut CurrencyPair,
+    verified_proofs: &VerifiedProofs,
+    pricing_config: &PricingConfig,
+    at_timestamp: Option<u64>,
+) -> Result<TokenPrice, ADCError> {
+    currency_pair
+        .validate_legs()
+        .map_err(|err| ADCError::Other(err.to_string()))?;
+
+    let mut pair_price = TokenPrice::one();
+    for leg in currency_pair.legs.iter_mut() {
+        let leg_price = get_currency_pair_price(leg, verified_proofs, pricing_config, at_timestamp);
+        currency_pair.sources.extend(leg.sources.clone());
+        currency_pair
+            .failed_sources
+            .extend(leg.failed_sources.clone());
+
+        match leg_price {
+            Ok(leg_price) => {
+                set_currency_pair_price(leg, &leg_price, pricing_config);
+                pair_price = pair_price.mul(&leg_price);
+            }
+            Err(err) => {
+                leg.error = Some(err.clone());
+                return Err(err);
+            }
+        }
+    }
+
+    // the age of the pair is the age of its oldest leg
+    currency_pair.age = currency_pair.legs.iter().filter_map(|leg| leg.age).max();
+
+    Ok(pair_price)
+}
+
+/// Derive the market data requested of the base token of a currency pair from its market data proofs
+/// the sources which failed to provide valid market data are recorded on the currency pair
+pub fn get_currency_pair_market_data(
+    currency_pair: &mut CurrencyPair,
+    verified_proofs: &VerifiedProofs,
+    opts: &RequestOpts,
+) -> Result<MarketData, ADCError> {
+    let proof_types = currency_pair.market_data_proofs.clone().unwrap_or_default();
+
+    get_market_data_from_proofs(
+        &currency_pair.base.ticker,
+        &proof_types,
+        verified_proofs,
+        opts,
+        &mut currency_pair.failed_sources,
+    )
+}
+
+/// Derive the price series requested of the base token of a currency pair from its series proofs
+/// over the window ending at `to`, the sources which failed to provide valid prices are recorded on the currency pair
+pub fn get_currency_pair_series(
+    currency_pair: &mut CurrencyPair,
+    verified_proofs: &VerifiedProofs,
+    series_opts: &SeriesOpts,
+    to: u64,
+) -> Result<PriceSeries, ADCError> {
+    let proof_types = currency_pair.series_proofs.clone().unwrap_or_default();
+
+    get_series_from_proofs(
+        &currency_pair.base.ticker,
+        &proof_types,
+        verified_proofs,
+        series_opts,
+        to,
+        &mut currency_pair.failed_sources,
+    )
+}
+
+/// Record a price derived for a currency pair on it,
+/// the exact price is provided with the decimals requested if any
+pub fn set_currency_pair_price(
+    currency_pair: &mut CurrencyPair,
+    pair_price: &TokenPrice,
+    pricing_config: &PricingConfig,
+) {
+    currency_pair.price = Some(pair_price.price);
+    currency_pair.fixed_price = pricing_config
+        .decimals
+        .and_then(|decimals| pair_price.fixed_price.as_ref()?.rescale(decimals));
+}
+
+/// Keep the price of a currency pair as the last verified price of the pair,
+/// only the latest prices derived from every source with the pricing config of the processor are kept
+/// so that a request with looser options cannot affect the prices served to other callers
+pub fn cache_currency_pair_price(currency_pair: &CurrencyPair, opts: &RequestOpts) {
+    let Some(price) = currency_pair
+        .price
+        .filter(|_| currency_pair.error.is_none())
+    else {
+        return;
+    };
+    let pricing_config = state::get_pricing_config();
+    let request_config = PricingConfig {
+        decimals: pricing_config.decimals,
+        ..pricing_config.with_overrides(opts)
+    };
+    if request_config != pricing_config || currency_pair.requested_sources.is_some() {
+        return;
+    }
+
+    let now = get_current_timestamp();
+    state::set_cached_price(CachedPrice {
+        pair: currency_pair.repr.clone(),
+        price,
+        fixed_price: currency_pair.fixed_price.clone(),
+        timestamp: now.saturating_sub(currency_pair.age.unwrap_or_default()),
+        verified_at: now,
+        sources: currency_pair.sources.clone(),
+        route: currency_pair.route.clone(),
+    });
+}
+
+/// Get a response to a request from the cached prices,
+/// provided the request only asks for the latest prices and every pair has a cached price fresher than `cache_max_age`
+pub fn get_cached_response(price_request: &Request) -> Option<Response> {
+    let opts = &price_request.opts;
+    let max_age = opts.cache_max_age?;
+    if !opts.price
+        || opts.wants_market_data()
+        || opts.series.is_some()
+        || opts.at_timestamp.is_some()
+    {
+        return None;
+    }
+
+    let now = get_current_timestamp();
+    let decimals = state::get_pricing_config().with_overrides(opts).decimals;
+    let mut response = Response::from(price_request.clone());
+    // every pair requested must be a valid pair to be answered from the cache
+    if response.pairs.is_empty() || response.pairs.len() != price_request.pairs.len() {
+        return None;
+    }
+
+    for currency_pair in response.pairs.iter_mut() {
+        let cached_price = state::get_cached_price(&currency_pair.repr)
+            .filter(|cached_price| now.saturating_sub(cached_price.timestamp) <= max_age)?;
+
+        currency_pair.price = Some(cached_price.price);
+        currency_pair.fixed_price = decimals
+            .zip(cached_price.fixed_price)
+            .and_then(|(decimals, fixed_price)| fixed_price.rescale(decimals));
+        currency_pair.age = Some(now.saturating_sub(cached_price.timestamp));
+        currency_pair.sources = cached_price.sources;
+        currency_pair.route = cached_price.route;
+    }
+    response.processed = true;
+
+    Some(response)
+}
+
+/// Record whether a request was fulfilled or failed from the response sent to its owner
+pub fn record_request_outcome<T>(id: &str, response: &Result<T, ErrorResponse>) {
+    let status = match response {
+        Ok(_) => RequestStatus::Fulfilled,
+        Err(error_response) => RequestStatus::Failed(error_response.error.clone()),
+    };
+    state::set_request_status(id, status, get_current_timestamp());
+}
+
+/// Expire the requests the orchestrator did not serve within the timeout,
+/// their owners are notified with a timeout error and the fee paid is credited back to their prepaid balance
+/// if the processor is configured to,
+/// then the records of the requests completed before the retention period are pruned
+pub fn expire_timed_out_requests() {
+    let config = state::get_request_timeout_config();
+    let now = get_current_timestamp();
+    let cutoff = time().saturating_sub(config.timeout.saturating_mul(1_000_000_000));
+
+    for id in state::get_pending_requests_before(cutoff) {
+        state::remove_pending_request(&id);
+        // the requests made before their records were kept cannot be notified
+        let Some(request_record) = state::set_request_status(&id, RequestStatus::Expired, now)
+        else {
+            continue;
+        };
+
+        let owner = request_record.owner;
+        let error = ADCError::Timeout(format!(
+            "request {} was not served within {}s",
+            id, config.timeout
+        ));
+        let error_response = ErrorResponse::new(id, owner, error);
+        // a notification which cannot be delivered does not prevent the other requests from expiring
+        let _ = match request_record.kind {
+            RequestKind::Price { .. } => send_adc_response(owner, Err(error_response)),
+            RequestKind::Url { .. } => send_adc_response_v2(owner, Err(error_response)),
+        };
+
+        if config.refund_expired {
+            refund_request_fee(&request_record.id);
+        }
+    }
+
+    state::prune_request_records(now.saturating_sub(REQUEST_RETENTION));
+}
+
+/// Get the current time of the IC in seconds
+pub fn get_current_timestamp() -> u64 {
+    time() / 1_000_000_000
+}
+
+/// Validate the options of a price request for `pairs` against the pricing config of the processor,
+/// the same options are accepted by every endpoint prices can be requested through
+pub fn validate_request_opts(opts: &RequestOpts, pairs: &[String]) -> anyhow::Result<()> {
+    if !opts.wants_any_data() {
+        anyhow::bail!("neither the price, any market data nor a price series is requested");
+    }
+    if let Some(series) = &opts.series {
+        series.validate()?;
+        // the series is the one of the base token in USD, which is not the price of a pair quoted in another token
+        let quoted_pair = pairs.iter().find(|pair| {
+            pair.rsplit_once('/')
+                .is_some_and(|(_, quote)| !quote.trim().eq_ignore_ascii_case("USD"))
+        });
+        if let Some(pair) = quoted_pair {
+            anyhow::bail!(
+                "a price series is only available for pairs priced in USD, {} is not",
+                pair
+            );
+        }
+    }
+    state::get_pricing_config()
+        .with_overrides(opts)
+        .validate()?;
+    if let Some(at_timestamp) = opts.at_timestamp {
+        if at_timestamp > get_current_timestamp() {
+            anyhow::bail!("at_timestamp must not be in the future");
+        }
+        if opts.wants_market_data() {
+            anyhow::bail!("market data is only available for the latest prices");
+        }
+    }
+
+    Ok(())
+}
+
 pub async fn generate_request_url() -> String {
     // derive the request id
     let (random_bytes,): (Vec<u8>,) =
@@ -85,15 +492,70 @@ pub async fn generate_request_url() -> String {
     format!("{}_{}", time().to_string(), random_hex_byte)
 }
 
-pub async fn check_gas() {
-    // Define the fee in cycles (for example, 1 trillion cycles)
-    let fee: u128 = state::get_transaction_fee();
+/// Check that a caller is allowed to make a request through an endpoint under the access mode of the processor
+pub fn check_caller_access(caller: &Principal, endpoint: &Endpoint) -> Result<(), ADCError> {
+    check_access(
+        &state::get_access_mode(),
+        endpoint,
+        state::get_whitelist_rule(caller).as_ref(),
+        state::is_blacklisted(caller),
+    )
+}
 
-    // Accept up to `fee` cycles from the attached call
-    let accepted_cycles = msg_cycles_accept128(fee);
+/// Count a request made by a caller against the quota it is whitelisted with,
+/// the quotas only apply to the whitelisted principals in whitelist mode
+pub fn use_request_quota(caller: &Principal) {
+    if state::get_access_mode() != AccessMode::Whitelist {
+        return;
+    }
+    if let Some(mut rule) = state::get_whitelist_rule(caller) {
+        use_quota(&mut rule);
+        state::set_whitelist_rule(caller, rule);
+    }
+}
 
-    // If not enough cycles were attached, abort the call
-    if accepted_cycles < fee {
-        ic_cdk::api::trap("Insufficient cycles attached to cover fee.");
+/// Count a request of a caller against its rate limit and daily quota,
+/// the usage returned is to be recorded once the request is made
+pub fn check_rate_limit(caller: &Principal) -> Result<QuotaUsage, ADCError> {
+    let config = state::get_principal_rate_limit_config(caller);
+    let mut usage = state::get_quota_usage(caller);
+    count_request(&mut usage, &config, get_current_timestamp())?;
+
+    Ok(usage)
+}
+
+/// Charge the fee of a request to its caller from the cycles attached to the call,
+/// or from its prepaid balance when not enough cycles are attached, and record it on its ledger account
+pub fn charge_fee(payer: Principal, fee: u128) -> Result<u128, ADCError> {
+    let mut account = state::get_ledger_account(&payer);
+
+    // If not enough cycles were attached, charge the prepaid balance without accepting any of them
+    let available_cycles = msg_cycles_available128();
+    if available_cycles >= fee {
+        // Accept `fee` cycles from the attached call
+        msg_cycles_accept128(fee);
+        ledger::record_fee(&mut account, fee);
+    } else {
+        ledger::charge(&mut account, fee).map_err(|_| ADCError::FeeTooLow {
+            required: fee,
+            provided: available_cycles.saturating_add(account.balance),
+        })?;
     }
+    state::set_ledger_account(&payer, account);
+
+    Ok(fee)
+}
+
+/// Credit the fee paid for a request back to the prepaid balance of its owner
+pub fn refund_request_fee(id: &str) {
+    let Some(request_record) = state::get_request_record(id) else {
+        return;
+    };
+    if request_record.fee_paid == 0 {
+        return;
+    }
+
+    let mut account = state::get_ledger_account(&request_record.owner);
+    ledger::refund(&mut account, request_record.fee_paid);
+    state::set_ledger_account(&request_record.owner, account);
 }
@@ -0,0 +1,231 @@
+use std::fmt::Display;
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+use crate::SourceFailure;
+
+/// The kind of failure encountered while serving a request,
+/// so the calling canisters can branch on it instead of matching messages
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub enum ADCError {
+    /// the ticker is not supported by the source
+    UnknownTicker(String),
+    /// fewer sources than required provided a valid price for the ticker
+    InsufficientSources {
+        ticker: String,
+        available: u32,
+        required: u32,
+    },
+    /// the proofs could not be verified
+    VerificationFailed(String),
+    /// the price was published more than `max_age` seconds ago, or its age is not known
+    StalePrice { age: Option<u64>, max_age: u64 },
+    /// the historical price was published more than `tolerance` seconds away from the `requested` timestamp,
+    /// or its timestamp is not known
+    TimestampOutOfRange {
+        timestamp: Option<u64>,
+        requested: u64,
+        tolerance: u64,
+    },
+    /// the price deviates from the median of the source prices by more than `max_deviation`
+    DeviationTooHigh { price: f64, max_deviation: f64 },
+    /// the confidence interval of the price relative to the price exceeds `max_confidence_ratio`
+    ConfidenceTooWide {
+        price: f64,
+        confidence: f64,
+        max_confidence_ratio: f64,
+    },
+    /// the source responded with an unsuccessful http status
+    UpstreamHttpStatus { source: String, status: u16 },
+    /// the response of the source could not be parsed
+    InvalidResponse(String),
+    /// the source did not respond in time, or the request was not served before it expired
+    Timeout(String),
+    /// the cycles attached to the request do not cover the fee
+    FeeTooLow { required: u128, provided: u128 },
+    /// the prepaid balance of the caller does not cover the amount charged or withdrawn
+    InsufficientBalance { required: u128, available: u128 },
+    /// the caller is not allowed to make the request, with the reason why
+    AccessDenied(String),
+    /// the caller made too many requests at once, another request is allowed in `retry_after` seconds
+    RateLimited { retry_after: u64 },
+    /// the caller made as many requests as its daily quota allows, it is reset at `resets_at` (in seconds)
+    QuotaExceeded { quota: u64, resets_at: u64 },
+    /// any other failure
+    Other(String),
+}
+
+impl ADCError {
+    /// Get the failure shared by all the sources which failed to provide a price,
+    /// `None` when there are no failures or the sources failed for different reasons
+    pub fn common_failure(failed_sources: &[SourceFailure]) -> Option<ADCError> {
+        let first_failure = &failed_sources.first()?.error;
+        failed_sources
+            .iter()
+            .all(|failure| {
+                std::mem::discriminant(&failure.error) == std::mem::discriminant(first_failure)
+            })
+            .then(|| first_failure.clone())
+    }
+
+    /// Get the failure to report for a ticker which did not get enough valid prices,
+    /// which is the reason all its sources failed for if none was valid and they all failed for the same one
+    pub fn insufficient_sources(
+        ticker: &str,
+        available: usize,
+        required: usize,
+        failed_sources: &[SourceFailure],
+    ) -> ADCError {
+        let insufficient_sources = ADCError::InsufficientSources {
+            ticker: ticker.to_string(),
+            available: available as u32,
+            required: required as u32,
+        };
+        if available > 0 {
+            return insufficient_sources;
+        }
+
+        Self::common_failure(failed_sources).unwrap_or(insufficient_sources)
+    }
+}
+
+impl Display for ADCError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ADCError::UnknownTicker(ticker) => write!(f, "Unknown ticker {}", ticker),
+            ADCError::InsufficientSources {
+                ticker,
+                available,
+                required,
+            } => write!(
+                f,
+                "Insufficient sources for {}: {} of {} required sources provided a valid price",
+                ticker, available, required
+            ),
+            ADCError::VerificationFailed(message) => {
+                write!(f, "Proof verification failed: {}", message)
+            }
+            ADCError::StalePrice {
+                age: Some(age),
+                max_age,
+            } => write!(
+                f,
+                "Price is stale: published {}s ago, maximum age is {}s",
+                age, max_age
+            ),
+            ADCError::StalePrice { age: None, .. } => write!(f, "Price timestamp not available"),
+            ADCError::TimestampOutOfRange {
+                timestamp: Some(timestamp),
+                requested,
+                tolerance,
+            } => write!(
+                f,
+                "Price published at {} is more than {}s away from the requested timestamp {}",
+                timestamp, tolerance, requested
+            ),
+            ADCError::TimestampOutOfRange {
+                timestamp: None, ..
+            } => {
+                write!(f, "Price timestamp not available")
+            }
+            ADCError::DeviationTooHigh {
+                price,
+                max_deviation,
+            } => write!(
+                f,
+                "Price {} deviates from the other sources by more than {}",
+                price, max_deviation
+            ),
+            ADCError::ConfidenceTooWide {
+                price,
+                confidence,
+                max_confidence_ratio,
+            } => write!(
+                f,
+                "Confidence interval {} of price {} exceeds the maximum ratio of {}",
+                confidence, price, max_confidence_ratio
+            ),
+            ADCError::UpstreamHttpStatus { source, status } => {
+                write!(f, "{} responded with http status {}", source, status)
+            }
+            ADCError::InvalidResponse(message) => write!(f, "Invalid response: {}", message),
+            ADCError::Timeout(message) => write!(f, "Timed out: {}", message),
+            ADCError::FeeTooLow { required, provided } => write!(
+                f,
+                "Insufficient cycles attached to cover fee: {} required, {} provided",
+                required, provided
+            ),
+            ADCError::InsufficientBalance {
+                required,
+                available,
+            } => write!(
+                f,
+                "Insufficient prepaid balance: {} required, {} available",
+                required, available
+            ),
+            ADCError::AccessDenied(reason) => write!(f, "Access denied: {}", reason),
+            ADCError::RateLimited { retry_after } => {
+                write!(f, "Rate limited: retry in {}s", retry_after)
+            }
+            ADCError::QuotaExceeded { quota, resets_at } => write!(
+                f,
+                "Daily quota of {} requests exceeded, it resets at {}",
+                quota, resets_at
+            ),
+            ADCError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ADCError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insufficient_sources_reports_common_failure() {
+        let stale = ADCError::StalePrice {
+            age: Some(120),
+            max_age: 60,
+        };
+        let failed_sources = vec![
+            SourceFailure::new("pyth", "BTC", stale.clone()),
+            SourceFailure::new(
+                "coinbase",
+                "BTC",
+                ADCError::StalePrice {
+                    age: None,
+                    max_age: 60,
+                },
+            ),
+        ];
+        assert_eq!(
+            ADCError::insufficient_sources("BTC", 0, 1, &failed_sources),
+            stale
+        );
+        // the quorum missed is reported when some of the sources were valid
+        assert_eq!(
+            ADCError::insufficient_sources("BTC", 1, 2, &failed_sources),
+            ADCError::InsufficientSources {
+                ticker: "BTC".to_string(),
+                available: 1,
+                required: 2
+            }
+        );
+
+        let failed_sources = vec![
+            SourceFailure::new("pyth", "BTC", stale),
+            SourceFailure::new("kraken", "BTC", ADCError::UnknownTicker("BTC".to_string())),
+        ];
+        assert_eq!(
+            ADCError::insufficient_sources("BTC", 0, 1, &failed_sources),
+            ADCError::InsufficientSources {
+                ticker: "BTC".to_string(),
+                available: 0,
+                required: 1
+            }
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+
+/// The number of decimals prices are represented with while they are being combined
+pub const FIXED_POINT_DECIMALS: u32 = 18;
+/// The maximum number of decimals a fixed point price can be requested with,
+/// which is the precision prices are combined with as more decimals would not be exact
+pub const MAX_FIXED_POINT_DECIMALS: u32 = FIXED_POINT_DECIMALS;
+
+/// A price represented as an integer `value` scaled by 10^`decimals`
+/// e.g a value of 6701234 with 2 decimals is 67012.34
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq, Eq)]
+pub struct FixedPointPrice {
+    pub value: u128,
+    pub decimals: u32,
+}
+
+impl FixedPointPrice {
+    pub fn new(value: u128, decimals: u32) -> Self {
+        Self { value, decimals }
+    }
+
+    /// Parse a decimal string e.g "67012.34" or "1.5e-7" into a price with the decimals provided
+    pub fn from_decimal_str(text: &str, decimals: u32) -> Option<Self> {
+        let (mantissa, exponent) = parse_decimal(text)?;
+        Self::from_mantissa(mantissa, exponent, decimals)
+    }
+
+    /// Get the price `mantissa * 10^exponent` with the decimals provided, rounding to the nearest unit
+    pub fn from_mantissa(mantissa: u128, exponent: i32, decimals: u32) -> Option<Self> {
+        let value = scale(mantissa, exponent.checked_add(decimals as i32)?)?;
+        Some(Self::new(value, decimals))
+    }
+
+    /// Get this price with a different number of decimals, rounding to the nearest unit
+    pub fn rescale(&self, decimals: u32) -> Option<Self> {
+        Self::from_mantissa(self.value, -(self.decimals as i32), decimals)
+    }
+}
+
+/// Split a decimal string e.g "67012.34" or "1.5e-7" into an integer mantissa
+/// and the power of ten it is scaled by, negative numbers are not supported
+pub fn parse_decimal(text: &str) -> Option<(u128, i32)> {
+    let text = text.trim();
+    let (number, exponent) = match text.find(['e', 'E']) {
+        Some(index) => (&text[..index], text[index + 1..].parse::<i32>().ok()?),
+        None => (text, 0),
+    };
+    let number = number.strip_prefix('+').unwrap_or(number);
+    let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+
+    if integer.is_empty() && fraction.is_empty() {
+        return None;
+    }
+
+    let mut mantissa: u128 = 0;
+    for digit in integer.chars().chain(fraction.chars()) {
+        let digit = digit.to_digit(10)? as u128;
+        mantissa = mantissa.checked_mul(10)?.checked_add(digit)?;
+    }
+
+    Some((mantissa, exponent.checked_sub(fraction.len() as i32)?))
+}
+
+/// Compute `value * 10^exponent`, rounding to the nearest integer when the exponent is negative
+pub fn scale(value: u128, exponent: i32) -> Option<u128> {
+    if exponent >= 0 {
+        return value.checked_mul(10u128.checked_pow(exponent as u32)?);
+    }
+
+    match 10u128.checked_pow(exponent.unsigned_abs()) {
+        Some(divisor) => mul_div(value, 1, divisor),
+        // the divisor is larger than any value
+        None => Some(0),
+    }
+}
+
+/// Compute `a * b / c` rounded to the nearest integer
+/// without overflowing on the intermediate product
+pub fn mul_div(a: u128, b: u128, c: u128) -> Option<u128> {
+    if c == 0 {
+        return None;
+    }
+
+    let (high, low) = widening_mul(a, b);
+    // the quotient would not fit in 128 bits
+    if high >= c {
+        return None;
+    }
+
+    // long division of the 256 bit product by the divisor
+    let mut remainder = high;
+    let mut quotient: u128 = 0;
+    for bit in (0..128).rev() {
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | ((low >> bit) & 1);
+        quotient <<= 1;
+        if carry == 1 || remainder >= c {
+            remainder = remainder.wrapping_sub(c);
+            quotient |= 1;
+        }
+    }
+
+    // round half up
+    if remainder >= c - remainder {
+        quotient.checked_add(1)
+    } else {
+        Some(quotient)
+    }
+}
+
+/// Multiply two 128 bit integers into the high and low halves of a 256 bit integer
+fn widening_mul(a: u128, b: u128) -> (u128, u128) {
+    let mask = u64::MAX as u128;
+    let (a_high, a_low) = (a >> 64, a & mask);
+    let (b_high, b_low) = (b >> 64, b & mask);
+
+    let low_low = a_low * b_low;
+    let high_low = a_high * b_low;
+    let low_high = a_low * b_high;
+    let high_high = a_high * b_high;
+
+    let cross = (low_low >> 64) + (high_low & mask) + (low_high & mask);
+    let low = (low_low & mask) | (cross << 64);
+    let high = high_high + (high_low >> 64) + (low_high >> 64) + (cross >> 64);
+
+    (high, low)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_decimal_str() {
+        let price = FixedPointPrice::from_decimal_str("67012.34", 8).unwrap();
+        assert_eq!(price, FixedPointPrice::new(6_701_234_000_000, 8));
+
+        let price = FixedPointPrice::from_decimal_str("1.5e-7", 8).unwrap();
+        assert_eq!(price.value, 15);
+
+        // rounded to the nearest unit
+        let price = FixedPointPrice::from_decimal_str("0.125", 2).unwrap();
+        assert_eq!(price.value, 13);
+
+        assert!(FixedPointPrice::from_decimal_str("-1", 2).is_none());
+        assert!(FixedPointPrice::from_decimal_str("abc", 2).is_none());
+    }
+
+    #[test]
+    fn test_rescale() {
+        let price = FixedPointPrice::new(6_701_234, 2);
+
+        assert_eq!(price.rescale(0).unwrap().value, 67_012);
+        assert_eq!(price.rescale(4).unwrap().value, 670_123_400);
+    }
+
+    #[test]
+    fn test_mul_div_does_not_overflow_intermediate_product() {
+        let one = 10u128.pow(FIXED_POINT_DECIMALS);
+        let btc = 67_012 * one;
+        let eth = 2_612 * one;
+
+        // ETH/BTC at 18 decimals needs an intermediate product larger than u128
+        let eth_btc = mul_div(eth, one, btc).unwrap();
+        assert_eq!(eth_btc, 38_978_093_475_795_380);
+
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX), Some(u128::MAX));
+        assert_eq!(mul_div(u128::MAX, 2, 1), None);
+        assert_eq!(mul_div(1, 1, 0), None);
+        assert_eq!(mul_div(1, 1, 2), Some(1));
+    }
+}
@@ -1,9 +1,19 @@
-use std::fmt::{Debug, Display};
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display},
+};
 
 use anyhow::Result;
 use candid::{CandidType, Principal};
 use serde::{Deserialize, Serialize};
 
+pub mod error;
+pub mod fixed_point;
+pub mod source_definition;
+pub use error::ADCError;
+pub use fixed_point::{FixedPointPrice, FIXED_POINT_DECIMALS, MAX_FIXED_POINT_DECIMALS};
+pub use source_definition::{ExtractedPrice, SourceDefinition, TickerMapping, TimestampFormat};
+
 pub type ADCResponse = Result<Response, ErrorResponse>;
 pub type ADCResponseV2 = Result<ResponseV2, ErrorResponse>;
 
@@ -11,8 +21,50 @@ pub type ADCResponseV2 = Result<ResponseV2, ErrorResponse>;
 pub enum ProofTypes {
     Pyth(String),
     Redstone(String),
+    Coinbase(String),
+    Binance(String),
+    Kraken(String),
+    /// a proof of the market data of a token e.g its market cap
+    CoinGecko(String),
+    /// a proof of the prices of a token over a window, as a list of price points
+    RedstoneSeries(String),
+    /// a proof of the prices of a token over a window, as a list of candles
+    BinanceSeries(String),
+    /// a proof shared by several tokens of a batched pyth request,
+    /// where `feed_id` identifies the price feed of this token in the response
+    PythBatch {
+        feed_id: String,
+        proof: String,
+    },
+    /// a proof shared by several tokens of a batched redstone request,
+    /// where `symbol` identifies the price of this token in the response
+    RedstoneBatch {
+        symbol: String,
+        proof: String,
+    },
+    /// a proof from a source registered through a `SourceDefinition`
+    Custom {
+        source: String,
+        proof: String,
+    },
+    /// a proof of the attributes of the pyth price feed `feed_id` e.g its base and quote currencies,
+    /// which the feed used as the direct route of a currency pair is checked against
+    PythFeedInfo {
+        feed_id: String,
+        proof: String,
+    },
 }
 
+/// The names of the sources which have a dedicated implementation
+pub const BUILTIN_SOURCES: [&str; 6] = [
+    "pyth",
+    "redstone",
+    "coinbase",
+    "binance",
+    "kraken",
+    "coingecko",
+];
+
 impl Debug for ProofTypes {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         fn format(proof: &String) -> String {
@@ -22,6 +74,37 @@ impl Debug for ProofTypes {
         match self {
             Self::Pyth(arg0) => f.debug_tuple("Pyth").field(&format(arg0)).finish(),
             Self::Redstone(arg0) => f.debug_tuple("Redstone").field(&format(arg0)).finish(),
+            Self::Coinbase(arg0) => f.debug_tuple("Coinbase").field(&format(arg0)).finish(),
+            Self::Binance(arg0) => f.debug_tuple("Binance").field(&format(arg0)).finish(),
+            Self::Kraken(arg0) => f.debug_tuple("Kraken").field(&format(arg0)).finish(),
+            Self::CoinGecko(arg0) => f.debug_tuple("CoinGecko").field(&format(arg0)).finish(),
+            Self::RedstoneSeries(arg0) => f
+                .debug_tuple("RedstoneSeries")
+                .field(&format(arg0))
+                .finish(),
+            Self::BinanceSeries(arg0) => {
+                f.debug_tuple("BinanceSeries").field(&format(arg0)).finish()
+            }
+            Self::PythBatch { feed_id, proof } => f
+                .debug_struct("PythBatch")
+                .field("feed_id", feed_id)
+                .field("proof", &format(proof))
+                .finish(),
+            Self::RedstoneBatch { symbol, proof } => f
+                .debug_struct("RedstoneBatch")
+                .field("symbol", symbol)
+                .field("proof", &format(proof))
+                .finish(),
+            Self::Custom { source, proof } => f
+                .debug_struct("Custom")
+                .field("source", source)
+                .field("proof", &format(proof))
+                .finish(),
+            Self::PythFeedInfo { feed_id, proof } => f
+                .debug_struct("PythFeedInfo")
+                .field("feed_id", feed_id)
+                .field("proof", &format(proof))
+                .finish(),
         }
     }
 }
@@ -31,6 +114,34 @@ impl ProofTypes {
         match self {
             ProofTypes::Pyth(value) => format!("{}", value),
             ProofTypes::Redstone(value) => format!("{}", value),
+            ProofTypes::Coinbase(value) => format!("{}", value),
+            ProofTypes::Binance(value) => format!("{}", value),
+            ProofTypes::Kraken(value) => format!("{}", value),
+            ProofTypes::CoinGecko(value) => format!("{}", value),
+            ProofTypes::RedstoneSeries(value) => format!("{}", value),
+            ProofTypes::BinanceSeries(value) => format!("{}", value),
+            ProofTypes::PythBatch { proof, .. } => format!("{}", proof),
+            ProofTypes::RedstoneBatch { proof, .. } => format!("{}", proof),
+            ProofTypes::Custom { proof, .. } => format!("{}", proof),
+            ProofTypes::PythFeedInfo { proof, .. } => format!("{}", proof),
+        }
+    }
+
+    /// the name of the source this proof was generated from e.g "pyth"
+    pub fn source(&self) -> &str {
+        match self {
+            ProofTypes::Pyth(_) => "pyth",
+            ProofTypes::Redstone(_) => "redstone",
+            ProofTypes::Coinbase(_) => "coinbase",
+            ProofTypes::Binance(_) => "binance",
+            ProofTypes::Kraken(_) => "kraken",
+            ProofTypes::CoinGecko(_) => "coingecko",
+            ProofTypes::RedstoneSeries(_) => "redstone",
+            ProofTypes::BinanceSeries(_) => "binance",
+            ProofTypes::PythBatch { .. } => "pyth",
+            ProofTypes::RedstoneBatch { .. } => "redstone",
+            ProofTypes::Custom { source, .. } => source,
+            ProofTypes::PythFeedInfo { .. } => "pyth",
         }
     }
 }
@@ -45,6 +156,10 @@ pub struct Request {
     pub owner: Principal,
     /// a vector of strings representing the currency pair e.b ["BTC", "BTC/USDT"]
     pub pairs: Vec<String>,
+    /// the sources requested for each pair, indexed by pair,
+    /// every available source is used for the pairs which are not listed
+    #[serde(default)]
+    pub sources: HashMap<String, Vec<String>>,
     // add other proprties about the price here
     pub opts: RequestOpts,
 }
@@ -77,11 +192,52 @@ impl Debug for Request {
             .field("id", &self.id)
             .field("owner", &self.owner.to_text())
             .field("pairs", &self.pairs)
+            .field("sources", &self.sources)
             .field("opts", &self.opts)
             .finish()
     }
 }
 
+/// A currency pair requested through `request_data_v2`
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub struct PairSpec {
+    /// the ticker of the base token e.g "ETH"
+    pub base: String,
+    /// the ticker of the quote token e.g "BTC", the price is provided in USD when it is not set
+    pub quote: Option<String>,
+    /// the names of the sources the price should be derived from e.g ["pyth", "coinbase"],
+    /// every available source is used when it is not set
+    pub sources: Option<Vec<String>>,
+}
+
+/// A pair which was rejected from a request along with the reason it was rejected
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub struct RejectedPair {
+    /// the position of the pair in the request
+    pub index: u32,
+    /// the pair as it was requested
+    pub pair: PairSpec,
+    /// a text describing why the pair was rejected
+    pub reason: String,
+}
+
+/// The reason a request for prices was rejected before being sent to the orchestrator
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub enum RequestError {
+    /// the request does not contain any pair
+    NoPairs,
+    /// the request contains more pairs than can be priced in a single request
+    TooManyPairs { requested: u32, limit: u32 },
+    /// the options provided with the request are invalid
+    InvalidOpts(String),
+    /// some of the pairs requested are invalid, none of the pairs of the request are priced
+    InvalidPairs(Vec<RejectedPair>),
+    /// the request was rejected by the processor e.g because the fee attached is too low
+    Rejected(ADCError),
+    /// no subscription with this id is owned by the caller
+    UnknownSubscription(String),
+}
+
 #[derive(Deserialize, Serialize, Clone, CandidType)]
 pub struct Response {
     /// the id of this request
@@ -94,6 +250,8 @@ pub struct Response {
     /// this property indicates if the metadata information about this request has been succesfully fetched
     /// and is ready to be sent to the canister
     pub processed: bool,
+    /// the options the request was made with
+    pub opts: RequestOpts,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, CandidType)]
@@ -117,6 +275,7 @@ impl Debug for Response {
             .field("owner", &self.owner.to_text())
             .field("pairs", &self.pairs)
             .field("processed", &self.processed)
+            .field("opts", &self.opts)
             .finish()
     }
 }
@@ -127,8 +286,8 @@ pub struct ErrorResponse {
     pub id: String,
     /// the principal of the canister which originated this request
     pub owner: Principal,
-    /// A text describing the error message
-    pub message: String,
+    /// the failure encountered while serving the request
+    pub error: ADCError,
 }
 
 /// a struct representing a currency pair
@@ -138,12 +297,465 @@ pub struct CurrencyPair {
     pub base: Token,
     /// the quote currency
     pub quote: Option<Token>,
-    /// if there is an error getting the proofs of this currency pair
-    pub error: Option<String>,
+    /// if there is an error getting the proofs or the price of this currency pair
+    pub error: Option<ADCError>,
     /// price derived from this currencyc pair
     pub price: Option<f64>,
+    /// the exact price derived from this currency pair with the decimals requested,
+    /// only provided when decimals are requested and every source price used is exact
+    pub fixed_price: Option<FixedPointPrice>,
     /// a string representation of the price pair "USDT/BTC"
     pub repr: String,
+    /// the prices provided by each source used to derive the price of this pair
+    pub sources: Vec<SourcePrice>,
+    /// the age (in seconds) of the oldest source price used to derive the price of this pair
+    pub age: Option<u64>,
+    /// the sources which could not provide a valid price for the tokens of this pair
+    pub failed_sources: Vec<SourceFailure>,
+    /// how the price of this pair is derived, set once the proofs of the pair are fetched
+    pub route: Option<PriceRoute>,
+    /// the consecutive pairs whose prices are multiplied to derive the price of this pair
+    /// e.g STETH/ETH and ETH/USD for STETH/ETH/USD, empty when the pair is priced on its own
+    pub legs: Vec<CurrencyPair>,
+    /// the sources the price of this pair should be derived from, every available source when not set
+    pub requested_sources: Option<Vec<String>>,
+    /// the proofs of the market data of the base token, set when market data is requested
+    pub market_data_proofs: Option<Vec<ProofTypes>>,
+    /// the market data of the base token derived from `market_data_proofs`
+    pub market_data: Option<MarketData>,
+    /// the proofs of the price series of the base token, set when a series is requested
+    pub series_proofs: Option<Vec<ProofTypes>>,
+    /// the unix timestamp (in seconds) the window of `series_proofs` ends at, set once they are fetched
+    pub series_to: Option<u64>,
+    /// the price series of the base token derived from `series_proofs`
+    pub series: Option<PriceSeries>,
+}
+
+/// The market data of a token in USD, only the values requested through `RequestOpts` are provided
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq, Default)]
+pub struct MarketData {
+    /// the volume traded across all markets over the last 24 hours
+    pub volume: Option<f64>,
+    /// the market capitalization
+    pub market_cap: Option<f64>,
+    /// the relative price change over the last 24 hours in percent e.g -2.5 for a 2.5% drop
+    pub change_24h: Option<f64>,
+    /// the market data provided by each source used
+    pub sources: Vec<SourceMarketData>,
+}
+
+/// The market data provided by a single source for a token, along with the hash of its proof
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub struct SourceMarketData {
+    /// the name of the source e.g "coingecko"
+    pub source: String,
+    /// the ticker of the token whose market data was requested
+    pub ticker: String,
+    pub volume: Option<f64>,
+    pub market_cap: Option<f64>,
+    pub change_24h: Option<f64>,
+    /// the hex encoded sha256 hash of the proof the market data was extracted from
+    pub proof_hash: String,
+}
+
+/// The last verified price of a currency pair kept by the processor
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub struct CachedPrice {
+    /// the currency pair e.g "ETH/BTC"
+    pub pair: String,
+    pub price: f64,
+    /// the exact price with the decimals it was requested with, if any
+    pub fixed_price: Option<FixedPointPrice>,
+    /// the unix timestamp (in seconds) of the oldest source price used,
+    /// or the time the price was verified at if none of the sources provide one
+    pub timestamp: u64,
+    /// the unix timestamp (in seconds) the price was verified at
+    pub verified_at: u64,
+    /// the prices used to derive this price
+    pub sources: Vec<SourcePrice>,
+    pub route: Option<PriceRoute>,
+}
+
+/// The minimum interval (in seconds) between two updates pushed to a subscriber
+pub const MIN_SUBSCRIPTION_HEARTBEAT: u64 = 60;
+
+/// A subscription of a canister to the prices of currency pairs,
+/// the processor pushes the prices to the canister through `receive_adc_response`
+/// every `heartbeat` seconds or as soon as a price deviates from the last one pushed by `deviation_threshold`
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub struct Subscription {
+    pub id: String,
+    /// the canister the updates are pushed to
+    pub owner: Principal,
+    pub pairs: Vec<PairSpec>,
+    /// the maximum time (in seconds) between two updates
+    pub heartbeat: u64,
+    /// the relative deviation (e.g 0.01 for 1%) of a price from the last price pushed
+    /// which triggers an update before the heartbeat
+    pub deviation_threshold: Option<f64>,
+    /// the cycles left to pay for the updates, each update is charged the transaction fee
+    pub balance: u128,
+    /// the last price pushed for each pair
+    pub last_prices: HashMap<String, f64>,
+    /// the unix timestamp (in seconds) of the last update pushed
+    pub last_update: Option<u64>,
+}
+
+impl Subscription {
+    /// Check if the heartbeat of the subscription has elapsed since its last update at time `now` (in seconds)
+    pub fn is_heartbeat_due(&self, now: u64) -> bool {
+        match self.last_update {
+            Some(last_update) => now.saturating_sub(last_update) >= self.heartbeat,
+            None => true,
+        }
+    }
+
+    /// Check if an update of the subscription can be due at time `now` (in seconds),
+    /// which is only known once its pairs are priced when it has a deviation threshold
+    pub fn may_be_due(&self, now: u64) -> bool {
+        self.deviation_threshold.is_some() || self.is_heartbeat_due(now)
+    }
+
+    /// Get the request the orchestrator prices the pairs of the subscription with
+    pub fn to_request(&self) -> Request {
+        let opts = RequestOpts {
+            price: true,
+            ..Default::default()
+        };
+        Request::from_pair_specs(self.id.clone(), self.owner, self.pairs.clone(), opts)
+    }
+}
+
+/// The data a request made to the processor asks for
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub enum RequestKind {
+    /// the currency pairs priced through `request_data` or `request_data_v2`
+    Price { pairs: Vec<String> },
+    /// the http request proxied through `request_data_url`
+    Url { target_url: String, method: String },
+}
+
+/// The stage a request made to the processor is at
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub enum RequestStatus {
+    /// the request is waiting for the orchestrator to serve it
+    Pending,
+    /// the response was sent to the owner of the request
+    Fulfilled,
+    /// the failure of the request was sent to the owner of the request
+    Failed(ADCError),
+    /// the request was not served within the timeout and its owner was notified with a timeout error
+    Expired,
+}
+
+/// The record of a request made to the processor, kept after it is completed so that its outcome can be queried
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub struct RequestRecord {
+    pub id: String,
+    /// the canister which made the request and receives its response
+    pub owner: Principal,
+    pub kind: RequestKind,
+    /// the unix timestamp (in seconds) the request was made at
+    pub created_at: u64,
+    /// the cycles charged for the request
+    pub fee_paid: u128,
+    pub status: RequestStatus,
+    /// the unix timestamp (in seconds) the request was fulfilled, failed or expired at
+    pub completed_at: Option<u64>,
+}
+
+impl RequestRecord {
+    /// Create the record of a request which is waiting to be served
+    pub fn new(
+        id: String,
+        owner: Principal,
+        kind: RequestKind,
+        created_at: u64,
+        fee_paid: u128,
+    ) -> Self {
+        Self {
+            id,
+            owner,
+            kind,
+            created_at,
+            fee_paid,
+            status: RequestStatus::Pending,
+            completed_at: None,
+        }
+    }
+}
+
+/// The default time (in seconds) a request can wait for the orchestrator before it expires
+pub const DEFAULT_REQUEST_TIMEOUT: u64 = 10 * 60;
+/// The minimum time (in seconds) a request can wait for the orchestrator before it expires
+pub const MIN_REQUEST_TIMEOUT: u64 = 60;
+/// The time (in seconds) the record of a completed request is kept for
+pub const REQUEST_RETENTION: u64 = 7 * 24 * 60 * 60;
+
+/// How the processor handles the requests the orchestrator does not serve in time
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub struct RequestTimeoutConfig {
+    /// the time (in seconds) after which a pending request expires
+    pub timeout: u64,
+    /// whether the fee paid for an expired request is refunded to its owner
+    pub refund_expired: bool,
+}
+
+impl Default for RequestTimeoutConfig {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_REQUEST_TIMEOUT,
+            refund_expired: false,
+        }
+    }
+}
+
+impl RequestTimeoutConfig {
+    /// Check that the timeout is long enough for the orchestrator to serve a request
+    /// and that requests expire before their records are pruned
+    pub fn validate(&self) -> Result<()> {
+        if self.timeout < MIN_REQUEST_TIMEOUT || self.timeout > REQUEST_RETENTION {
+            anyhow::bail!(
+                "timeout must be between {}s and {}s",
+                MIN_REQUEST_TIMEOUT,
+                REQUEST_RETENTION
+            )
+        }
+
+        Ok(())
+    }
+}
+
+/// The cycles a caller prepaid to the processor along with the totals it was charged and credited
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq, Default)]
+pub struct LedgerAccount {
+    /// the prepaid cycles the fees are charged from when no cycles are attached to a request
+    pub balance: u128,
+    pub deposited: u128,
+    pub withdrawn: u128,
+    /// the fees charged, whether from the cycles attached to the requests or from the balance
+    pub fees_paid: u128,
+    /// the cycles credited back to the balance for the requests which were not served
+    /// and the subscriptions which were cancelled
+    pub refunded: u128,
+}
+
+/// The fees (in cycles) charged for a request, which grow with what the orchestrator has to fetch and prove for it
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq, Default)]
+pub struct FeeSchedule {
+    /// the fee charged for every request, whatever it is for
+    pub base: u128,
+    /// the fee charged for each pair of a price request
+    pub per_pair: u128,
+    /// the fee charged for each source a pair of a price request is priced from
+    pub per_source: u128,
+    /// the fee charged for each KB (started) of the url, headers and body of a URL request
+    pub per_kb: u128,
+}
+
+/// A request the fee is quoted for, with the same arguments as the endpoint it would be made through
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType)]
+pub enum FeeQuoteRequest {
+    /// a request made through `request_data_v2`
+    Price { pairs: Vec<PairSpec> },
+    /// a request made through `request_data_url`
+    Url {
+        target_url: String,
+        method: String,
+        redacted: String,
+        headers: Vec<Headers>,
+        body: String,
+    },
+}
+
+/// Who can make requests to the processor
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq, Default)]
+pub enum AccessMode {
+    /// every principal can make any request
+    #[default]
+    Open,
+    /// only the whitelisted principals can make requests, within the rule they are whitelisted with
+    Whitelist,
+    /// every principal except the blacklisted ones can make any request
+    Blacklist,
+}
+
+/// The kinds of requests a principal can be allowed to make
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub enum Endpoint {
+    /// prices requested through `request_data`, `request_data_v2` or `subscribe`
+    Price,
+    /// requests proxied through `request_data_url`
+    Url,
+}
+
+/// The requests a whitelisted principal can make
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq, Default)]
+pub struct AccessRule {
+    /// the kinds of requests the principal can make, all of them when not set
+    pub endpoints: Option<Vec<Endpoint>>,
+    /// the number of requests the principal can still make, unlimited when not set
+    pub quota: Option<u64>,
+}
+
+/// A token bucket limiting the rate at which a principal can make requests
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub struct RateLimit {
+    /// the number of requests which can be made at once
+    pub capacity: u32,
+    /// the time (in seconds) it takes for another request to be allowed
+    pub refill_interval: u64,
+}
+
+/// The limits on the requests a principal can make
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq, Default)]
+pub struct RateLimitConfig {
+    /// the rate limit of the requests, unlimited when not set
+    pub rate_limit: Option<RateLimit>,
+    /// the number of requests which can be made each day (UTC), unlimited when not set
+    pub daily_quota: Option<u64>,
+}
+
+/// The requests a principal made within its rate limit and daily quota
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq, Default)]
+pub struct QuotaUsage {
+    /// the requests which could be made at once as of `refilled_at`
+    pub tokens: u32,
+    /// the time (in seconds) the tokens were last refilled at
+    pub refilled_at: u64,
+    /// the day (since the unix epoch) `requests_today` were made on
+    pub day: u64,
+    pub requests_today: u64,
+}
+
+/// The limits on the requests of a principal along with what is left of them
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub struct Quota {
+    pub config: RateLimitConfig,
+    /// the requests which can be made right now before being rate limited, unlimited when not set
+    pub available_requests: Option<u32>,
+    pub requests_today: u64,
+    /// the requests which can still be made today, unlimited when not set
+    pub remaining_today: Option<u64>,
+    /// the time (in seconds) the daily quota resets at
+    pub resets_at: u64,
+}
+
+impl Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Endpoint::Price => write!(f, "price"),
+            Endpoint::Url => write!(f, "url"),
+        }
+    }
+}
+
+/// The aggregate computed over the prices of a token within a window
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub enum SeriesKind {
+    /// the time weighted average price
+    Twap,
+    /// the volume weighted average price, only computed from the sources which provide volumes
+    Vwap,
+    /// the open, high, low and close prices of consecutive candles of `candle_interval` seconds
+    Ohlc { candle_interval: u64 },
+}
+
+/// The price series requested for the base token of each pair
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub struct SeriesOpts {
+    pub kind: SeriesKind,
+    /// the length (in seconds) of the window ending at the time priced
+    pub window: u64,
+}
+
+/// The maximum length (in seconds) of the window of a price series
+pub const MAX_SERIES_WINDOW: u64 = 7 * 24 * 60 * 60;
+/// The minimum length (in seconds) of a candle
+pub const MIN_CANDLE_INTERVAL: u64 = 60;
+/// The maximum number of candles a price series can be split into
+pub const MAX_SERIES_CANDLES: u64 = 500;
+
+/// The value computed over the price series of a token
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub enum SeriesValue {
+    Twap(f64),
+    Vwap(f64),
+    Ohlc(Vec<Candle>),
+}
+
+/// The prices of a token within a period of time
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub struct Candle {
+    /// the unix timestamp (in seconds) the candle starts at
+    pub open_time: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// the volume traded within the candle, if the source provides it
+    pub volume: Option<f64>,
+}
+
+/// The price series of a token in USD over a window
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub struct PriceSeries {
+    /// the unix timestamp (in seconds) the window starts at
+    pub from: u64,
+    /// the unix timestamp (in seconds) the window ends at
+    pub to: u64,
+    pub value: SeriesValue,
+    /// the sources whose prices were used
+    pub sources: Vec<String>,
+    /// the number of price points the value was computed from
+    pub points: u32,
+}
+
+/// The maximum number of legs a currency pair can be priced through
+pub const MAX_PRICE_LEGS: usize = 3;
+
+/// How the price of a currency pair is derived from the prices provided by the sources
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub enum PriceRoute {
+    /// the sources provide the price of the base token in the quote token directly
+    /// e.g an ETH/BTC market
+    Direct,
+    /// the price of the base token and the quote token are both gotten in an intermediary currency
+    /// and the base price is divided by the quote price e.g ETH/USD / BTC/USD
+    Triangulated { via: String },
+    /// the price is the product of the prices of consecutive legs through intermediary assets
+    /// e.g STETH/ETH * ETH/USD
+    MultiHop { via: Vec<String> },
+}
+
+/// The price provided by a single source for a token, along with metadata about its proof
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub struct SourcePrice {
+    /// the name of the source e.g "pyth"
+    pub source: String,
+    /// the ticker of the token whose price was requested
+    pub ticker: String,
+    /// the price of the token provided by the source
+    pub price: f64,
+    /// the exact price of the token provided by the source with `FIXED_POINT_DECIMALS` decimals
+    pub fixed_price: Option<FixedPointPrice>,
+    /// the time (in seconds) the price was published by the source, if provided
+    pub timestamp: Option<u64>,
+    /// the confidence interval around the price, if provided by the source e.g pyth
+    pub confidence: Option<f64>,
+    /// the hex encoded sha256 hash of the proof the price was extracted from
+    pub proof_hash: String,
+}
+
+/// A record of a source which failed to provide a valid price for a token
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub struct SourceFailure {
+    /// the name of the source e.g "pyth"
+    pub source: String,
+    /// the ticker of the token whose price was requested
+    pub ticker: String,
+    /// the reason the source failed
+    pub error: ADCError,
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
@@ -152,11 +764,114 @@ pub struct Token {
     pub proofs: Option<Vec<ProofTypes>>,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug, CandidType)]
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, Default)]
 pub struct RequestOpts {
     pub price: bool,
+    /// the strategy used to combine the source prices, defaults to the one set on the processor
+    pub aggregation: Option<AggregationStrategy>,
+    /// the maximum relative deviation (e.g 0.05 for 5%) a source price can have from the median
+    /// before it is discarded, defaults to the one set on the processor
+    pub max_deviation: Option<f64>,
+    /// the minimum number of sources which must provide a valid price for a token,
+    /// defaults to the one set on the processor
+    pub min_sources: Option<u32>,
+    /// the maximum age (in seconds) of a source price before it is discarded as stale,
+    /// defaults to the one set on the processor
+    pub max_age: Option<u64>,
+    /// the maximum ratio of the confidence interval to the price (e.g 0.01 for 1%)
+    /// before a source price is discarded, defaults to the one set on the processor
+    pub max_confidence_ratio: Option<f64>,
+    /// the number of decimals of the exact fixed point price of each pair,
+    /// defaults to the one set on the processor
+    pub decimals: Option<u32>,
+    /// whether the volume traded over the last 24 hours of the base token of each pair is requested,
+    /// it is not requested when not set
+    pub volume: Option<bool>,
+    /// whether the market capitalization of the base token of each pair is requested,
+    /// it is not requested when not set
+    pub market_cap: Option<bool>,
+    /// whether the price change over the last 24 hours of the base token of each pair is requested,
+    /// it is not requested when not set
+    pub change_24h: Option<bool>,
+    /// the unix timestamp (in seconds) of the historical prices requested, the latest prices are requested when not set
+    pub at_timestamp: Option<u64>,
+    /// the maximum difference (in seconds) between the timestamp of a historical source price
+    /// and `at_timestamp` before it is discarded, defaults to the one set on the processor
+    pub timestamp_tolerance: Option<u64>,
+    /// the price series of the base token of each pair requested over a window ending at the time priced
+    pub series: Option<SeriesOpts>,
+    /// the maximum age (in seconds) of the cached prices a request can be answered with straight away,
+    /// the prices are always fetched when not set
+    pub cache_max_age: Option<u64>,
 }
 
+impl RequestOpts {
+    /// Check if any market data is requested alongside or instead of the price
+    pub fn wants_market_data(&self) -> bool {
+        [self.volume, self.market_cap, self.change_24h]
+            .into_iter()
+            .any(|requested| requested.unwrap_or(false))
+    }
+
+    /// Check if anything is requested at all, be it the price, market data or a price series
+    pub fn wants_any_data(&self) -> bool {
+        self.price || self.wants_market_data() || self.series.is_some()
+    }
+}
+
+/// The strategy used to combine the prices gotten from several sources into a single price,
+/// the mean is the default as it is how prices were always combined
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq, Default)]
+pub enum AggregationStrategy {
+    /// the arithmetic mean of all the source prices
+    #[default]
+    Mean,
+    /// the middle value of the sorted source prices
+    Median,
+    /// the mean of the source prices after discarding a fraction (between 0 and 0.5)
+    /// of the lowest and highest prices
+    TrimmedMean(f64),
+    /// the mean of the source prices weighted by source, unlisted sources have a weight of 1
+    WeightedMean(Vec<SourceWeight>),
+}
+
+/// The weight given to prices from a particular source e.g "pyth"
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub struct SourceWeight {
+    pub source: String,
+    pub weight: f64,
+}
+
+/// The pricing configuration set by the owner of the processor,
+/// used for every request which does not override it through its `RequestOpts`
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq, Default)]
+pub struct PricingConfig {
+    /// the strategy used to combine the source prices
+    pub aggregation: AggregationStrategy,
+    /// the maximum relative deviation a source price can have from the median of all source prices
+    pub max_deviation: Option<f64>,
+    /// the minimum number of sources which must provide a valid price for a token,
+    /// at least one valid source is always required
+    pub min_sources: u32,
+    /// the maximum age (in seconds) of a source price before it is discarded as stale
+    pub max_age: Option<u64>,
+    /// the maximum ratio of the confidence interval to the price before a source price is discarded
+    pub max_confidence_ratio: Option<f64>,
+    /// the number of decimals of the exact fixed point price of each pair,
+    /// no fixed point price is provided when it is not set
+    pub decimals: Option<u32>,
+    /// the maximum difference (in seconds) between the timestamp of a historical source price
+    /// and the timestamp requested, defaults to `DEFAULT_TIMESTAMP_TOLERANCE`
+    pub timestamp_tolerance: Option<u64>,
+}
+
+/// The default maximum difference (in seconds) between the timestamp of a historical source price
+/// and the timestamp requested
+pub const DEFAULT_TIMESTAMP_TOLERANCE: u64 = 60;
+/// The maximum timestamp tolerance (in seconds) which can be requested,
+/// historical prices are looked up within this window before the timestamp requested
+pub const MAX_TIMESTAMP_TOLERANCE: u64 = 3600;
+
 // ------ implementations for structs
 impl Request {
     pub fn new(
@@ -174,11 +889,153 @@ impl Request {
             id,
             owner,
             pairs: pairs_list,
+            sources: HashMap::new(),
+            opts,
+        }
+    }
+
+    /// Create a request from pairs which have been checked with `PairSpec::validate_all`
+    pub fn from_pair_specs(
+        id: String,
+        owner: Principal,
+        pair_specs: Vec<PairSpec>,
+        opts: RequestOpts,
+    ) -> Self {
+        let mut pairs = vec![];
+        let mut sources = HashMap::new();
+        for pair_spec in pair_specs {
+            let pair = pair_spec.to_pair_string();
+            if let Some(pair_sources) = pair_spec.sources {
+                sources.insert(pair.clone(), pair_sources);
+            }
+            pairs.push(pair);
+        }
+
+        Self {
+            id,
+            owner,
+            pairs,
+            sources,
             opts,
         }
     }
 }
 
+impl PairSpec {
+    /// Get the string representation of this pair e.g "ETH/BTC"
+    pub fn to_pair_string(&self) -> String {
+        let base = self.base.trim().to_ascii_uppercase();
+        match &self.quote {
+            Some(quote) => format!("{}/{}", base, quote.trim().to_ascii_uppercase()),
+            None => base,
+        }
+    }
+
+    /// Check that this pair is well formed and only requests the sources available
+    pub fn validate(&self, available_sources: &[String]) -> Result<()> {
+        validate_ticker(&self.base)?;
+        if let Some(quote) = &self.quote {
+            validate_ticker(quote)?;
+            if quote.trim().eq_ignore_ascii_case(self.base.trim()) {
+                anyhow::bail!("base and quote must be different")
+            }
+        }
+
+        if let Some(sources) = &self.sources {
+            if sources.is_empty() {
+                anyhow::bail!("at least one source must be requested")
+            }
+            if let Some(source) = sources
+                .iter()
+                .find(|source| !available_sources.contains(source))
+            {
+                anyhow::bail!("unknown source {}", source)
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Check every pair of a request, listing all the pairs which are rejected
+    pub fn validate_all(
+        pair_specs: &[PairSpec],
+        available_sources: &[String],
+        limit: usize,
+    ) -> Result<(), RequestError> {
+        if pair_specs.is_empty() {
+            return Err(RequestError::NoPairs);
+        }
+        if pair_specs.len() > limit {
+            return Err(RequestError::TooManyPairs {
+                requested: pair_specs.len() as u32,
+                limit: limit as u32,
+            });
+        }
+
+        let mut rejected_pairs = vec![];
+        for (index, pair_spec) in pair_specs.iter().enumerate() {
+            let is_duplicate = pair_specs[..index]
+                .iter()
+                .any(|previous| previous.to_pair_string() == pair_spec.to_pair_string());
+            let validation = match is_duplicate {
+                true => Err(anyhow::anyhow!("duplicate pair")),
+                false => pair_spec.validate(available_sources),
+            };
+            if let Err(err) = validation {
+                rejected_pairs.push(RejectedPair {
+                    index: index as u32,
+                    pair: pair_spec.clone(),
+                    reason: err.to_string(),
+                });
+            }
+        }
+
+        match rejected_pairs.is_empty() {
+            true => Ok(()),
+            false => Err(RequestError::InvalidPairs(rejected_pairs)),
+        }
+    }
+}
+
+/// Check that a ticker is not empty and only contains letters, digits, '.', '-' or '_'
+fn validate_ticker(ticker: &str) -> Result<()> {
+    let ticker = ticker.trim();
+    if ticker.is_empty() {
+        anyhow::bail!("ticker must not be empty")
+    }
+    if !ticker
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+    {
+        anyhow::bail!("invalid ticker {}", ticker)
+    }
+
+    Ok(())
+}
+
+impl Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestError::NoPairs => write!(f, "no pairs requested"),
+            RequestError::TooManyPairs { requested, limit } => write!(
+                f,
+                "{} pairs requested, the number of pairs must not be more than {}",
+                requested, limit
+            ),
+            RequestError::InvalidOpts(message) => write!(f, "invalid opts: {}", message),
+            RequestError::InvalidPairs(rejected_pairs) => {
+                let reasons: Vec<String> = rejected_pairs
+                    .iter()
+                    .map(|rejected| format!("#{} {}", rejected.index, rejected.reason))
+                    .collect();
+                write!(f, "invalid pairs: {}", reasons.join(", "))
+            }
+            RequestError::Rejected(error) => write!(f, "request rejected: {}", error),
+            RequestError::UnknownSubscription(id) => write!(f, "unknown subscription {}", id),
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Clone, Debug, CandidType)]
 pub struct Headers {
     pub key: String,
@@ -205,11 +1062,25 @@ impl ProxyRequest {
             owner,
         }
     }
+
+    /// Get the size (in bytes) of what is sent to the target of this request
+    pub fn payload_size(&self) -> usize {
+        let headers_size: usize = self
+            .headers
+            .iter()
+            .map(|header| header.key.len() + header.value.len())
+            .sum();
+        self.target_url.len()
+            + self.method.len()
+            + self.redacted.len()
+            + headers_size
+            + self.body.len()
+    }
 }
 
 impl ErrorResponse {
-    pub fn new(id: String, owner: Principal, message: String) -> Self {
-        Self { id, owner, message }
+    pub fn new(id: String, owner: Principal, error: ADCError) -> Self {
+        Self { id, owner, error }
     }
 }
 
@@ -219,6 +1090,10 @@ impl From<Request> for Response {
             .pairs
             .iter()
             .filter_map(|pair_string| CurrencyPair::try_from(pair_string.to_owned()).ok())
+            .map(|pair| {
+                let requested_sources = request.sources.get(&pair.repr).cloned();
+                pair.with_requested_sources(requested_sources)
+            })
             .collect();
 
         Self {
@@ -226,6 +1101,7 @@ impl From<Request> for Response {
             owner: request.owner,
             pairs,
             processed: false,
+            opts: request.opts,
         }
     }
 }
@@ -242,6 +1118,105 @@ impl From<ProxyRequest> for ResponseV2 {
     }
 }
 
+impl PricingConfig {
+    /// Apply the options provided with a request on top of this configuration
+    pub fn with_overrides(&self, opts: &RequestOpts) -> Self {
+        Self {
+            aggregation: opts
+                .aggregation
+                .clone()
+                .unwrap_or_else(|| self.aggregation.clone()),
+            max_deviation: opts.max_deviation.or(self.max_deviation),
+            min_sources: opts.min_sources.unwrap_or(self.min_sources),
+            max_age: opts.max_age.or(self.max_age),
+            max_confidence_ratio: opts.max_confidence_ratio.or(self.max_confidence_ratio),
+            decimals: opts.decimals.or(self.decimals),
+            timestamp_tolerance: opts.timestamp_tolerance.or(self.timestamp_tolerance),
+        }
+    }
+
+    /// Get the maximum difference between the timestamp of a historical source price and the timestamp requested
+    pub fn get_timestamp_tolerance(&self) -> u64 {
+        self.timestamp_tolerance
+            .unwrap_or(DEFAULT_TIMESTAMP_TOLERANCE)
+    }
+
+    /// Check that the values of this configuration are within their valid ranges
+    pub fn validate(&self) -> Result<()> {
+        if let Some(max_deviation) = self.max_deviation {
+            if max_deviation.is_nan() || max_deviation <= 0.0 {
+                anyhow::bail!("max_deviation must be greater than 0")
+            }
+        }
+        if let Some(max_confidence_ratio) = self.max_confidence_ratio {
+            if max_confidence_ratio.is_nan() || max_confidence_ratio <= 0.0 {
+                anyhow::bail!("max_confidence_ratio must be greater than 0")
+            }
+        }
+        if self.get_timestamp_tolerance() > MAX_TIMESTAMP_TOLERANCE {
+            anyhow::bail!(
+                "timestamp_tolerance must not be greater than {}",
+                MAX_TIMESTAMP_TOLERANCE
+            )
+        }
+        if let Some(decimals) = self.decimals {
+            if decimals > MAX_FIXED_POINT_DECIMALS {
+                anyhow::bail!(
+                    "decimals must not be greater than {}",
+                    MAX_FIXED_POINT_DECIMALS
+                )
+            }
+        }
+
+        match &self.aggregation {
+            AggregationStrategy::TrimmedMean(fraction) if !(0.0..0.5).contains(fraction) => {
+                anyhow::bail!("trimmed mean fraction must be between 0 and 0.5")
+            }
+            AggregationStrategy::WeightedMean(weights)
+                if weights.iter().any(|w| w.weight.is_nan() || w.weight < 0.0) =>
+            {
+                anyhow::bail!("source weights must not be negative")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl SeriesOpts {
+    /// Check that the window and candles requested are within their valid ranges
+    pub fn validate(&self) -> Result<()> {
+        if self.window == 0 || self.window > MAX_SERIES_WINDOW {
+            anyhow::bail!("series window must be between 1 and {}", MAX_SERIES_WINDOW)
+        }
+        if let SeriesKind::Ohlc { candle_interval } = self.kind {
+            if candle_interval < MIN_CANDLE_INTERVAL {
+                anyhow::bail!(
+                    "candle_interval must not be less than {}",
+                    MIN_CANDLE_INTERVAL
+                )
+            }
+            if self.window.div_ceil(candle_interval) > MAX_SERIES_CANDLES {
+                anyhow::bail!(
+                    "series must not be split into more than {} candles",
+                    MAX_SERIES_CANDLES
+                )
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl SourceFailure {
+    pub fn new(source: &str, ticker: &str, error: ADCError) -> Self {
+        Self {
+            source: source.to_string(),
+            ticker: ticker.to_string(),
+            error,
+        }
+    }
+}
+
 impl Token {
     pub fn new(ticker: String) -> Self {
         Token {
@@ -257,31 +1232,127 @@ impl TryFrom<String> for CurrencyPair {
     fn try_from(currency_pair: String) -> Result<Self, Self::Error> {
         //TODO: Do we need to have a whitelist of currencies we support and fail if base not included
         let currency_pair = currency_pair.to_uppercase();
-        let base_and_quote: Vec<&str> = currency_pair.split('/').collect();
+        let route: Vec<&str> = currency_pair.split('/').collect();
 
-        if base_and_quote.len() > 2 {
+        if route.len() > MAX_PRICE_LEGS + 1 || route.iter().any(|ticker| ticker.is_empty()) {
             anyhow::bail!("invalid currency_pair")
         }
 
-        let base = Token::new(base_and_quote[0].to_string());
-        let quote = if base_and_quote.len() == 2 {
-            Some(Token::new(base_and_quote[1].to_string()))
+        let base = Token::new(route[0].to_string());
+        let quote = if route.len() >= 2 {
+            Some(Token::new(route[route.len() - 1].to_string()))
         } else {
             None
         };
 
+        // a route through intermediary assets is priced as consecutive pairs e.g STETH/ETH/USD
+        let legs = if route.len() > 2 {
+            route
+                .windows(2)
+                .map(|leg| Self::try_from(format!("{}/{}", leg[0], leg[1])))
+                .collect::<Result<Vec<Self>, Self::Error>>()?
+        } else {
+            vec![]
+        };
+
         Ok(Self {
             base,
             quote,
             error: None,
             price: None,
+            fixed_price: None,
             repr: currency_pair,
+            sources: vec![],
+            age: None,
+            failed_sources: vec![],
+            route: None,
+            legs,
+            requested_sources: None,
+            market_data_proofs: None,
+            market_data: None,
+            series_proofs: None,
+            series_to: None,
+            series: None,
         })
     }
 }
 
+impl CurrencyPair {
+    /// Restrict the sources this pair and its legs are priced with
+    pub fn with_requested_sources(mut self, requested_sources: Option<Vec<String>>) -> Self {
+        self.legs = self
+            .legs
+            .into_iter()
+            .map(|leg| leg.with_requested_sources(requested_sources.clone()))
+            .collect();
+        self.requested_sources = requested_sources;
+        self
+    }
+
+    /// Check if the price of this pair may be derived from the source provided
+    pub fn uses_source(&self, source: &str) -> bool {
+        match &self.requested_sources {
+            Some(requested_sources) => requested_sources.iter().any(|s| s == source),
+            None => true,
+        }
+    }
+
+    /// Check that the legs of this pair form a route from its base to its quote (USD when it is not set)
+    /// e.g STETH/ETH then ETH/USD for STETH/USD
+    pub fn validate_legs(&self) -> Result<()> {
+        let (Some(first_leg), Some(last_leg)) = (self.legs.first(), self.legs.last()) else {
+            return Ok(());
+        };
+        let leg_quote = |leg: &CurrencyPair| {
+            leg.quote
+                .as_ref()
+                .map(|quote| quote.ticker.clone())
+                .unwrap_or_else(|| "USD".to_string())
+        };
+
+        if !first_leg
+            .base
+            .ticker
+            .eq_ignore_ascii_case(&self.base.ticker)
+        {
+            anyhow::bail!(
+                "the route of {} starts at {} instead of {}",
+                self.repr,
+                first_leg.base.ticker,
+                self.base.ticker
+            )
+        }
+        for legs in self.legs.windows(2) {
+            if !leg_quote(&legs[0]).eq_ignore_ascii_case(&legs[1].base.ticker) {
+                anyhow::bail!(
+                    "the legs {} and {} of {} do not follow each other",
+                    legs[0].repr,
+                    legs[1].repr,
+                    self.repr
+                )
+            }
+        }
+        let quote = leg_quote(self);
+        if !leg_quote(last_leg).eq_ignore_ascii_case(&quote) {
+            anyhow::bail!(
+                "the route of {} ends at {} instead of {}",
+                self.repr,
+                leg_quote(last_leg),
+                quote
+            )
+        }
+
+        Ok(())
+    }
+}
+
 impl Display for CurrencyPair {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // a route through intermediary assets is represented by the tickers of all its legs
+        if !self.legs.is_empty() {
+            return write!(f, "{}", self.repr);
+        }
+
         // get the base
         let base = self.base.ticker.clone();
         // get the quote of it exists otherwise return empty string
@@ -296,6 +1367,20 @@ impl Display for CurrencyPair {
 }
 
 mod tests {
+    #[test]
+    fn test_decode_request_opts_without_market_data() {
+        // the callers which predate the market data only send the price
+        #[derive(candid::CandidType)]
+        struct PriceOpts {
+            price: bool,
+        }
+        let bytes = candid::encode_one(PriceOpts { price: true }).unwrap();
+
+        let opts: super::RequestOpts = candid::decode_one(&bytes).unwrap();
+        assert!(opts.price);
+        assert!(!opts.wants_market_data());
+    }
+
     #[test]
     fn test_currency_pair_with_base() {
         let pair_string = "BTC/ETH";
@@ -316,4 +1401,137 @@ mod tests {
         assert_eq!(pair.quote, None);
         assert_eq!(pair.repr, pair_string);
     }
+
+    #[test]
+    fn test_currency_pair_with_route() {
+        let pair_string = "STETH/ETH/USD";
+        let pair = super::CurrencyPair::try_from(String::from(pair_string)).unwrap();
+
+        assert_eq!(pair.base.ticker, "STETH");
+        assert_eq!(pair.clone().quote.unwrap().ticker, "USD");
+        assert_eq!(pair.legs.len(), 2);
+        assert_eq!(pair.legs[0].repr, "STETH/ETH");
+        assert_eq!(pair.legs[1].repr, "ETH/USD");
+        assert_eq!(pair.to_string(), pair_string);
+
+        assert!(super::CurrencyPair::try_from(String::from("A/B/C/D/E")).is_err());
+        assert!(super::CurrencyPair::try_from(String::from("STETH//USD")).is_err());
+    }
+
+    #[test]
+    fn test_validate_legs() {
+        let pair = |pair_string: &str| super::CurrencyPair::try_from(pair_string.to_string());
+        let mut steth_usd = pair("STETH").unwrap();
+        steth_usd.legs = vec![pair("STETH/ETH").unwrap(), pair("ETH/USD").unwrap()];
+        assert!(steth_usd.validate_legs().is_ok());
+        assert!(pair("STETH/ETH/USD").unwrap().validate_legs().is_ok());
+
+        // the legs must start at the base, follow each other and end at the quote
+        let mut mismatched = steth_usd.clone();
+        mismatched.legs[0] = pair("WBTC/ETH").unwrap();
+        assert!(mismatched.validate_legs().is_err());
+        let mut mismatched = steth_usd.clone();
+        mismatched.legs[1] = pair("BTC/USD").unwrap();
+        assert!(mismatched.validate_legs().is_err());
+        let mut mismatched = steth_usd.clone();
+        mismatched.quote = Some(super::Token::new("BTC".to_string()));
+        assert!(mismatched.validate_legs().is_err());
+    }
+
+    #[test]
+    fn test_validate_pair_specs() {
+        let available_sources = vec!["pyth".to_string(), "coinbase".to_string()];
+        let pair_spec =
+            |base: &str, quote: Option<&str>, sources: Option<Vec<&str>>| super::PairSpec {
+                base: base.to_string(),
+                quote: quote.map(str::to_string),
+                sources: sources.map(|s| s.into_iter().map(str::to_string).collect()),
+            };
+
+        let valid_pairs = vec![
+            pair_spec("eth", Some("btc"), Some(vec!["pyth"])),
+            pair_spec("BTC", None, None),
+        ];
+        assert!(super::PairSpec::validate_all(&valid_pairs, &available_sources, 10).is_ok());
+        assert_eq!(valid_pairs[0].to_pair_string(), "ETH/BTC");
+
+        let pairs = vec![
+            pair_spec("ETH", Some("BTC"), None),
+            pair_spec("ETH/USD", None, None),
+            pair_spec("SOL", Some("sol"), None),
+            pair_spec("BTC", None, Some(vec!["unknown"])),
+            pair_spec("eth", Some("btc"), None),
+        ];
+        let rejected_pairs = match super::PairSpec::validate_all(&pairs, &available_sources, 10) {
+            Err(super::RequestError::InvalidPairs(rejected_pairs)) => rejected_pairs,
+            other => panic!("unexpected validation result {:?}", other),
+        };
+        let rejected: Vec<(u32, &str)> = rejected_pairs
+            .iter()
+            .map(|rejected| (rejected.index, rejected.reason.as_str()))
+            .collect();
+        assert_eq!(
+            rejected,
+            vec![
+                (1, "invalid ticker ETH/USD"),
+                (2, "base and quote must be different"),
+                (3, "unknown source unknown"),
+                (4, "duplicate pair"),
+            ]
+        );
+
+        assert_eq!(
+            super::PairSpec::validate_all(&pairs, &available_sources, 2),
+            Err(super::RequestError::TooManyPairs {
+                requested: 5,
+                limit: 2
+            })
+        );
+        assert_eq!(
+            super::PairSpec::validate_all(&[], &available_sources, 2),
+            Err(super::RequestError::NoPairs)
+        );
+    }
+    #[test]
+    fn test_validate_series_opts() {
+        let series = |kind: super::SeriesKind, window: u64| super::SeriesOpts { kind, window };
+
+        assert!(series(super::SeriesKind::Twap, 1800).validate().is_ok());
+        assert!(series(super::SeriesKind::Twap, 0).validate().is_err());
+        assert!(
+            series(super::SeriesKind::Vwap, super::MAX_SERIES_WINDOW + 1)
+                .validate()
+                .is_err()
+        );
+
+        let ohlc = |candle_interval: u64| super::SeriesKind::Ohlc { candle_interval };
+        assert!(series(ohlc(300), 86400).validate().is_ok());
+        assert!(series(ohlc(30), 3600).validate().is_err());
+        // 86400 / 60 candles is more than allowed
+        assert!(series(ohlc(60), 86400).validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_request_timeout_config() {
+        let config = |timeout: u64| super::RequestTimeoutConfig {
+            timeout,
+            refund_expired: true,
+        };
+
+        assert!(super::RequestTimeoutConfig::default().validate().is_ok());
+        assert!(config(super::MIN_REQUEST_TIMEOUT - 1).validate().is_err());
+        assert!(config(super::REQUEST_RETENTION + 1).validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_pricing_config_decimals() {
+        let config = |decimals: u32| super::PricingConfig {
+            decimals: Some(decimals),
+            ..Default::default()
+        };
+
+        // prices are only exact up to the precision they are combined with
+        assert!(config(super::FIXED_POINT_DECIMALS).validate().is_ok());
+        assert!(config(super::FIXED_POINT_DECIMALS + 1).validate().is_err());
+    }
 }
@@ -0,0 +1,316 @@
+use anyhow::{Context, Result};
+use candid::CandidType;
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::fixed_point::parse_decimal;
+use crate::{FixedPointPrice, BUILTIN_SOURCES, FIXED_POINT_DECIMALS};
+
+/// A declarative definition of a pricing source,
+/// used to request and parse the price of a token without a dedicated implementation
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub struct SourceDefinition {
+    /// the unique name of the source e.g "bitstamp"
+    pub name: String,
+    /// the URL to request, where `{ticker}` is replaced by the ticker of the token
+    /// e.g "https://www.bitstamp.net/api/v2/ticker/{ticker}usd"
+    pub url_template: String,
+    /// the tickers which are named differently by this source e.g BTC -> XBT
+    #[serde(default)]
+    pub ticker_mapping: Vec<TickerMapping>,
+    /// the JSON path to the price in the response e.g "data.amount" or "result.*.c.0",
+    /// see `select` for the syntax of the paths
+    pub price_path: String,
+    /// the JSON path to the decimal exponent the price (and confidence) should be scaled by
+    pub exponent_path: Option<String>,
+    /// the JSON path to the time the price was published
+    pub timestamp_path: Option<String>,
+    /// the format of the value at `timestamp_path`
+    #[serde(default)]
+    pub timestamp_format: TimestampFormat,
+    /// the JSON path to the confidence interval around the price
+    pub confidence_path: Option<String>,
+}
+
+/// The name used by a source for a ticker
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq)]
+pub struct TickerMapping {
+    pub ticker: String,
+    pub source_ticker: String,
+}
+
+/// The format of a timestamp provided by a source
+#[derive(Deserialize, Serialize, Clone, Debug, CandidType, PartialEq, Default)]
+pub enum TimestampFormat {
+    #[default]
+    Seconds,
+    Milliseconds,
+    /// an RFC 3339 date time string e.g "2024-10-15T10:32:41Z"
+    Rfc3339,
+}
+
+/// The values extracted from the http response body of a source
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtractedPrice {
+    pub price: f64,
+    /// the exact price with `FIXED_POINT_DECIMALS` decimals, if the price could be read exactly
+    pub fixed_price: Option<FixedPointPrice>,
+    /// the time (in seconds) the price was published by the source
+    pub timestamp: Option<u64>,
+    pub confidence: Option<f64>,
+}
+
+impl SourceDefinition {
+    /// Check that this definition can be used to request and parse prices
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            anyhow::bail!("source name must not be empty")
+        }
+        if BUILTIN_SOURCES.contains(&self.name.to_lowercase().as_str()) {
+            anyhow::bail!("source name {} is reserved", self.name)
+        }
+        if !self.url_template.starts_with("https://") {
+            anyhow::bail!("url_template must be an https URL")
+        }
+        if !self.url_template.contains("{ticker}") {
+            anyhow::bail!("url_template must contain a {{ticker}} placeholder")
+        }
+        if self.price_path.trim().is_empty() {
+            anyhow::bail!("price_path must not be empty")
+        }
+
+        Ok(())
+    }
+
+    /// Get the ticker used by this source for the ticker provided
+    pub fn get_source_ticker(&self, ticker: &str) -> String {
+        self.ticker_mapping
+            .iter()
+            .find(|mapping| mapping.ticker.eq_ignore_ascii_case(ticker))
+            .map(|mapping| mapping.source_ticker.clone())
+            .unwrap_or_else(|| ticker.to_string())
+    }
+
+    /// Get the URL to request the price of a ticker from
+    pub fn get_url(&self, ticker: &str) -> String {
+        self.url_template
+            .replace("{ticker}", &self.get_source_ticker(ticker))
+    }
+
+    /// Extract the price and its metadata from a string representation of the body of the http response
+    pub fn extract(&self, http_response_string: &str) -> Result<ExtractedPrice> {
+        // Parse the JSON response
+        let data: Value = serde_json::from_str(http_response_string)?;
+
+        self.extract_value(&data)
+    }
+
+    /// Extract the price and its metadata from a JSON value,
+    /// e.g the entry of a token in a response providing the prices of several tokens
+    pub fn extract_value(&self, data: &Value) -> Result<ExtractedPrice> {
+        let select_value =
+            |path: &str| select(data, path).with_context(|| format!("{} field is missing", path));
+
+        let price_value = select_value(&self.price_path)?;
+        let mut price =
+            as_f64(price_value).with_context(|| format!("{} field is missing", self.price_path))?;
+        let mut confidence = match &self.confidence_path {
+            Some(path) => Some(
+                select_value(path)
+                    .map(as_f64)?
+                    .with_context(|| format!("{} field is missing", path))?,
+            ),
+            None => None,
+        };
+
+        // scale the price and confidence by the exponent if provided
+        let mut price_exponent = 0;
+        if let Some(path) = &self.exponent_path {
+            let exponent = select_value(path)
+                .map(as_f64)?
+                .with_context(|| format!("{} field is missing", path))?;
+            price_exponent = exponent as i32;
+            let multiplier = 10_f64.powi(price_exponent);
+            price *= multiplier;
+            confidence = confidence.map(|confidence| confidence * multiplier);
+        }
+
+        let timestamp = match &self.timestamp_path {
+            Some(path) => Some(self.timestamp_format.parse(select_value(path)?)?),
+            None => None,
+        };
+
+        if !price.is_finite() {
+            anyhow::bail!("Invalid price")
+        }
+
+        let fixed_price = as_decimal(price_value).and_then(|(mantissa, exponent)| {
+            FixedPointPrice::from_mantissa(
+                mantissa,
+                exponent.checked_add(price_exponent)?,
+                FIXED_POINT_DECIMALS,
+            )
+        });
+
+        Ok(ExtractedPrice {
+            price,
+            fixed_price,
+            timestamp,
+            confidence,
+        })
+    }
+}
+
+impl TimestampFormat {
+    /// Convert a timestamp value in this format to seconds
+    pub fn parse(&self, value: &Value) -> Result<u64> {
+        let timestamp = match self {
+            TimestampFormat::Seconds => as_f64(value).map(|ts| ts as u64),
+            TimestampFormat::Milliseconds => as_f64(value).map(|ts| ts as u64 / 1000),
+            TimestampFormat::Rfc3339 => value
+                .as_str()
+                .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+                .map(|ts| ts.timestamp() as u64),
+        };
+
+        timestamp.context("Invalid timestamp")
+    }
+}
+
+/// Select a value from a JSON document using a dot separated path
+/// where numeric segments index into arrays, `field=value` selects the first item of an array whose field
+/// has that value, `*` selects the first value of an object or array and object keys are case insensitive,
+/// several paths can be separated by `|` to select the value at the first one found
+/// e.g "0.price.price", "result.*.c.0" or "id=e62df6c8.price.price|parsed.id=e62df6c8.price.price"
+pub fn select<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('|')
+        .find_map(|path| select_path(value, path.trim()))
+}
+
+fn select_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .filter(|segment| !segment.is_empty())
+        .try_fold(value, |current, segment| match (segment, current) {
+            ("*", Value::Object(map)) => map.values().next(),
+            ("*", Value::Array(items)) => items.first(),
+            (segment, Value::Array(items)) => match segment.split_once('=') {
+                Some((field, expected)) => items.iter().find(|item| {
+                    item.get(field)
+                        .and_then(Value::as_str)
+                        .map(|value| value.eq_ignore_ascii_case(expected))
+                        .unwrap_or(false)
+                }),
+                None => segment
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| items.get(index)),
+            },
+            (segment, Value::Object(map)) => map.get(segment).or_else(|| {
+                map.iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(segment))
+                    .map(|(_, value)| value)
+            }),
+            _ => None,
+        })
+}
+
+/// Read a JSON value as a number, where numbers might be provided as strings e.g "67012.34"
+pub fn as_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(string) => string.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Read a JSON value as an exact decimal split into an integer mantissa and a power of ten exponent
+pub fn as_decimal(value: &Value) -> Option<(u128, i32)> {
+    match value {
+        Value::Number(number) => parse_decimal(&number.to_string()),
+        Value::String(string) => parse_decimal(string),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definition(price_path: &str) -> SourceDefinition {
+        SourceDefinition {
+            name: "test".to_string(),
+            url_template: "https://example.com/{ticker}".to_string(),
+            ticker_mapping: vec![TickerMapping {
+                ticker: "BTC".to_string(),
+                source_ticker: "XBT".to_string(),
+            }],
+            price_path: price_path.to_string(),
+            exponent_path: None,
+            timestamp_path: None,
+            timestamp_format: TimestampFormat::default(),
+            confidence_path: None,
+        }
+    }
+
+    #[test]
+    fn test_get_url_with_ticker_mapping() {
+        let source = definition("price");
+
+        assert_eq!(source.get_url("BTC"), "https://example.com/XBT");
+        assert_eq!(source.get_url("ETH"), "https://example.com/ETH");
+    }
+
+    #[test]
+    fn test_extract_with_wildcard() {
+        let source = definition("result.*.c.0");
+        let body = r#"{"error":[],"result":{"XXBTZUSD":{"c":["67015.50000","0.0015"]}}}"#;
+
+        let extracted = source.extract(body).unwrap();
+        assert_eq!(extracted.price, 67015.5);
+    }
+
+    #[test]
+    fn test_extract_with_exponent_and_timestamp() {
+        let mut source = definition("0.price.price");
+        source.exponent_path = Some("0.price.expo".to_string());
+        source.confidence_path = Some("0.price.conf".to_string());
+        source.timestamp_path = Some("0.price.publish_time".to_string());
+        let body =
+            r#"[{"price":{"price":"6701234","conf":"500","expo":-2,"publish_time":1728988361}}]"#;
+
+        let extracted = source.extract(body).unwrap();
+        assert!((extracted.price - 67012.34).abs() < 1e-9);
+        assert_eq!(
+            extracted.fixed_price.unwrap().value,
+            67_012_340_000_000_000_000_000
+        );
+        assert!((extracted.confidence.unwrap() - 5.0).abs() < 1e-9);
+        assert_eq!(extracted.timestamp, Some(1728988361));
+    }
+
+    #[test]
+    fn test_select_with_filter_and_alternatives() {
+        let path = "id=bb.price|parsed.id=bb.price";
+        let body: Value =
+            serde_json::from_str(r#"[{"id":"aa","price":"1.5"},{"id":"BB","price":"2.5"}]"#)
+                .unwrap();
+        let parsed_body = serde_json::json!({ "binary": [], "parsed": body.clone() });
+
+        assert_eq!(select(&body, path), Some(&Value::from("2.5")));
+        assert_eq!(select(&parsed_body, path), Some(&Value::from("2.5")));
+        assert_eq!(select(&body, "id=cc.price"), None);
+
+        // object keys are matched regardless of their case
+        let body = serde_json::json!({ "ETH": { "value": 2612.8 } });
+        assert_eq!(select(&body, "eth.value"), Some(&Value::from(2612.8)));
+    }
+
+    #[test]
+    fn test_validate_rejects_builtin_names() {
+        let mut source = definition("price");
+        source.name = "Pyth".to_string();
+
+        assert!(source.validate().is_err());
+    }
+}